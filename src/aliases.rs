@@ -0,0 +1,114 @@
+use std::ffi::OsString;
+
+use crate::{
+    config::{self, Config},
+    error::{AuthError, Result},
+};
+
+/// Aliases may name other aliases, up to this deep
+const MAX_DEPTH: usize = 16;
+
+/// Splits an alias into words like a shell would: on whitespace, with
+/// single and double quotes keeping spaces and backslashes escaping the
+/// next character
+fn split(name: &str, command: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word = None::<String>;
+    let mut quote = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_default();
+            }
+            (Some('\''), c) => word.get_or_insert_default().push(c),
+            (_, '\\') => {
+                let escaped = chars.next().ok_or_else(|| invalid(name, "ends with a backslash"))?;
+                word.get_or_insert_default().push(escaped);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (_, c) => word.get_or_insert_default().push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(invalid(name, "has an unclosed quote"));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+fn invalid(name: &str, details: &str) -> AuthError {
+    let path = Config::path().map(|path| path.display().to_string()).unwrap_or_default();
+    AuthError::ConfigError { path, details: format!("alias '{name}' {details}") }
+}
+
+/// Where the command is, after the global options and their values
+fn command_index(cmd: &clap::Command, args: &[OsString]) -> Option<usize> {
+    let takes_value = |long: &str| {
+        cmd.get_arguments()
+            .any(|arg| arg.get_long() == Some(long) && arg.get_action().takes_values())
+    };
+
+    let mut index = 1;
+    while let Some(arg) = args.get(index)?.to_str() {
+        match arg.strip_prefix("--") {
+            Some(long) if !long.contains('=') && takes_value(long) => index += 2,
+            Some(_) => index += 1,
+            None if arg.starts_with('-') => index += 1,
+            None => return Some(index),
+        }
+    }
+    None
+}
+
+/// The value of `--system-config`, given as `--system-config PATH` or
+/// `--system-config=PATH`, which decides which config the aliases come from
+fn system_config(args: &[OsString]) -> Option<OsString> {
+    args.iter().enumerate().find_map(|(position, arg)| {
+        if arg == "--system-config" {
+            return args.get(position + 1).cloned();
+        }
+        arg.to_str()?.strip_prefix("--system-config=").map(OsString::from)
+    })
+}
+
+/// Expands `[aliases]` from the config file in the command line, before
+/// it's parsed. Commands auth-rs has always win over aliases of the same
+/// name. A config file that can't be read is left for the command to report.
+pub fn expand(cmd: clap::Command, mut args: Vec<OsString>) -> Result<Vec<OsString>> {
+    if let Some(path) = system_config(&args) {
+        config::set_system_path(path.into());
+    }
+    let Ok(config) = Config::load() else {
+        return Ok(args);
+    };
+
+    let mut expanded = vec![];
+    while let Some(index) = command_index(&cmd, &args) {
+        let Some(name) = args[index].to_str().map(str::to_owned) else {
+            break;
+        };
+        let Some(command) = config.aliases.get(&name).filter(|_| cmd.find_subcommand(&name).is_none()) else {
+            break;
+        };
+
+        if expanded.contains(&name) {
+            return Err(invalid(&name, "expands to itself"));
+        }
+        if expanded.len() == MAX_DEPTH {
+            return Err(invalid(&name, "names too many other aliases"));
+        }
+        expanded.push(name.clone());
+
+        let words = split(&name, command)?;
+        if words.is_empty() {
+            return Err(invalid(&name, "is empty"));
+        }
+        args.splice(index..=index, words.into_iter().map(OsString::from));
+    }
+    Ok(args)
+}
@@ -0,0 +1,65 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use log::debug;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use crate::{client::{self, Account}, config, error::Result, game::Game, paths, sessions};
+
+/// How long a fetched image is used before it's fetched again, as
+/// characters change their look now and then
+const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Where the character's images are fetched from: `[desktop] avatar_url`,
+/// then the game's own chathead service. Old School has none.
+fn url(game: Game, display_name: &str) -> Option<String> {
+    let template = match (&config::get().desktop.avatar_url, game) {
+        (Some(template), _) => template.as_str(),
+        (None, Game::Rs3) => "https://secure.runescape.com/m=avatar-rs/{name}/chat.png",
+        (None, Game::Osrs) => return None,
+    };
+    Some(template.replace("{name}", &utf8_percent_encode(display_name, NON_ALPHANUMERIC).to_string()))
+}
+
+/// Kept with the session's offline list, so `gc` removes them with the session
+fn path(session_name: &Option<String>, character_id: &str) -> Result<PathBuf> {
+    Ok(paths::cache_dir()?.join(sessions::key(session_name)).join("avatars").join(format!("{character_id}.png")))
+}
+
+fn is_fresh(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| SystemTime::now().duration_since(modified).is_ok_and(|age| age < MAX_AGE))
+}
+
+async fn download(session_name: &Option<String>, url: &str, path: &Path) -> Result<()> {
+    let response = client::http_for(session_name)?.get(url).send().await?.error_for_status()?;
+    let bytes = response.bytes().await?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// The character's image for desktop entries, fetched when `[desktop]
+/// avatars` is on and cached for a week. `None` when there's no image to be
+/// had, leaving the entry with the game's icon; an image that can't be
+/// fetched again keeps the one fetched last.
+pub async fn fetch(session_name: &Option<String>, account: &Account, game: Game) -> Option<PathBuf> {
+    if !config::get().desktop.avatars || account.display_name.is_empty() {
+        return None;
+    }
+    let url = url(game, &account.display_name)?;
+    let path = path(session_name, &account.account_id).ok()?;
+    if is_fresh(&path) {
+        return Some(path);
+    }
+
+    if let Err(error) = download(session_name, &url, &path).await {
+        debug!("Couldn't fetch the image of {}: {error}", account.display_name);
+    }
+    path.exists().then_some(path)
+}
@@ -0,0 +1,55 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::{sync::Semaphore, task::JoinSet};
+
+use crate::{
+    client::{Account, AccountsDiff, Client},
+    config,
+    error::Result,
+};
+
+/// How one session's refresh in a batch went
+pub struct Refreshed {
+    pub session_name: Option<String>,
+    pub result: Result<(Vec<Account>, AccountsDiff)>,
+    pub elapsed: Duration,
+}
+
+impl Refreshed {
+    pub fn name(&self) -> String {
+        self.session_name.clone().unwrap_or_else(|| "default".to_owned())
+    }
+}
+
+/// Refreshes the sessions' character lists, `parallelism` at a time or
+/// `[api] parallel_refreshes` without one, and returns how each went in the
+/// order given. A failed session doesn't stop the rest; the rate limiter
+/// still paces the requests of them all.
+pub async fn refresh(session_names: Vec<Option<String>>, parallelism: Option<usize>) -> Vec<Refreshed> {
+    let parallelism = parallelism.unwrap_or(config::get().api.parallel_refreshes).max(1);
+    let permits = Arc::new(Semaphore::new(parallelism));
+
+    let mut tasks = JoinSet::new();
+    for (index, session_name) in session_names.into_iter().enumerate() {
+        let permits = permits.clone();
+        tasks.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let started = Instant::now();
+            let result = Client::new(session_name.clone()).accounts_with_diff(false, true).await;
+            (index, Refreshed { session_name, result, elapsed: started.elapsed() })
+        });
+    }
+
+    let mut refreshed = Vec::with_capacity(tasks.len());
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok(result) => refreshed.push(result),
+            Err(error) => std::panic::resume_unwind(error.into_panic()),
+        }
+    }
+    refreshed.sort_by_key(|(index, _)| *index);
+    refreshed.into_iter().map(|(_, refreshed)| refreshed).collect()
+}
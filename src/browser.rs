@@ -1,287 +1,332 @@
-use std::sync::{mpsc::channel, Arc, Mutex};
-use log::error;
+use std::process::Command;
 
+use log::{debug, warn};
 use tao::{
-    dpi::{LogicalPosition, LogicalSize}, event::{Event, WindowEvent}, event_loop::{ControlFlow, EventLoopBuilder}, window::WindowBuilder
+    dpi::{LogicalPosition, LogicalSize}, event::{Event, WindowEvent}, event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy}, platform::run_return::EventLoopExtRunReturn, window::{Window, WindowBuilder}
 };
 use url::Url;
-use uuid::Uuid;
-use wry::{Rect, WebViewBuilder};
+use wry::{Rect, WebContext, WebView, WebViewBuilder};
 
-use crate::{client::Client, error::{AuthError, Result}};
+use crate::{client::Client, clipboard, env, error::{AuthError, Result}, flow::{parse_redirect, AuthFlow, Redirect}, login::{self, LoginWindow}, loopback, network, paths, placement::{self, Placement}, progress::{self, Event as Progress}, proxy, recovery, sessions, theme};
+#[cfg(target_os = "linux")]
+use crate::sandbox;
 
-async fn handle_auth_redirect(
-    client: &Client,
-    code: String,
-    state: String, 
-    options: AuthOptions,
-    consent_state: Arc<Mutex<Option<String>>>,
-) -> Result<CustomEvent> {
-    if state != options.state {
-        return Err(AuthError::InvalidResponse("Auth state parameter mismatch - possible CSRF attack".to_string()));
-    }
-    
-    let token_response = client.token(&code, &options.verifier).await?;
-    let (consent_url, new_consent_state) = create_consent_url(&token_response.tokens.id_token)?;
-    
-    if let Ok(mut state_guard) = consent_state.lock() {
-        *state_guard = Some(new_consent_state);
-    }
-    
-    Ok(CustomEvent::LoadUrl(consent_url))
-}
-
-async fn handle_consent_redirect(
-    client: &Client,
-    id_token: String,
-    state: String,
-    consent_state: Arc<Mutex<Option<String>>>,
-) -> Result<CustomEvent> {
-    let expected_state = consent_state.lock().ok().and_then(|guard| guard.clone());
-    match expected_state {
-        Some(expected) if expected == state => {
-            client.create_session(&id_token).await?;
-            Ok(CustomEvent::Close)
-        }
-        Some(_) => Err(AuthError::InvalidResponse("Consent state parameter mismatch - possible CSRF attack".to_string())),
-        None => Err(AuthError::InvalidResponse("No consent state found - possible CSRF attack".to_string())),
-    }
+#[derive(Debug)]
+enum CustomEvent {
+    Close,
+    LoadUrl(String),
+    Shortcut(Shortcut),
 }
 
-#[derive(Debug, Clone)]
-struct AuthOptions {
-    state: String,
-    challenge: String,
-    verifier: String,
+/// Keys the login window answers to. The webview takes key presses before
+/// the window sees them, so they're caught by a script in the page.
+#[derive(Debug, Clone, Copy)]
+enum Shortcut {
+    /// Esc, giving up on the login
+    Cancel,
+    /// F5 or Ctrl+R
+    Reload,
+    /// Ctrl+Shift+C, for finishing the login by hand when the window can't
+    CopyUrl,
+    /// Ctrl+=
+    ZoomIn,
+    /// Ctrl+-
+    ZoomOut,
 }
 
-impl AuthOptions {
-    fn new() -> Result<Self> {
-        let state = Uuid::new_v4();
-        let code_verify = pkce::code_verifier(43);
-        let code_challenge = pkce::code_challenge(&code_verify);
-        let verifier = String::from_utf8(code_verify)
-            .map_err(|e| AuthError::InvalidResponse(format!("Invalid UTF-8 in code verifier: {e}")))?;
-
-        Ok(Self {
-            state: state.to_string(),
-            challenge: code_challenge,
-            verifier,
-        })
+impl Shortcut {
+    fn from_message(message: &str) -> Option<Self> {
+        match message {
+            "shortcut:cancel" => Some(Self::Cancel),
+            "shortcut:reload" => Some(Self::Reload),
+            "shortcut:copy-url" => Some(Self::CopyUrl),
+            "shortcut:zoom-in" => Some(Self::ZoomIn),
+            "shortcut:zoom-out" => Some(Self::ZoomOut),
+            _ => None,
+        }
     }
 }
 
-#[derive(Debug, Clone)]
-enum Redirects {
-    Auth {
-        code: String,
-        state: String,
-    },
-    Consent {
-        id_token: String,
-        state: String,
-    }
-}
+/// Labels the login form's fields for the platform's autofill and password
+/// managers, and stops spellcheck and autocapitalization from mangling
+/// emails and passwords. The form is rendered after the page loads, so
+/// fields are labeled as they appear.
+const FORM_SCRIPT: &str = r#"
+(() => {
+    const label = (input) => {
+        const type = (input.getAttribute('type') || '').toLowerCase();
+        const name = (input.name + ' ' + input.id).toLowerCase();
+        if (type === 'password') {
+            input.autocomplete = input.autocomplete || 'current-password';
+        } else if (type === 'email' || name.includes('email') || name.includes('username')) {
+            input.autocomplete = input.autocomplete || 'username';
+        } else if (name.includes('otp') || name.includes('code')) {
+            input.autocomplete = input.autocomplete || 'one-time-code';
+        } else {
+            return;
+        }
+        input.spellcheck = false;
+        input.setAttribute('autocapitalize', 'off');
+        input.setAttribute('autocorrect', 'off');
+    };
+    const labelAll = (root) => root.querySelectorAll && root.querySelectorAll('input').forEach(label);
+    new MutationObserver((mutations) => mutations.forEach((mutation) => mutation.addedNodes.forEach((node) => {
+        if (node instanceof HTMLInputElement) label(node); else labelAll(node);
+    }))).observe(document, { childList: true, subtree: true });
+    document.addEventListener('DOMContentLoaded', () => labelAll(document));
+})();
+"#;
 
+const MIN_ZOOM: f64 = 0.5;
+const MAX_ZOOM: f64 = 3.0;
+const ZOOM_STEP: f64 = 0.1;
 
-fn parse_redirect(url: &str) -> Option<Redirects> {
-    let parsed_url = Url::parse(url).ok()?;
-    
-    if let Some(auth_redirect) = try_parse_auth_redirect(&parsed_url) {
-        return Some(auth_redirect);
-    }
-    
-    if let Some(consent_redirect) = try_parse_consent_redirect(url) {
-        return Some(consent_redirect);
+const SHORTCUTS_SCRIPT: &str = r#"
+window.addEventListener('keydown', (event) => {
+    const ctrl = event.ctrlKey || event.metaKey;
+    const key = event.key.toLowerCase();
+    let shortcut = null;
+    if (key === 'escape' && !event.repeat) shortcut = 'cancel';
+    else if (key === 'f5' || (ctrl && !event.shiftKey && key === 'r')) shortcut = 'reload';
+    else if (ctrl && event.shiftKey && key === 'c') shortcut = 'copy-url';
+    else if (ctrl && (key === '=' || key === '+')) shortcut = 'zoom-in';
+    else if (ctrl && key === '-') shortcut = 'zoom-out';
+    if (shortcut) {
+        event.preventDefault();
+        window.ipc.postMessage('shortcut:' + shortcut);
     }
-    
-    None
-}
+}, true);
+"#;
 
-fn try_parse_auth_redirect(url: &Url) -> Option<Redirects> {
-    if url.scheme() != "https" {
-        return None;
-    }
-    
-    if url.host_str() != Some("secure.runescape.com") {
-        return None;
+impl LoginWindow for EventLoopProxy<CustomEvent> {
+    fn open(&self, url: String) -> Result<()> {
+        self.send_event(CustomEvent::LoadUrl(url))
+            .map_err(|_| AuthError::InvalidResponse("The login window closed".to_owned()))
     }
-    
-    if url.path() != "/m=weblogin/launcher-redirect" {
-        return None;
-    }
-    
-    let code = url.query_pairs().find(|q| q.0 == "code")?.1;
-    let state = url.query_pairs().find(|q| q.0 == "state")?.1;
-    
-    Some(Redirects::Auth { 
-        code: code.into_owned(), 
-        state: state.into_owned() 
-    })
-}
 
-fn try_parse_consent_redirect(url: &str) -> Option<Redirects> {
-    let url_with_query = url.replace("#", "?");
-    let parsed_url = Url::parse(&url_with_query).ok()?;
-    
-    if parsed_url.host_str() != Some("localhost") {
-        return None;
+    fn close(&self) {
+        let _ = self.send_event(CustomEvent::Close);
     }
-    
-    let state = parsed_url.query_pairs().find(|q| q.0 == "state")?.1;
-    let id_token = parsed_url.query_pairs().find(|q| q.0 == "id_token")?.1;
-    
-    Some(Redirects::Consent {
-        id_token: id_token.into_owned(),
-        state: state.into_owned(),
-    })
 }
 
-#[derive(Debug)]
-enum CustomEvent {
-    Close,
-    LoadUrl(String),
-}
+/// Runs the login flow in a webview window. With `print_only`, a JSON summary
+/// of the stored session is printed to stdout once it completes. With
+/// `consent_listener` the consent redirect goes to a local HTTP listener
+/// rather than being caught in the window. `email` is prefilled on the login
+/// page and remembered for the session. The window opens where `placement`
+/// says, above the others with `always_on_top`. Esc cancels the login, F5
+/// or Ctrl+R reloads the page and Ctrl+Shift+C copies its URL. The page starts
+/// at `zoom` times its size, the window growing to fit, and Ctrl+= and Ctrl+-
+/// change it from there. Returns once the window has closed, with the error
+/// that ended the login if it didn't finish.
+pub fn authorize(
+    session_name: Option<String>,
+    print_only: bool,
+    consent_listener: bool,
+    email: Option<String>,
+    placement: Placement,
+    always_on_top: bool,
+    zoom: f64,
+) -> Result<()> {
+    let marker = recovery::begin(&session_name)?;
+    if let Some(email) = &email {
+        sessions::remember_email(&session_name, email)?;
+    }
+    let email = email.or_else(|| sessions::email(&session_name));
 
-#[derive(Debug)]
-enum Message {
-    AuthRedirect { code: String, state: String, options: AuthOptions },
-    ConsentRedirect { id_token: String, state: String },
-}
+    let mut event_loop = EventLoopBuilder::with_user_event().build();
+    let proxy = event_loop.create_proxy();
+    let mut zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    let size = LogicalSize::new(400.0 * zoom, 700.0 * zoom);
+    let builder = WindowBuilder::new()
+        .with_title(match &session_name {
+            Some(name) => format!("Authorize ({name})"),
+            None => "Authorize".to_owned(),
+        })
+        .with_inner_size(size)
+        .with_always_on_top(always_on_top)
+        .with_minimizable(false)
+        .with_maximizable(false);
+    // A window left to the window manager can open on a display that's
+    // switched off, which looks like authorize hanging
+    let builder = match placement::position(&event_loop, placement, size) {
+        Some(position) => builder.with_position(position),
+        None => builder,
+    };
+    let window = builder
+        .build(&event_loop)
+        .map_err(|e| AuthError::InvalidResponse(format!("Failed to create window: {e}")))?;
 
-fn create_auth_url() -> Result<(String, AuthOptions)> {
-    let auth_options = AuthOptions::new()?;
-    let mut url = Url::parse(crate::env::ORIGIN)?
-        .join("/oauth2/auth")?;
-    let mut query = url.query_pairs_mut();
-    query.append_pair("flow", "launcher");
-    query.append_pair("response_type", "code");
-    query.append_pair("client_id", crate::env::CLIENT_ID);
-    query.append_pair("redirect_uri", crate::env::REDIRECT);
-    query.append_pair("code_challenge", &auth_options.challenge);
-    query.append_pair("code_challenge_method", "S256");
-    query.append_pair("prompt", "login");
-    query.append_pair(
-        "scope",
-        "openid offline gamesso.token.create user.profile.read",
-    );
-    query.append_pair("state", &auth_options.state);
-    drop(query);
+    let mut oauth = crate::env::oauth(&session_name);
+    let use_listener = consent_listener || oauth.consent_listener;
+    let (navigation, redirects) = login::navigation(use_listener);
+    if use_listener {
+        let navigation = navigation.clone();
+        oauth.consent_redirect = loopback::start(move |url| {
+            if let Some(redirect @ Redirect::Consent { .. }) = parse_redirect(&url) {
+                navigation.redirect(redirect);
+            }
+        })?;
+    }
 
-    Ok((url.as_str().to_owned(), auth_options))
-}
+    let flow_session = session_name.clone();
+    let mut flow = AuthFlow::new(session_name.clone(), oauth, email);
+    let auth_url = flow.start()?;
+    let driver = login::drive(Client::new(session_name), flow, redirects, proxy, print_only);
 
-fn create_consent_url(id_token: &str) -> Result<(String, String)> {
-    let state = Uuid::new_v4().to_string();
-    let nonce = Uuid::new_v4().to_string();
-    let mut url = Url::parse(crate::env::ORIGIN)?
-        .join("/oauth2/auth")?;
-    let mut query = url.query_pairs_mut();
-    query.append_pair("id_token_hint", id_token);
-    query.append_pair("nonce", &nonce);
-    query.append_pair("prompt", "consent");
-    query.append_pair("response_type", "id_token code");
-    query.append_pair("client_id", "1fddee4e-b100-4f4e-b2b0-097f9088f9d2");
-    query.append_pair("redirect_uri", "http://localhost");
-    query.append_pair("scope", "openid offline");
-    query.append_pair("state", &state);
-    drop(query);
+    // The webview does its own networking, so it's handed the proxy the
+    // API calls already use through reqwest, the session's own if it has one
+    let webview_proxy = match Url::parse(&auth_url) {
+        Ok(url) => proxy::for_webview(&flow_session, &url)?,
+        Err(_) => None,
+    };
+    if let Ok(Some(bind)) = network::bind() {
+        eprintln!(
+            "{} The login window can't be bound to {bind}, it connects over the system's default route",
+            theme::warning("Warning:").bold()
+        );
+    }
+    if network::overrides_dns() {
+        eprintln!(
+            "{} The login window looks names up with the system's resolver, not [network] hosts or doh",
+            theme::warning("Warning:").bold()
+        );
+    }
+    if webview_proxy.is_some() && use_listener {
+        eprintln!(
+            "{} The consent redirect to the local listener goes through the proxy too. If the login hangs after consenting, authorize without the listener",
+            theme::warning("Warning:").bold()
+        );
+    }
+    let title_navigation = navigation.clone();
+    let shortcuts = event_loop.create_proxy();
+    let mut web_context = web_context()?;
+    let builder = WebViewBuilder::new_with_web_context(&mut web_context)
+        .with_document_title_changed_handler(move |title| title_navigation.title_changed(&title))
+        .with_navigation_handler(move |navigate_to| navigation.allow(&navigate_to))
+        .with_clipboard(true)
+        .with_initialization_script(SHORTCUTS_SCRIPT)
+        .with_initialization_script(FORM_SCRIPT)
+        .with_focused(true)
+        .with_ipc_handler(move |request| {
+            if let Some(shortcut) = Shortcut::from_message(request.body()) {
+                let _ = shortcuts.send_event(CustomEvent::Shortcut(shortcut));
+            }
+        })
+        .with_bounds(Rect {
+            position: LogicalPosition::new(0, 0).into(),
+            size: size.into()
+        })
+        .with_url(auth_url);
+    let builder = match webview_proxy.as_ref().and_then(proxy::webview_config) {
+        Some(config) => builder.with_proxy_config(config),
+        None => builder,
+    };
 
-    Ok((url.as_str().to_owned(), state))
-}
+    let webview = build_webview(builder, &window)?;
+    if zoom != 1.0 {
+        if let Err(e) = webview.zoom(zoom) {
+            warn!("Failed to zoom the login page: {e}");
+        }
+    }
+    progress::emit(Progress::AuthUrlOpened);
 
+    let mut load_error = None;
+    let mut cancelled = false;
+    let exit_code = event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
 
-fn spawn_message_handler(
-    client: Client,
-    rx: std::sync::mpsc::Receiver<Message>,
-    consent_state: Arc<Mutex<Option<String>>>,
-    proxy: tao::event_loop::EventLoopProxy<CustomEvent>,
-) {
-    tokio::spawn(async move {
-        while let Ok(message) = rx.recv() {
-            let result = match message {
-                Message::AuthRedirect { code, state, options } => {
-                    handle_auth_redirect(&client, code, state, options, consent_state.clone()).await
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            // Keys typed with the window focused but not the page, input
+            // methods' included, would otherwise go nowhere
+            Event::WindowEvent { event: WindowEvent::Focused(true), .. } => {
+                if let Err(e) = webview.focus() {
+                    debug!("Failed to focus the login page: {e}");
                 }
-                Message::ConsentRedirect { id_token, state } => {
-                    handle_consent_redirect(&client, id_token, state, consent_state.clone()).await
+            }
+            Event::WindowEvent { event: WindowEvent::Resized(size), .. } => {
+                let bounds = Rect {
+                    position: LogicalPosition::new(0, 0).into(),
+                    size: LogicalSize::new(size.width, size.height).into()
+                };
+                if let Err(e) = webview.set_bounds(bounds) {
+                    warn!("Failed to resize the page to the window: {e}");
                 }
-            };
-
-            match result {
-                Ok(event) => {
-                    if let Err(e) = proxy.send_event(event) {
-                        error!("Failed to send event: {e:?}");
-                        let _ = proxy.send_event(CustomEvent::Close);
-                        break;
-                    }
+            },
+            Event::UserEvent(CustomEvent::Close) => *control_flow = ControlFlow::Exit,
+            Event::UserEvent(CustomEvent::Shortcut(Shortcut::Cancel)) => {
+                cancelled = true;
+                *control_flow = ControlFlow::Exit;
+            }
+            Event::UserEvent(CustomEvent::Shortcut(Shortcut::Reload)) => {
+                if let Err(e) = webview.reload() {
+                    warn!("Failed to reload the login page: {e}");
+                }
+            }
+            Event::UserEvent(CustomEvent::Shortcut(Shortcut::CopyUrl)) => {
+                match webview.url().map_err(|e| AuthError::WebviewError(e.to_string())).and_then(|url| clipboard::copy(&url)) {
+                    Ok(()) => eprintln!("Copied the login page's URL"),
+                    Err(e) => warn!("Failed to copy the login page's URL: {e}"),
+                }
+            }
+            Event::UserEvent(CustomEvent::Shortcut(shortcut @ (Shortcut::ZoomIn | Shortcut::ZoomOut))) => {
+                let step = if matches!(shortcut, Shortcut::ZoomIn) { ZOOM_STEP } else { -ZOOM_STEP };
+                zoom = (zoom + step).clamp(MIN_ZOOM, MAX_ZOOM);
+                if let Err(e) = webview.zoom(zoom) {
+                    warn!("Failed to zoom the login page: {e}");
                 }
-                Err(e) => {
-                    error!("Error during authentication: {e}");
-                    let _ = proxy.send_event(CustomEvent::Close);
-                    break;
+            }
+            Event::UserEvent(CustomEvent::LoadUrl(url)) => {
+                if let Err(e) = webview.load_url(&url) {
+                    load_error = Some(AuthError::WebviewError(format!("Failed to load {url}: {e}")));
+                    *control_flow = ControlFlow::Exit;
                 }
             }
+            _ => (),
         }
-
-        let _ = proxy.send_event(CustomEvent::Close);
     });
-}
-
-pub fn authorize(session_name: Option<String>) -> Result<()> {
-    let (tx, rx) = channel::<Message>();
-    let consent_state: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
 
-    let event_loop = EventLoopBuilder::with_user_event().build();
-    let proxy = event_loop.create_proxy();
-    let window = WindowBuilder::new()
-        .with_title("Authorize")
-        .with_inner_size(LogicalSize::new(400.0, 700.0))
-        .with_minimizable(false)
-        .with_maximizable(false)
-        .build(&event_loop)
-        .map_err(|e| AuthError::InvalidResponse(format!("Failed to create window: {e}")))?;
-
-    let client = Client::new(session_name);
-    spawn_message_handler(client, rx, consent_state, proxy.clone());
-
-    let (auth_url, options) = create_auth_url()?;
-    let builder = WebViewBuilder::new()
-        .with_navigation_handler(move |navigate_to| {            
-            if let Some(redirect) = parse_redirect(&navigate_to) {
-                match redirect {
-                    Redirects::Auth { code, state } => {
-                        if let Err(e) = tx.send(Message::AuthRedirect { 
-                            code, 
-                            state, 
-                            options: options.clone() 
-                        }) {
-                            error!("Failed to send auth redirect message: {e}");
-                        }
-                    }
-                    Redirects::Consent { id_token, state } => {
-                        if let Err(e) = tx.send(Message::ConsentRedirect { 
-                            id_token, 
-                            state 
-                        }) {
-                            error!("Failed to send consent redirect message: {e}");
-                        }
-                    }
+    let result = driver.shutdown();
+    drop(marker);
+    if placement == Placement::Remember {
+        // Wayland doesn't tell windows where they are
+        match window.outer_position() {
+            Ok(position) => {
+                if let Err(e) = placement::remember(position) {
+                    warn!("Failed to remember where the login window was: {e}");
                 }
-                false
-            } else {
-                true
             }
-        })
-        .with_clipboard(true)
-        .with_bounds(Rect {
-            position: LogicalPosition::new(0, 0).into(),
-            size: LogicalSize::new(400, 700).into()
-        })
-        .with_url(auth_url);
+            Err(e) => debug!("Can't tell where the login window was: {e}"),
+        }
+    }
+    if let Some(error) = load_error {
+        return Err(error);
+    }
+    // Cancelling stops the login like closing the window, but was meant
+    if cancelled && result.is_err() {
+        return Err(AuthError::Cancelled);
+    }
+    // The event loop gives up on a display that went away, and so does the
+    // window with it
+    if exit_code != 0 && result.is_err() {
+        return Err(AuthError::WebviewError("Lost the connection to the display".to_owned()));
+    }
+    result
+}
+
+/// Where the login and account settings windows keep their cookies and
+/// storage, shared so the account settings open signed in
+fn web_context() -> Result<WebContext> {
+    Ok(WebContext::new(Some(paths::data_dir()?.join("webview"))))
+}
 
+fn build_webview(builder: WebViewBuilder, window: &Window) -> Result<WebView> {
     #[cfg(not(target_os = "linux"))]
-    let webview = builder.build(&window)
+    let webview = builder.build(window)
         .map_err(|e| AuthError::WebviewError(format!("{}", e)))?;
     #[cfg(target_os = "linux")]
     let webview = {
@@ -295,8 +340,42 @@ pub fn authorize(session_name: Option<String>) -> Result<()> {
         vbox.pack_start(&fixed, true, true, 0);
         builder.build_gtk(&fixed).map_err(|e| AuthError::WebviewError(format!("{e}")))?
     };
+    Ok(webview)
+}
+
+/// Shows the Jagex account settings in a window until it's closed. The
+/// window shares the login window's [`web_context`], so it's signed in to
+/// whichever account last logged in, as long as Jagex still remembers that
+/// login.
+pub fn account_settings(session_name: &Option<String>) -> Result<()> {
+    let mut event_loop = EventLoop::new();
+    let size = LogicalSize::new(1000.0, 800.0);
+    let window = WindowBuilder::new()
+        .with_title(match session_name {
+            Some(name) => format!("Account settings ({name})"),
+            None => "Account settings".to_owned(),
+        })
+        .with_inner_size(size)
+        .build(&event_loop)
+        .map_err(|e| AuthError::InvalidResponse(format!("Failed to create window: {e}")))?;
 
-    event_loop.run(move |event, _, control_flow| {
+    let webview_proxy = proxy::for_webview(session_name, &Url::parse(env::ACCOUNT_SETTINGS)?)?;
+    let mut web_context = web_context()?;
+    let builder = WebViewBuilder::new_with_web_context(&mut web_context)
+        .with_clipboard(true)
+        .with_initialization_script(FORM_SCRIPT)
+        .with_bounds(Rect {
+            position: LogicalPosition::new(0, 0).into(),
+            size: size.into()
+        })
+        .with_url(env::ACCOUNT_SETTINGS);
+    let builder = match webview_proxy.as_ref().and_then(proxy::webview_config) {
+        Some(config) => builder.with_proxy_config(config),
+        None => builder,
+    };
+    let webview = build_webview(builder, &window)?;
+
+    event_loop.run_return(|event, _, control_flow| {
         *control_flow = ControlFlow::Wait;
 
         match event {
@@ -305,19 +384,44 @@ pub fn authorize(session_name: Option<String>) -> Result<()> {
                 ..
             } => *control_flow = ControlFlow::Exit,
             Event::WindowEvent { event: WindowEvent::Resized(size), .. } => {
-                webview.set_bounds(Rect {
+                let bounds = Rect {
                     position: LogicalPosition::new(0, 0).into(),
                     size: LogicalSize::new(size.width, size.height).into()
-                }).unwrap();
-            },
-            Event::UserEvent(CustomEvent::Close) => *control_flow = ControlFlow::Exit,
-            Event::UserEvent(CustomEvent::LoadUrl(url)) => {
-                if let Err(e) = webview.load_url(&url) {
-                    error!("Failed to load URL: {e}");
-                    *control_flow = ControlFlow::Exit;
+                };
+                if let Err(e) = webview.set_bounds(bounds) {
+                    warn!("Failed to resize the page to the window: {e}");
                 }
-            }
+            },
             _ => (),
         }
     });
+    Ok(())
+}
+
+/// Opens the URL in the system's default browser, through the OpenURI
+/// portal when sandboxed
+pub fn open_external(url: &str) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    if sandbox::detect().is_some() {
+        return sandbox::open_uri(url);
+    }
+
+    let (program, args): (&str, &[&str]) = if cfg!(target_os = "windows") {
+        ("rundll32", &["url.dll,FileProtocolHandler"])
+    } else if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else {
+        ("xdg-open", &[])
+    };
+    let status = Command::new(program).args(args).arg(url).status().map_err(|e| AuthError::ExecError {
+        program: program.to_owned(),
+        details: e.to_string(),
+    })?;
+    if !status.success() {
+        return Err(AuthError::ExecError {
+            program: program.to_owned(),
+            details: format!("Exited with {status} opening {url}"),
+        });
+    }
+    Ok(())
 }
@@ -1,10 +1,22 @@
 
 
-use std::{path::PathBuf, time::SystemTime};
-
+use std::{
+    collections::BTreeMap,
+    num::NonZeroU32,
+    path::PathBuf,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use console::style;
+use dialoguer::Select;
 use keyring::Entry;
+use log::debug;
+use ring::{aead, pbkdf2, rand::{SecureRandom, SystemRandom}};
 use serde::{Deserialize, Serialize};
-use crate::error::{AuthError, Result};
+use crate::{config, error::{AuthError, Result}, network, output, paths, progress, prompt, proxy::{self, Route}, sessions, status, theme};
+#[cfg(target_os = "linux")]
+use crate::sandbox;
 
 #[derive(Serialize, Deserialize)]
 struct SessionRequest {
@@ -22,7 +34,68 @@ pub struct Tokens {
     pub token_type: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// A short, non-secret hash of the Jagex account the characters belong to,
+/// kept in desktop entries to notice when the session has since been logged
+/// in to another account
+pub fn fingerprint(accounts: &[Account]) -> Option<String> {
+    let mut hashes = accounts.iter().map(|a| a.user_hash.as_str()).filter(|h| !h.is_empty()).collect::<Vec<_>>();
+    if hashes.is_empty() {
+        return None;
+    }
+    hashes.sort_unstable();
+    hashes.dedup();
+
+    let digest = ring::digest::digest(&ring::digest::SHA256, hashes.join("\n").as_bytes());
+    Some(digest.as_ref()[..6].iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// An OAuth error response, as JSON or form fields (RFC 6749 section 5.2)
+#[derive(Deserialize)]
+struct TokenErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// The error the token endpoint answered with, explained
+fn token_error(status: reqwest::StatusCode, body: &str) -> AuthError {
+    let response = serde_json::from_str::<TokenErrorResponse>(body).ok().or_else(|| {
+        let fields = url::form_urlencoded::parse(body.as_bytes()).collect::<BTreeMap<_, _>>();
+        Some(TokenErrorResponse {
+            error: fields.get("error")?.to_string(),
+            error_description: fields.get("error_description").map(|d| d.to_string()),
+        })
+    });
+    let Some(response) = response else {
+        return AuthError::InvalidResponse(format!("The token endpoint answered {status}"));
+    };
+
+    let (details, hint) = match response.error.as_str() {
+        "invalid_grant" => (
+            "The authorization code expired or was already used",
+            "Log in again with 'auth-rs authorize'. Codes only last a few minutes, so finish the login in one go",
+        ),
+        "invalid_client" | "unauthorized_client" => (
+            "The client ID was refused",
+            "Check client_id under [oauth] and AUTH_RS_CLIENT_ID, or remove them to use the launcher's",
+        ),
+        "invalid_scope" => (
+            "The requested scopes were refused",
+            "Check scope under [oauth] and AUTH_RS_SCOPE, or remove them to use the launcher's",
+        ),
+        "unsupported_grant_type" | "invalid_request" => (
+            "The login request was malformed",
+            "Jagex may have changed its login. Please report this bug",
+        ),
+        _ => ("The login was refused", "Please try authenticating again"),
+    };
+    AuthError::TokenRejected {
+        details,
+        description: response.error_description.filter(|d| !d.is_empty()).unwrap_or(response.error),
+        hint,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Account {
     #[serde(rename = "accountId")]
     pub account_id: String,
@@ -32,10 +105,76 @@ pub struct Account {
     pub user_hash: String,
 }
 
+/// A character whose display name changed since the list was cached
+#[derive(Debug, Clone)]
+pub struct Rename {
+    pub account_id: String,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+/// How the characters changed since the offline list was written. Empty
+/// when there was no list to compare with.
+#[derive(Debug, Default)]
+pub struct AccountsDiff {
+    pub added: Vec<Account>,
+    pub removed: Vec<Account>,
+    pub renamed: Vec<Rename>,
+}
+
+impl AccountsDiff {
+    fn between(cached: &[Account], accounts: &[Account]) -> Self {
+        if cached.is_empty() {
+            return Self::default();
+        }
+
+        let find = |list: &[Account], id: &str| list.iter().position(|a| a.account_id == id);
+        let added = accounts.iter().filter(|a| find(cached, &a.account_id).is_none()).cloned().collect();
+        let removed = cached.iter().filter(|a| find(accounts, &a.account_id).is_none()).cloned().collect();
+        let renamed = accounts
+            .iter()
+            .filter_map(|account| {
+                let previous = &cached[find(cached, &account.account_id)?];
+                (previous.display_name != account.display_name).then(|| Rename {
+                    account_id: account.account_id.clone(),
+                    old_name: previous.display_name.clone(),
+                    new_name: account.display_name.clone(),
+                })
+            })
+            .collect();
+        Self { added, removed, renamed }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Session {
     #[serde(rename = "sessionId")]
     pub session_id: String,
+    /// Unix timestamp of the login the session came from. Jagex doesn't say
+    /// how long sessions last, so the tokens' lifetime is the estimate.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued_at: Option<u64>,
+    /// Seconds the login's tokens were valid for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<u64>,
+}
+
+impl Session {
+    /// When the session is estimated to expire, unknown for sessions stored
+    /// by older versions
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(self.issued_at? + self.expires_in?))
+    }
+
+    /// Time left before the estimated expiry, zero once it has passed
+    pub fn remaining(&self) -> Option<Duration> {
+        let expires_at = self.expires_at()?;
+        Some(expires_at.duration_since(SystemTime::now()).unwrap_or_default())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -44,40 +183,358 @@ pub struct AuthState {
     pub tokens: Tokens
 }
 
+/// Sessions saved to disk when the credential store couldn't take them,
+/// encrypted with a passphrase the user picks. They're moved back into the
+/// credential store as soon as it takes them.
+struct FileStore;
+
+impl FileStore {
+    const PBKDF2_ITERATIONS: u32 = 600_000;
+    const SALT_LEN: usize = 16;
+
+    fn path(session_name: &Option<String>) -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join("sessions").join(format!("{}.locked", sessions::key(session_name))))
+    }
+
+    /// Where older versions saved the session unencrypted
+    fn plaintext_path(session_name: &Option<String>) -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join("sessions").join(format!("{}.json", sessions::key(session_name))))
+    }
+
+    fn key(passphrase: &str, salt: &[u8]) -> aead::LessSafeKey {
+        let mut key = [0; 32];
+        let iterations = NonZeroU32::new(Self::PBKDF2_ITERATIONS).unwrap_or(NonZeroU32::MIN);
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut key);
+        // 32 bytes is always a valid AES-256 key
+        aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &key).unwrap())
+    }
+
+    /// Saves the session as the salt, then the nonce, then the ciphertext
+    fn store(session_name: &Option<String>, session: &Session, passphrase: &str) -> Result<PathBuf> {
+        let path = Self::path(session_name)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let error = || AuthError::CredentialStoreError("Could not encrypt the session".to_owned());
+        let random = SystemRandom::new();
+        let mut salt = [0; Self::SALT_LEN];
+        let mut nonce = [0; aead::NONCE_LEN];
+        random.fill(&mut salt).map_err(|_| error())?;
+        random.fill(&mut nonce).map_err(|_| error())?;
+
+        let mut sealed = serde_json::to_vec(session)?;
+        Self::key(passphrase, &salt)
+            .seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut sealed)
+            .map_err(|_| error())?;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(&path)?, &[salt.as_slice(), &nonce, &sealed].concat())?;
+
+        Ok(path)
+    }
+
+    /// Reads the saved session, asking for its passphrase
+    fn load(session_name: &Option<String>) -> Result<Option<Session>> {
+        let plaintext_path = Self::plaintext_path(session_name)?;
+        if plaintext_path.exists() {
+            let file = std::fs::File::open(plaintext_path)?;
+            return Ok(Some(serde_json::from_reader(file)?));
+        }
+
+        let path = Self::path(session_name)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read(&path)?;
+        if contents.len() < Self::SALT_LEN + aead::NONCE_LEN {
+            return Err(AuthError::CredentialStoreError(format!("{} is damaged", path.display())));
+        }
+        let (salt, rest) = contents.split_at(Self::SALT_LEN);
+        let (nonce, ciphertext) = rest.split_at(aead::NONCE_LEN);
+        let nonce = aead::Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| AuthError::CredentialStoreError(format!("{} is damaged", path.display())))?;
+
+        let passphrase = prompt::passphrase(
+            &format!("Passphrase of the session saved to {}", path.display()),
+            false,
+        )?;
+        let mut in_out = ciphertext.to_vec();
+        let session_json = Self::key(&passphrase, salt)
+            .open_in_place(nonce, aead::Aad::empty(), &mut in_out)
+            .map_err(|_| AuthError::SessionFileLocked {
+                details: "The passphrase is wrong. Try again, or log out of the session and authorize again".to_owned(),
+            })?;
+        Ok(Some(serde_json::from_slice(session_json)?))
+    }
+
+    fn clear(session_name: &Option<String>) -> Result<()> {
+        for path in [Self::path(session_name)?, Self::plaintext_path(session_name)?] {
+            if path.exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sessions sealed with the Secret portal's key when running as a Flatpak,
+/// kept in the app's own data directory
+#[cfg(target_os = "linux")]
+struct PortalStore;
+
+#[cfg(target_os = "linux")]
+impl PortalStore {
+    fn path(session_name: &Option<String>) -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join("sessions").join(format!("{}.sealed", sessions::key(session_name))))
+    }
+
+    fn store(session_name: &Option<String>, session: &Session) -> Result<()> {
+        let path = Self::path(session_name)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let secret = sandbox::retrieve_secret()?;
+        let sealed = sandbox::seal(&secret, &sessions::key(session_name), &serde_json::to_vec(session)?)?;
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        std::io::Write::write_all(&mut options.open(&path)?, &sealed)?;
+        Ok(())
+    }
+
+    fn load(session_name: &Option<String>) -> Result<Option<Session>> {
+        let path = Self::path(session_name)?;
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let secret = sandbox::retrieve_secret()?;
+        let session_json = sandbox::open(&secret, &sessions::key(session_name), &std::fs::read(path)?)?;
+        Ok(Some(serde_json::from_slice(&session_json)?))
+    }
+
+    fn clear(session_name: &Option<String>) -> Result<()> {
+        let path = Self::path(session_name)?;
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
 struct SessionStore;
 
 impl SessionStore {
-    const SERVICE: &'static str = "auth-rs";
+    const DEFAULT_SERVICE: &'static str = "auth-rs";
+
+    /// Service name from `AUTH_RS_KEYRING_SERVICE`, then the config file
+    fn service() -> String {
+        std::env::var("AUTH_RS_KEYRING_SERVICE")
+            .ok()
+            .or_else(|| config::get().keyring.service.clone())
+            .unwrap_or_else(|| Self::DEFAULT_SERVICE.to_owned())
+    }
     
-    fn get_entry(session_name: &Option<String>) -> Result<Entry> {
-        let key = match session_name {
-            Some(session_name) => format!("named-session-{session_name}"),
-            None => "session".to_owned(),
-        };
-        Entry::new(Self::SERVICE, &key)
+    fn entry(key: &str) -> Result<Entry> {
+        #[cfg(target_os = "linux")]
+        if let Some(collection) = &config::get().keyring.collection {
+            return Entry::new_with_target(collection, &Self::service(), key)
+                .map_err(AuthError::from);
+        }
+
+        Entry::new(&Self::service(), key)
             .map_err(AuthError::from)
     }
     
+    fn get_entry(session_name: &Option<String>) -> Result<Entry> {
+        Self::entry(&sessions::key(session_name))
+    }
+    
     fn store(session_name: &Option<String>, session: &Session) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        if sandbox::uses_secret_portal() {
+            return PortalStore::store(session_name, session);
+        }
+
         let entry = Self::get_entry(session_name)?;
         let session_json = serde_json::to_string(session)?;
+
+        #[cfg(target_os = "macos")]
+        if let Some(options) = Self::macos_password_options(&sessions::key(session_name)) {
+            return Self::store_with_options(&entry, &session_json, options);
+        }
+
         entry.set_password(&session_json)
             .map_err(AuthError::from)
     }
+
+    /// Keychain options for the item when access control is configured, which
+    /// keyring has no way to set
+    #[cfg(target_os = "macos")]
+    fn macos_password_options(key: &str) -> Option<security_framework::passwords_options::PasswordOptions> {
+        use security_framework::passwords_options::{AccessControlOptions, PasswordOptions};
+
+        let macos = &config::get().keyring.macos;
+        if !macos.require_user_presence && macos.access_group.is_none() {
+            return None;
+        }
+
+        let mut options = PasswordOptions::new_generic_password(&Self::service(), key);
+        if macos.require_user_presence {
+            options.set_access_control_options(AccessControlOptions::USER_PRESENCE);
+        }
+        if let Some(group) = &macos.access_group {
+            options.set_access_group(group);
+        }
+        Some(options)
+    }
+
+    /// Replaces the item outright, as access control can't be changed by updating it
+    #[cfg(target_os = "macos")]
+    fn store_with_options(
+        entry: &Entry,
+        session_json: &str,
+        options: security_framework::passwords_options::PasswordOptions,
+    ) -> Result<()> {
+        match entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => (),
+            Err(e) => return Err(AuthError::from(e)),
+        }
+
+        security_framework::passwords::set_generic_password_options(session_json.as_bytes(), options)
+            .map_err(|e| AuthError::CredentialStoreError(e.to_string()))
+    }
     
+    /// Stores the session, giving the user the chance to unlock the keyring
+    /// rather than losing a freshly created session
+    fn store_with_retry(session_name: &Option<String>, session: &Session) -> Result<()> {
+        let mut attempt = 0;
+        loop {
+            match Self::store(session_name, session) {
+                Err(AuthError::KeyringLocked(details)) => {
+                    if !prompt::retry_after_unlock(attempt)? {
+                        return Err(AuthError::KeyringLocked(details));
+                    }
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Offers ways to keep a session the credential store refused, as
+    /// creating another one means logging in again including 2FA
+    fn store_or_fallback(session_name: &Option<String>, session: &Session) -> Result<()> {
+        loop {
+            let error = match Self::store_with_retry(session_name, session) {
+                Err(error) if error.is_credential_store_error() && prompt::is_interactive() => error,
+                // A session left on disk by an earlier failure is replaced
+                Ok(()) => return FileStore::clear(session_name),
+                result => return result,
+            };
+
+            eprintln!("{} {error}", theme::error("Failed to store the session:"));
+            let choice = Select::with_theme(&theme::prompts())
+                .with_prompt("What should happen to the new session?")
+                .items([
+                    "Retry the credential store",
+                    "Save it to a file encrypted with a passphrase",
+                    "Print the session ID",
+                    "Discard it",
+                ])
+                .default(0)
+                .interact()?;
+
+            match choice {
+                0 => continue,
+                1 => {
+                    let passphrase = prompt::passphrase("Passphrase to encrypt the session with", true)?;
+                    let path = FileStore::store(session_name, session, &passphrase)?;
+                    eprintln!(
+                        "Session saved to {}. It's asked for until the credential store takes the session back",
+                        style(path.display()).bold()
+                    );
+                    return Ok(());
+                }
+                2 => {
+                    eprintln!("Session ID (set it as JX_SESSION_ID to launch a client by hand):");
+                    println!("{}", session.session_id);
+                    return Err(error);
+                }
+                _ => return Err(error),
+            }
+        }
+    }
+
     fn load(session_name: &Option<String>) -> Result<Option<Session>> {
+        #[cfg(target_os = "linux")]
+        if sandbox::uses_secret_portal() {
+            if let Some(session) = PortalStore::load(session_name)? {
+                return Ok(Some(session));
+            }
+            return Self::load_from_file(session_name);
+        }
+
         let entry = Self::get_entry(session_name)?;
-        match entry.get_password() {
+        let stored = match entry.get_password() {
             Ok(session_json) => {
                 let session: Session = serde_json::from_str(&session_json)?;
-                Ok(Some(session))
+                return Ok(Some(session));
             }
             Err(keyring::Error::NoEntry) => Ok(None),
             Err(e) => Err(AuthError::from(e))
+        };
+
+        match Self::load_from_file(session_name)? {
+            Some(session) => Ok(Some(session)),
+            None => stored,
+        }
+    }
+
+    /// Reads a session saved to disk while the credential store was failing,
+    /// moving it back into the store when it works again
+    fn load_from_file(session_name: &Option<String>) -> Result<Option<Session>> {
+        let Some(session) = FileStore::load(session_name)? else {
+            return Ok(None);
+        };
+        match Self::store(session_name, &session) {
+            Ok(()) => {
+                FileStore::clear(session_name)?;
+                eprintln!("Moved the session saved to a file back into the credential store");
+            }
+            Err(e) => debug!("The credential store still can't take the session saved to a file: {e}"),
         }
+        Ok(Some(session))
     }
     
+    /// Writes and removes a throwaway entry, which surfaces locked or missing
+    /// credential stores that a plain lookup can miss
+    fn probe() -> Result<()> {
+        #[cfg(target_os = "linux")]
+        if sandbox::uses_secret_portal() {
+            return sandbox::retrieve_secret().map(|_| ());
+        }
+
+        let entry = Self::entry("doctor-probe")?;
+        entry.set_password("probe")?;
+        entry.delete_credential()?;
+        Ok(())
+    }
+
     fn clear(session_name: &Option<String>) -> Result<()> {
+        FileStore::clear(session_name)?;
+        #[cfg(target_os = "linux")]
+        if sandbox::uses_secret_portal() {
+            return PortalStore::clear(session_name);
+        }
+
         let entry = Self::get_entry(session_name)?;
         match entry.delete_credential() {
             Ok(()) => Ok(()),
@@ -87,60 +544,198 @@ impl SessionStore {
     }
 }
 
+/// Offline character lists older than this come with a warning
+const STALE_CACHE_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+static MAX_CACHE_AGE: OnceLock<Duration> = OnceLock::new();
+
+/// Makes offline lookups fail once the cached list is older than `max_age`
+pub fn set_max_cache_age(max_age: Duration) {
+    let _ = MAX_CACHE_AGE.set(max_age);
+}
+
+/// A rough age for messages, in the largest whole unit
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    let (count, unit) = match secs {
+        0..60 => (secs, "second"),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    if count == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
+
+/// Token bucket spreading out requests to Jagex, shared by every `Client` so
+/// commands going through many sessions at once can't get the IP throttled
+struct RateLimiter {
+    /// Requests available straight away, and when they were last topped up
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn get() -> &'static Self {
+        static LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+        LIMITER.get_or_init(|| Self {
+            state: Mutex::new((f64::from(config::get().api.burst), Instant::now())),
+        })
+    }
+
+    /// Takes a request from the bucket, first waiting for one to free up if
+    /// it's empty
+    async fn acquire(&self) {
+        let api = &config::get().api;
+        let per_second = f64::from(api.requests_per_minute.max(1)) / 60.0;
+        let burst = f64::from(api.burst.max(1));
+
+        let wait = {
+            let Ok(mut state) = self.state.lock() else {
+                return;
+            };
+            let (tokens, last) = &mut *state;
+            let now = Instant::now();
+            *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * per_second).min(burst);
+            *last = now;
+            *tokens -= 1.0;
+            // A negative balance is the queue of requests ahead of this one
+            Duration::from_secs_f64((-*tokens).max(0.0) / per_second)
+        };
+
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Connection pool shared by every `Client`, so commands going through several
+/// sessions reuse connections to Jagex rather than handshaking for each one.
+/// Built on first use, as setting up TLS is wasted on offline commands.
+pub fn shared_http() -> &'static reqwest::Client {
+    static HTTP: OnceLock<reqwest::Client> = OnceLock::new();
+    HTTP.get_or_init(|| http_builder().build().unwrap_or_else(|_| reqwest::Client::new()))
+}
+
+fn http_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder()
+        .pool_idle_timeout(Duration::from_secs(90))
+        .tcp_keepalive(Duration::from_secs(60))
+        .http2_keep_alive_interval(Duration::from_secs(30))
+        .http2_keep_alive_while_idle(true);
+    network::apply(builder)
+}
+
+/// The connection pool for a session's route. Sessions on the same route
+/// share one, and those left to the environment use [`shared_http`].
+pub fn http_for(session_name: &Option<String>) -> Result<&'static reqwest::Client> {
+    static ROUTED: OnceLock<Mutex<BTreeMap<String, &'static reqwest::Client>>> = OnceLock::new();
+
+    let route = proxy::route(session_name)?;
+    let (key, builder) = match &route {
+        Route::Environment => return Ok(shared_http()),
+        Route::Direct => ("direct".to_owned(), http_builder().no_proxy()),
+        Route::Proxy(url) => (url.to_string(), http_builder().proxy(reqwest::Proxy::all(url.as_str())?)),
+    };
+
+    let mut routed = ROUTED.get_or_init(Default::default).lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(http) = routed.get(&key) {
+        return Ok(http);
+    }
+    // Kept for the rest of the process like the shared pool, one per proxy
+    let http: &'static reqwest::Client = Box::leak(Box::new(builder.build()?));
+    routed.insert(key, http);
+    Ok(http)
+}
+
 pub struct Client {
     session_name: Option<String>,
-    client: reqwest::Client,
 }
 
 
 impl Client {
     pub fn new(session_name: Option<String>) -> Self {
-        Self {
-            session_name,
-            client: reqwest::Client::new(),
+        Self { session_name }
+    }
+
+    fn http(&self) -> Result<&'static reqwest::Client> {
+        http_for(&self.session_name)
+    }
+
+    /// Sends a request once the rate limiter allows it. Server errors are
+    /// reported as an outage rather than the response they came with.
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        RateLimiter::get().acquire().await;
+        let response = request.send().await?;
+        if response.status().is_server_error() {
+            return Err(status::outage(self.http()?, response.status()).await);
         }
+        Ok(response)
+    }
+
+    pub fn session_name(&self) -> &Option<String> {
+        &self.session_name
     }
 
     pub async fn token(&self, code: &str, verifier: &str) -> Result<AuthState> {
         let url = "https://account.jagex.com/oauth2/token";
         let time = SystemTime::now();
-        let response = self.client
+        let oauth = crate::env::oauth(&self.session_name);
+        let request = self.http()?
             .post(url)
             .form(&[
                 ("grant_type", "authorization_code"),
-                ("client_id", crate::env::CLIENT_ID),
+                ("client_id", oauth.client_id.as_str()),
                 ("code", code),
                 ("code_verifier", verifier),
                 ("redirect_uri", crate::env::REDIRECT),
-            ])
-            .send()
-            .await?;
+            ]);
+        let response = self.send(request).await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(token_error(status, &response.text().await.unwrap_or_default()));
+        }
 
         let tokens: Tokens = response.json().await?;
         let state = AuthState { time, tokens };
         Ok(state)
     }
 
-    pub async fn create_session(&self, token: &str) -> Result<Session> {
+    /// Exchanges the consent step's ID token for a game session and stores
+    /// it, along with the time and lifetime of the tokens it came from
+    pub async fn create_session(&self, token: &str, issued_at: SystemTime, expires_in: Duration) -> Result<Session> {
         let url = "https://auth.jagex.com/game-session/v1/sessions";
         let body = SessionRequest { id_token: token.to_owned() };
-        let response = self.client.post(url)
+        let request = self.http()?.post(url)
             .body(serde_json::to_string(&body)?)
             .header("Content-Type", "application/json")
-            .header("Accept", "application/json")
-            .send()
-            .await?;
-        let session: Session = response.json().await?;
-        SessionStore::store(&self.session_name, &session)?;
+            .header("Accept", "application/json");
+        let response = self.send(request).await?;
+        let mut session: Session = response.json().await?;
+        session.issued_at = issued_at.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+        session.expires_in = Some(expires_in.as_secs());
+        SessionStore::store_or_fallback(&self.session_name, &session)?;
+        sessions::record(&self.session_name)?;
         self.clear_accounts_cache()?;
         Ok(session)
     }
 
+    pub fn credential_store_service() -> String {
+        SessionStore::service()
+    }
+
+    pub fn probe_credential_store() -> Result<()> {
+        SessionStore::probe()
+    }
+
     pub fn session(&self) -> Result<Session> {
         SessionStore::load(&self.session_name)?.ok_or(AuthError::SessionNotFound)
     }
     
-    fn clear_accounts_cache(&self) -> Result<()> {
+    /// Deletes the offline character list
+    pub fn clear_accounts_cache(&self) -> Result<()> {
         let path = match self.accounts_cache_dir() {
             Ok(path) => path,
             Err(AuthError::NoCacheDir) => return Ok(()),
@@ -155,16 +750,50 @@ impl Client {
     }
 
     fn accounts_cache_dir(&self) -> Result<PathBuf> {
-        let mut path = dirs::cache_dir().ok_or(AuthError::NoCacheDir)?;
-        let key = match &self.session_name {
-            Some(session_name) => format!("named-session-{session_name}"),
-            None => "session".to_owned(),
-        };
-        path = path.join("auth-rs");
-        path = path.join(key);
-        Ok(path)
+        Ok(paths::cache_dir()?.join(sessions::key(&self.session_name)))
     }
 
+    /// How long ago the offline character list was written, if there is one
+    pub fn accounts_cache_age(&self) -> Result<Option<Duration>> {
+        let path = self.accounts_cache_dir()?.join("accounts.json");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let modified = std::fs::metadata(path)?.modified()?;
+        Ok(Some(SystemTime::now().duration_since(modified).unwrap_or_default()))
+    }
+
+    /// The offline character list for `--offline`, with a warning once it's
+    /// older than a week and an error past `--max-age` or when none was saved
+    pub fn offline_accounts(&self) -> Result<Vec<Account>> {
+        let age = self.accounts_cache_age()?.ok_or(AuthError::NoOfflineCharacters)?;
+        if let Some(max_age) = MAX_CACHE_AGE.get().filter(|max_age| age > **max_age) {
+            return Err(AuthError::OfflineCacheStale {
+                age: format_age(age),
+                max_age: format_age(*max_age),
+            });
+        }
+        if age > STALE_CACHE_AGE {
+            output::renderer().status(&format!(
+                "{} the offline character list was saved {} ago and may be out of date",
+                theme::warning("Warning:").bold(),
+                format_age(age)
+            ));
+        }
+        self.accounts_cache()
+    }
+
+    /// The character from the offline list, whatever its age, if it's there
+    pub fn cached_account(&self, character_id: &str) -> Option<Account> {
+        self.cached_accounts().into_iter().find(|account| account.account_id == character_id)
+    }
+
+    /// The offline list, whatever its age, empty when there's none
+    pub fn cached_accounts(&self) -> Vec<Account> {
+        self.accounts_cache().unwrap_or_default()
+    }
+
+    /// Characters stored by `ls --write-cache`, without touching the network
     fn accounts_cache(&self) -> Result<Vec<Account>> {
         let path = self.accounts_cache_dir()?;
         let path = path.join("accounts.json");
@@ -178,47 +807,122 @@ impl Client {
         Ok(accounts)
     }
 
-    fn store_accounts(&self, accounts: &Vec<Account>) -> Result<()> {
+    /// The ETag of the response the offline character list was written from
+    fn accounts_etag(&self) -> Option<String> {
+        let etag = std::fs::read_to_string(self.accounts_cache_dir().ok()?.join("accounts.etag")).ok()?;
+        Some(etag.trim().to_owned()).filter(|etag| !etag.is_empty())
+    }
+
+    fn store_accounts(&self, accounts: &Vec<Account>, etag: Option<&str>) -> Result<()> {
+        if config::read_only() {
+            return Ok(());
+        }
         let path = self.accounts_cache_dir()?;
 
         if !path.exists() {
             std::fs::create_dir_all(&path)?;
         }
 
-        let path = path.join("accounts.json");
-        let file = std::fs::File::create(path)?;
-
+        // Written aside and moved into place, so a crash can't leave half a list
+        let temp_path = path.join("accounts.json.tmp");
+        let file = std::fs::File::create(&temp_path)?;
         serde_json::to_writer(file, accounts)?;
 
+        // The old ETag goes before the list is replaced and the new one only
+        // after, so a crash in between can't pair an ETag with another list
+        let etag_path = path.join("accounts.etag");
+        if etag_path.exists() {
+            std::fs::remove_file(&etag_path)?;
+        }
+        std::fs::rename(temp_path, path.join("accounts.json"))?;
+        if let Some(etag) = etag {
+            std::fs::write(etag_path, etag)?;
+        }
+
         Ok(())
     }
 
+    /// Removes a half-written or unreadable offline character list, returning
+    /// what was removed
+    pub fn repair_accounts_cache(&self) -> Result<Vec<String>> {
+        let dir = match self.accounts_cache_dir() {
+            Ok(dir) => dir,
+            Err(AuthError::NoCacheDir) => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut removed = vec![];
+
+        let temp_path = dir.join("accounts.json.tmp");
+        if temp_path.exists() {
+            std::fs::remove_file(&temp_path)?;
+            removed.push(format!("partial character list {}", temp_path.display()));
+        }
+        let path = dir.join("accounts.json");
+        if path.exists() && self.accounts_cache().is_err() {
+            std::fs::remove_file(&path)?;
+            removed.push(format!("unreadable character list {}", path.display()));
+        }
+
+        Ok(removed)
+    }
+
     pub async fn accounts(&self, offline: bool, store_offline: bool) -> Result<Vec<Account>> {
+        let (accounts, _) = self.accounts_with_diff(offline, store_offline).await?;
+        Ok(accounts)
+    }
+
+    /// Fetches the characters like `accounts`, along with how they differ
+    /// from the cached list. The cache is brought up to date whenever a
+    /// rename is seen, so offline lookups by name keep working.
+    pub async fn accounts_with_diff(&self, offline: bool, store_offline: bool) -> Result<(Vec<Account>, AccountsDiff)> {
         let session = self.session()?;
 
         if offline {
-            return self.accounts_cache();
+            return Ok((self.offline_accounts()?, AccountsDiff::default()));
         }
 
+        // With a list on disk, Jagex can answer a repeat fetch with a 304
+        let cached = self.accounts_cache().unwrap_or_default();
+        let etag = self.accounts_etag().filter(|_| !cached.is_empty());
+
         let url = "https://auth.jagex.com/game-session/v1/accounts";
-        let response = self.client.get(url)
+        let mut request = self.http()?.get(url)
             .header("Content-Type", "application/json")
             .header("Accept", "application/json")
-            .header("Authorization", format!("Bearer {}", session.session_id))
-            .send()
-            .await?;
+            .header("Authorization", format!("Bearer {}", session.session_id));
+        if let Some(etag) = &etag {
+            request = request.header("If-None-Match", etag);
+        }
+        let response = self.send(request).await?;
+
+        if matches!(response.status(), reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) {
+            return Err(AuthError::SessionExpired);
+        }
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            progress::emit(progress::Event::AccountsFetched { accounts: cached.len() });
+            // Written again so its age says when it was last confirmed
+            if store_offline {
+                self.store_accounts(&cached, etag.as_deref())?;
+            }
+            return Ok((cached, AccountsDiff::default()));
+        }
+
+        let response_etag = response.headers().get("ETag").and_then(|etag| etag.to_str().ok()).map(str::to_owned);
         let accounts: Vec<Account> = response.json().await?;
+        progress::emit(progress::Event::AccountsFetched { accounts: accounts.len() });
 
-        if store_offline {
-            self.store_accounts(&accounts)?;
+        let diff = AccountsDiff::between(&cached, &accounts);
+        if store_offline || !diff.renamed.is_empty() {
+            self.store_accounts(&accounts, response_etag.as_deref())?;
         }
 
-        Ok(accounts)
+        Ok((accounts, diff))
     }
 
     pub fn logout(&self) -> Result<()> {
         SessionStore::clear(&self.session_name)?;
         self.clear_accounts_cache()?;
+        sessions::forget(&self.session_name)?;
 
         Ok(())
     }
@@ -0,0 +1,28 @@
+use arboard::Clipboard;
+
+use crate::{error::Result, launch};
+
+/// Copies the text to the system clipboard. On Linux the clipboard is only
+/// served while its owner runs, so a helper process holds it until
+/// something else is copied.
+pub fn copy(text: &str) -> Result<()> {
+    if cfg!(target_os = "linux") {
+        return launch::spawn_helper(["hold-clipboard", text]);
+    }
+
+    Clipboard::new()?.set_text(text)?;
+    Ok(())
+}
+
+/// Serves the text as the clipboard contents until it's replaced
+pub fn hold(text: &str) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use arboard::SetExtLinux;
+        Clipboard::new()?.set().wait().text(text)?;
+    }
+    #[cfg(not(target_os = "linux"))]
+    Clipboard::new()?.set_text(text)?;
+
+    Ok(())
+}
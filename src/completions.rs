@@ -0,0 +1,256 @@
+use std::{collections::BTreeSet, process::Command};
+
+use clap::{Arg, ValueEnum};
+use console::style;
+
+use crate::{
+    client::Client,
+    config,
+    error::{AuthError, Result},
+    sessions, theme,
+};
+
+/// A shell completions are written for
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    fn name(&self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+        }
+    }
+}
+
+/// The completion script for the shell. It asks `bin complete` for the
+/// candidates each time, so they follow the current command line
+/// definitions, sessions and characters without regenerating the script.
+pub fn script(shell: Shell, bin: &str) -> String {
+    match shell {
+        Shell::Bash => format!(
+            r#"_auth_rs() {{
+    local IFS=$'\n'
+    COMPREPLY=($('{bin}' complete -- "${{COMP_WORDS[@]:0:$((COMP_CWORD + 1))}}" 2>/dev/null))
+}}
+complete -o default -F _auth_rs auth-rs
+"#
+        ),
+        Shell::Zsh => format!(
+            r#"#compdef auth-rs
+_auth-rs() {{
+    local -a candidates
+    candidates=("${{(@f)$('{bin}' complete -- "${{(@)words[1,CURRENT]}}" 2>/dev/null)}}")
+    compadd -a candidates
+}}
+if [[ "$funcstack[1]" = "_auth-rs" ]]; then
+    _auth-rs "$@"
+else
+    compdef _auth-rs auth-rs
+fi
+"#
+        ),
+        Shell::Fish => format!(
+            r#"function __auth_rs_complete
+    '{bin}' complete -- (commandline -opc) (commandline -ct) 2>/dev/null
+end
+complete -c auth-rs -f -a '(__auth_rs_complete)'
+"#
+        ),
+    }
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+/// Values for an option: its choices, or the sessions, characters,
+/// profiles or tags auth-rs knows of for the options that name them
+fn values(arg: &Arg, session_name: &Option<String>) -> Vec<String> {
+    let choices = arg.get_possible_values();
+    if !choices.is_empty() {
+        return choices.iter().filter(|value| !value.is_hide_set()).map(|value| value.get_name().to_owned()).collect();
+    }
+
+    let config = config::get();
+    match arg.get_id().as_str() {
+        "session_name" => sessions::list().unwrap_or_default().into_iter().flatten().collect(),
+        "character_id" => {
+            Client::new(session_name.clone()).cached_accounts().into_iter().map(|account| account.account_id).collect()
+        }
+        "profile" => config.profiles.keys().cloned().collect(),
+        "tag" => config
+            .sessions
+            .values()
+            .flat_map(|session| &session.tags)
+            .chain(config.characters.values().flat_map(|character| &character.tags))
+            .cloned()
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// The candidates for the last of `words`, the program name first. Options
+/// complete after a `-`, their values after the option, and subcommands
+/// and `[aliases]` otherwise.
+pub fn candidates(mut cmd: clap::Command, words: &[String]) -> Vec<String> {
+    // Propagates global options and the generated `--help` to subcommands
+    cmd.build();
+    let Some((current, before)) = words.split_last() else {
+        return vec![];
+    };
+
+    let mut cmd = &cmd;
+    let mut nested = false;
+    let mut pending = None;
+    let mut session_name = None;
+    for word in before.iter().skip(1) {
+        if let Some(arg) = pending.take() {
+            if arg == "session_name" {
+                session_name = Some(word.clone());
+            }
+            continue;
+        }
+
+        let arg = if let Some(long) = word.strip_prefix("--") {
+            cmd.get_arguments().find(|arg| arg.get_long() == Some(long))
+        } else if let Some(shorts) = word.strip_prefix('-').filter(|shorts| shorts.len() == 1) {
+            cmd.get_arguments().find(|arg| shorts.chars().next() == arg.get_short())
+        } else {
+            if let Some(subcommand) = cmd.find_subcommand(word) {
+                cmd = subcommand;
+                nested = true;
+            }
+            continue;
+        };
+        pending = arg.filter(|arg| takes_value(arg)).map(|arg| arg.get_id().as_str().to_owned());
+    }
+
+    let all = if let Some(id) = pending {
+        let arg = cmd.get_arguments().find(|arg| arg.get_id() == id.as_str());
+        arg.map(|arg| values(arg, &session_name)).unwrap_or_default()
+    } else if current.starts_with('-') {
+        cmd.get_arguments()
+            .filter(|arg| !arg.is_hide_set())
+            .filter_map(|arg| arg.get_long().map(|long| format!("--{long}")))
+            .collect()
+    } else {
+        let aliases = config::get().aliases.keys().filter(|_| !nested).cloned();
+        cmd.get_subcommands()
+            .filter(|subcommand| !subcommand.is_hide_set())
+            .map(|subcommand| subcommand.get_name().to_owned())
+            .chain(aliases)
+            .collect()
+    };
+    all.into_iter().filter(|candidate| candidate.starts_with(current.as_str())).collect()
+}
+
+/// Command lines to try: the top level, each subcommand's prefix and its
+/// options, and the choices of each option that has them
+fn cases(mut cmd: clap::Command) -> Vec<Vec<String>> {
+    cmd.build();
+    let program = cmd.get_name().to_owned();
+    let mut cases = vec![vec![program.clone(), String::new()]];
+
+    for subcommand in cmd.get_subcommands().filter(|subcommand| !subcommand.is_hide_set()) {
+        let name = subcommand.get_name().to_owned();
+        let prefix = name.chars().take(2).collect::<String>();
+        cases.push(vec![program.clone(), prefix]);
+        cases.push(vec![program.clone(), name.clone(), "--".to_owned()]);
+
+        let choices = subcommand
+            .get_arguments()
+            .filter(|arg| !arg.is_hide_set() && !arg.is_global_set() && !arg.get_possible_values().is_empty())
+            .filter_map(|arg| arg.get_long());
+        for long in choices {
+            cases.push(vec![program.clone(), name.clone(), format!("--{long}"), String::new()]);
+        }
+    }
+    cases
+}
+
+/// A word quoted for any of the shells
+fn quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// Runs the script in a fresh shell with the command line typed, returning
+/// the candidates the shell's completion function came back with
+fn run_in_shell(shell: Shell, script: &str, words: &[String]) -> Result<BTreeSet<String>> {
+    let quoted = words.iter().map(|word| quote(word)).collect::<Vec<_>>().join(" ");
+    let body = match shell {
+        Shell::Bash => format!(
+            "{script}\nCOMP_WORDS=({quoted})\nCOMP_CWORD={}\n_auth_rs\nprintf '%s\\n' \"${{COMPREPLY[@]}}\"\n",
+            words.len() - 1
+        ),
+        // The completion system isn't loaded, so `compdef` and `compadd`
+        // are stood in for
+        Shell::Zsh => format!(
+            "compdef() {{ :; }}\ncompadd() {{ printf '%s\\n' \"${{(@P)2}}\"; }}\n{script}\nwords=({quoted})\nCURRENT={}\n_auth-rs\n",
+            words.len()
+        ),
+        Shell::Fish => format!("{script}\ncomplete -C {}\n", quote(&words.join(" "))),
+    };
+
+    let args: &[&str] = match shell {
+        Shell::Bash => &["--norc", "--noprofile", "-c"],
+        Shell::Zsh => &["-f", "-c"],
+        Shell::Fish => &["--no-config", "-c"],
+    };
+    let output = Command::new(shell.name()).args(args).arg(body).output().map_err(|error| AuthError::ExecError {
+        program: shell.name().to_owned(),
+        details: error.to_string(),
+    })?;
+
+    // fish prints each candidate with its description after a tab
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| line.split('\t').next().unwrap_or_default().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// Sources the generated script in the shell and checks its completion
+/// function returns what auth-rs itself does for a set of command lines, so
+/// a change to the command line definitions that breaks completion is
+/// caught before it ships
+pub fn check(shell: Shell, cmd: clap::Command) -> Result<()> {
+    let bin = std::env::current_exe()?.display().to_string();
+    let script = script(shell, &bin);
+
+    let mut failed = 0;
+    let cases = cases(cmd.clone());
+    for words in &cases {
+        let line = words.join(" ");
+        let expected = candidates(cmd.clone(), words).into_iter().collect::<BTreeSet<_>>();
+        let actual = run_in_shell(shell, &script, words)?;
+        if expected == actual {
+            println!("  {} {}", theme::highlight("✓"), style(line).bold());
+            continue;
+        }
+
+        failed += 1;
+        println!("  {} {}", theme::error("✗"), style(line).bold());
+        let missing = expected.difference(&actual).cloned().collect::<Vec<_>>();
+        let unexpected = actual.difference(&expected).cloned().collect::<Vec<_>>();
+        if !missing.is_empty() {
+            println!("    missing: {}", style(missing.join(" ")).dim());
+        }
+        if !unexpected.is_empty() {
+            println!("    unexpected: {}", style(unexpected.join(" ")).dim());
+        }
+    }
+
+    if failed > 0 {
+        return Err(AuthError::CompletionCheckFailed { shell: shell.name(), failed, total: cases.len() });
+    }
+    println!("All {} command lines complete in {}", theme::highlight(cases.len()), shell.name());
+    Ok(())
+}
@@ -0,0 +1,554 @@
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{AuthError, Result},
+    game::Game,
+    gc::GcConfig,
+    network::NetworkConfig,
+    paths,
+    placement::WindowConfig,
+    policy::PolicyConfig,
+    presets::{self, Preset, PresetsConfig},
+    webhook::WebhookConfig,
+    restrictions::RestrictionsConfig,
+    status::StatusConfig,
+    theme::ThemeConfig,
+};
+
+/// A named launch configuration, written by `auth-rs init`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Profile {
+    pub session_name: Option<String>,
+    pub character_id: Option<String>,
+    pub game: Option<Game>,
+    pub preset: Option<Preset>,
+    pub exec: Option<String>,
+    pub args: Vec<String>,
+    /// Extra variables set for the client
+    pub env: BTreeMap<String, String>,
+    /// SHA-256 the executable must have, recorded by `profile pin`
+    pub sha256: Option<String>,
+}
+
+/// Per-character settings, keyed by character ID
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Character {
+    /// Your own label for the character, shown by `ls --format table`
+    pub nickname: Option<String>,
+    /// RuneLite `--profile` to launch this character with
+    pub runelite_profile: Option<String>,
+    /// Always launch this character with its own home directory
+    pub isolate_home: bool,
+    /// Groups the character belongs to, for `--tag`
+    pub tags: Vec<String>,
+    /// Launches allowed each day, over `[restrictions] daily_launches`
+    pub daily_launches: Option<u32>,
+}
+
+/// Per-session settings, keyed by session name (`default` for the unnamed session)
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Groups every character of the session belongs to, for `--tag`
+    pub tags: Vec<String>,
+    /// Email to prefill on the login page
+    pub email: Option<String>,
+    /// Proxy the session's requests and login window go through, e.g.
+    /// `socks5h://127.0.0.1:9050`, or `direct` to ignore the environment's
+    pub proxy: Option<String>,
+    /// Give a SOCKS proxy credentials of the session's own, so Tor builds
+    /// it a circuit apart from other sessions
+    pub isolate_circuit: bool,
+    pub oauth: OAuthConfig,
+}
+
+/// Overrides for the IDs and scopes sent to Jagex, under `[oauth]` or
+/// `[sessions.<name>.oauth]`. Only needed if Jagex changes them.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct OAuthConfig {
+    /// Client ID of the login step
+    pub client_id: Option<String>,
+    /// Scopes requested by the login step
+    pub scope: Option<String>,
+    /// Client ID of the consent step
+    pub consent_client_id: Option<String>,
+    /// Scopes requested by the consent step
+    pub consent_scope: Option<String>,
+    /// Receive the consent redirect on a local HTTP listener instead of
+    /// intercepting it in the window
+    pub consent_listener: bool,
+    /// Characters in the PKCE code verifier of the login step, 43 to 128
+    pub pkce_verifier_length: Option<usize>,
+    /// Minutes a login page stays usable before the login starts over
+    pub flow_timeout_minutes: Option<u64>,
+}
+
+/// Credential store settings, under `[keyring]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct KeyringConfig {
+    /// Service name entries are stored under, to keep installs sharing a
+    /// credential store apart
+    pub service: Option<String>,
+    /// Secret Service collection (by label) to store sessions in, created if
+    /// it doesn't exist. Linux only; the default collection is used otherwise.
+    pub collection: Option<String>,
+    pub macos: MacosKeychainConfig,
+}
+
+/// Keychain item options, under `[keyring.macos]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct MacosKeychainConfig {
+    /// Require Touch ID or the account password whenever the session is read
+    pub require_user_presence: bool,
+    /// Keychain access group to store the session in
+    pub access_group: Option<String>,
+}
+
+/// Defaults for `create-desktop-entry`, under `[desktop]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct DesktopConfig {
+    /// Run entries in the desktop's default terminal
+    pub terminal: bool,
+    /// Terminal emulator command to wrap entries in, e.g. `"foot"` or `"gnome-terminal --"`
+    pub terminal_command: Option<String>,
+    /// Use each character's chathead as its entry's icon
+    pub avatars: bool,
+    /// Where chatheads are fetched from, with `{name}` replaced by the
+    /// character's display name. RuneScape's own service when unset.
+    pub avatar_url: Option<String>,
+}
+
+/// Which of our environment variables launched clients inherit, under `[env]`.
+/// Names ending in `*` match every variable with that prefix.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct EnvConfig {
+    /// Only pass these variables on, when set
+    pub allow: Option<Vec<String>>,
+    /// Variables never passed on, e.g. `SSH_AUTH_SOCK`
+    pub deny: Vec<String>,
+}
+
+/// Limits on requests to Jagex, under `[api]`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Sustained rate, shared by everything the process does
+    pub requests_per_minute: u32,
+    /// Requests allowed in a quick burst before the rate applies
+    pub burst: u32,
+    /// Sessions `refresh` and the daemon refresh at once
+    pub parallel_refreshes: usize,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 60,
+            burst: 10,
+            parallel_refreshes: 4,
+        }
+    }
+}
+
+/// When to warn about a session running out, under `[expiry]`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ExpiryConfig {
+    /// Minutes before the estimated expiry that launching starts warning
+    pub warn_minutes: u64,
+}
+
+impl Default for ExpiryConfig {
+    fn default() -> Self {
+        Self { warn_minutes: 10 }
+    }
+}
+
+/// Settings for `daemon run`, under `[daemon]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct DaemonConfig {
+    /// Seconds between refreshes
+    pub interval: Option<u64>,
+    /// Address to serve Prometheus metrics on
+    pub metrics_addr: Option<SocketAddr>,
+    /// URLs told about failed refreshes, expiring sessions and changed characters
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+/// Settings for `exec --sandbox`, under `[sandbox]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct SandboxConfig {
+    /// Directory mounted as the client's home, instead of the one auth-rs manages
+    pub home: Option<PathBuf>,
+    /// Extra paths the client may write to
+    pub binds: Vec<PathBuf>,
+    /// Extra paths the client may read
+    pub ro_binds: Vec<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct Config {
+    pub default_profile: Option<String>,
+    /// Game launched when neither the command line nor the profile says
+    pub game: Option<Game>,
+    /// Forbid commands that change the stored sessions, for shared machines
+    pub read_only: bool,
+    pub profiles: BTreeMap<String, Profile>,
+    /// Commands of your own, expanded before the command line is parsed,
+    /// e.g. `main = "exec -s main -c 1234 runelite"` for `auth-rs main`
+    pub aliases: BTreeMap<String, String>,
+    pub characters: BTreeMap<String, Character>,
+    pub sessions: BTreeMap<String, SessionConfig>,
+    pub presets: PresetsConfig,
+    pub keyring: KeyringConfig,
+    pub sandbox: SandboxConfig,
+    pub desktop: DesktopConfig,
+    pub env: EnvConfig,
+    pub oauth: OAuthConfig,
+    pub theme: ThemeConfig,
+    pub daemon: DaemonConfig,
+    pub api: ApiConfig,
+    pub network: NetworkConfig,
+    pub status: StatusConfig,
+    pub window: WindowConfig,
+    pub expiry: ExpiryConfig,
+    pub restrictions: RestrictionsConfig,
+    pub policy: PolicyConfig,
+    pub gc: GcConfig,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Sets the config used for the rest of the process, loaded once at startup
+pub fn init(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+static SYSTEM_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+/// Adds the config at `path` as a system layer over the platform's usual one,
+/// which still applies and whose `[enforce]` still wins
+pub fn set_system_path(path: PathBuf) {
+    let _ = SYSTEM_PATH.set(path);
+}
+
+/// The extra system layer given with `--system-config`
+pub fn extra_system_path() -> Option<PathBuf> {
+    SYSTEM_PATH.get().cloned()
+}
+
+/// Where the admin-managed config shared by every user is read from. It's
+/// fixed, so a user can't swap out the `[enforce]` table with a flag.
+pub fn system_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        std::env::var_os("ProgramData").map(|dir| PathBuf::from(dir).join("auth-rs").join("config.toml"))
+    } else if cfg!(target_os = "macos") {
+        Some(PathBuf::from("/Library/Application Support/auth-rs/config.toml"))
+    } else {
+        Some(PathBuf::from("/etc/auth-rs/config.toml"))
+    }
+}
+
+fn read_table(path: &Path) -> Result<toml::Table> {
+    let contents = std::fs::read_to_string(path)?;
+    toml::from_str(&contents).map_err(|e| AuthError::ConfigError {
+        path: path.display().to_string(),
+        details: e.to_string(),
+    })
+}
+
+/// Merges `over` into `base`, table by table, with `over`'s values winning
+fn merge(base: &mut toml::Table, over: toml::Table) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The table under `key`, created when it isn't set
+pub fn table_mut<'a>(table: &'a mut toml::Table, key: &str) -> Result<&'a mut toml::Table> {
+    let path = Config::path().map(|path| path.display().to_string()).unwrap_or_default();
+    table
+        .entry(key)
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+        .as_table_mut()
+        .ok_or_else(|| AuthError::ConfigError { path, details: format!("'{key}' must be a table") })
+}
+
+/// The value as it would be written to a config file
+pub fn to_value<T: Serialize>(value: &T) -> Result<toml::Value> {
+    toml::Value::try_from(value).map_err(|e| AuthError::ConfigError {
+        path: Config::path().map(|path| path.display().to_string()).unwrap_or_default(),
+        details: e.to_string(),
+    })
+}
+
+/// Merges `under` into `base` where `base` has nothing set, table by table
+fn merge_missing(base: &mut toml::Table, under: toml::Table) {
+    for (key, value) in under {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(under)) => merge_missing(base, under),
+            (Some(_), _) => (),
+            (None, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+static READ_ONLY: OnceLock<bool> = OnceLock::new();
+
+/// Sets whether `--read-only` was passed
+pub fn set_read_only(read_only: bool) {
+    let _ = READ_ONLY.set(read_only);
+}
+
+/// Whether sessions and caches must be left as they are, by `--read-only`
+/// or `read_only` in the config file
+pub fn read_only() -> bool {
+    READ_ONLY.get().copied().unwrap_or_default() || get().read_only
+}
+
+/// The client options given on the command line, before the profile's are
+/// merged in
+#[derive(Debug, Default)]
+pub struct ClientOptions {
+    pub game: Option<Game>,
+    pub preset: Option<Preset>,
+    pub exec: Option<String>,
+    pub args: Vec<String>,
+}
+
+/// Fully resolved options for launching a program with a session
+#[derive(Debug)]
+pub struct LaunchTarget {
+    pub session_name: Option<String>,
+    pub character_id: String,
+    pub exec: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    /// The profile's pinned SHA-256 of `exec`, unless the program was given
+    /// on the command line
+    pub sha256: Option<String>,
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf> {
+        Ok(paths::config_dir()?.join("config.toml"))
+    }
+
+    /// The user's config file alone, without the system-wide layer, for
+    /// editing and saving back
+    pub fn load_user() -> Result<Self> {
+        let path = Self::path()?;
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(&path)?;
+        toml::from_str(&contents).map_err(|e| AuthError::ConfigError {
+            path: path.display().to_string(),
+            details: e.to_string(),
+        })
+    }
+
+    /// The config in effect: the system configs' settings as defaults, the
+    /// user's config over them, then the system configs' `[enforce]` tables
+    /// over both. A `--system-config` layer goes over the platform's system
+    /// config, except for `[enforce]`, where the platform's wins.
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        let extra = extra_system_path();
+        if let Some(extra) = extra.as_ref().filter(|path| !path.exists()) {
+            return Err(AuthError::ConfigError {
+                path: extra.display().to_string(),
+                details: "The system config given with --system-config doesn't exist".to_owned(),
+            });
+        }
+        // Lowest precedence first
+        let system_paths = extra
+            .into_iter()
+            .chain(system_path().filter(|path| path.exists()))
+            .rev()
+            .collect::<Vec<_>>();
+        if system_paths.is_empty() {
+            return Self::load_user();
+        }
+
+        let mut merged = toml::Table::new();
+        let mut enforced = toml::Table::new();
+        for system_path in &system_paths {
+            let mut layer = read_table(system_path)?;
+            match layer.remove("enforce") {
+                // The platform's layer is read first, and its rules win
+                Some(toml::Value::Table(layer_enforced)) => merge_missing(&mut enforced, layer_enforced),
+                Some(_) => {
+                    return Err(AuthError::ConfigError {
+                        path: system_path.display().to_string(),
+                        details: "'enforce' must be a table".to_owned(),
+                    })
+                }
+                None => (),
+            }
+            merge(&mut merged, layer);
+        }
+        if path.exists() {
+            merge(&mut merged, read_table(&path)?);
+        }
+        merge(&mut merged, enforced);
+
+        let layers = system_paths.iter().rev().map(|path| path.display().to_string()).collect::<Vec<_>>();
+        toml::Value::Table(merged).try_into().map_err(|e: toml::de::Error| AuthError::ConfigError {
+            path: format!("{} (over {})", path.display(), layers.join(" and ")),
+            details: e.to_string(),
+        })
+    }
+
+    /// Changes keys of the user's config file and writes it back. Only what
+    /// `edit` sets is written, so everything else still comes from the
+    /// system config and the defaults.
+    pub fn edit_user(edit: impl FnOnce(&mut toml::Table) -> Result<()>) -> Result<PathBuf> {
+        let path = Self::path()?;
+        let mut table = if path.exists() { read_table(&path)? } else { toml::Table::new() };
+        edit(&mut table)?;
+
+        let config_error = |details: String| AuthError::ConfigError { path: path.display().to_string(), details };
+        // Checked before it's written, so an edit can't leave a file that doesn't load
+        toml::Value::Table(table.clone()).try_into::<Config>().map_err(|e| config_error(e.to_string()))?;
+        let contents = toml::to_string_pretty(&table).map_err(|e| config_error(e.to_string()))?;
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    pub fn character(&self, character_id: &str) -> Option<&Character> {
+        self.characters.get(character_id)
+    }
+
+    pub fn session(&self, session_name: &Option<String>) -> Option<&SessionConfig> {
+        self.sessions.get(session_name.as_deref().unwrap_or("default"))
+    }
+
+    /// Whether the character, or the session it was found in, carries the tag
+    pub fn has_tag(&self, session_name: &Option<String>, character_id: &str, tag: &str) -> bool {
+        let tagged = |tags: &[String]| tags.iter().any(|t| t == tag);
+        self.session(session_name).is_some_and(|s| tagged(&s.tags))
+            || self.character(character_id).is_some_and(|c| tagged(&c.tags))
+    }
+
+    pub fn profile(&self, name: &str) -> Result<&Profile> {
+        self.profiles.get(name).ok_or_else(|| AuthError::ProfileNotFound {
+            name: name.to_owned(),
+        })
+    }
+
+    /// The named profile, or the default one when no character was given
+    fn select_profile(&self, profile: Option<String>, character_id: &Option<String>) -> Result<Profile> {
+        let profile_name = profile.or_else(|| {
+            if character_id.is_none() {
+                self.default_profile.clone()
+            } else {
+                None
+            }
+        });
+
+        match &profile_name {
+            Some(name) => Ok(self.profile(name)?.clone()),
+            None => Ok(Profile::default()),
+        }
+    }
+
+    /// Resolves just the session and character from the command line and
+    /// profile, for commands that don't launch a program
+    pub fn resolve_character(
+        &self,
+        profile: Option<String>,
+        session_name: Option<String>,
+        character_id: Option<String>,
+    ) -> Result<(Option<String>, String)> {
+        let profile = self.select_profile(profile, &character_id)?;
+        let character_id = character_id
+            .or(profile.character_id)
+            .ok_or(AuthError::MissingLaunchOption { option: "--character-id" })?;
+        Ok((session_name.or(profile.session_name), character_id))
+    }
+
+    /// Merges command line options over the selected (or default) profile.
+    /// The default profile is only used when no character was given on the
+    /// command line.
+    pub fn resolve_launch(
+        &self,
+        profile: Option<String>,
+        session_name: Option<String>,
+        character_id: Option<String>,
+        client: ClientOptions,
+    ) -> Result<LaunchTarget> {
+        let profile = self.select_profile(profile, &character_id)?;
+        let game = Game::resolve(client.game.or(profile.game));
+
+        // Arguments belong to the program they were given with, so a program
+        // on the command line also replaces the profile's arguments
+        let (preset, exec, args, sha256) = if client.preset.is_some() || client.exec.is_some() {
+            (client.preset, client.exec, client.args, None)
+        } else {
+            (profile.preset, profile.exec, profile.args, profile.sha256)
+        };
+
+        let (exec, args) = match preset {
+            Some(preset) => preset.command(game, &presets::client_args(exec, args))?,
+            None => (exec.ok_or(AuthError::MissingLaunchOption { option: "<EXEC>" })?, args),
+        };
+
+        Ok(LaunchTarget {
+            session_name: session_name.or(profile.session_name),
+            character_id: character_id
+                .or(profile.character_id)
+                .ok_or(AuthError::MissingLaunchOption { option: "--character-id" })?,
+            exec,
+            args,
+            env: profile.env,
+            sha256,
+        })
+    }
+
+    /// The executable the named profile launches
+    pub fn profile_executable(&self, name: &str) -> Result<String> {
+        let profile = self.profile(name)?;
+        match profile.preset {
+            Some(preset) => {
+                let args = presets::client_args(profile.exec.clone(), profile.args.clone());
+                Ok(preset.command(Game::resolve(profile.game), &args)?.0)
+            }
+            None => profile.exec.clone().ok_or(AuthError::MissingLaunchOption { option: "<EXEC>" }),
+        }
+    }
+}
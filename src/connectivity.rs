@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    client,
+    error::{AuthError, Result},
+};
+
+/// Any page Jagex serves: a response of any status means it can be reached
+const JAGEX_URL: &str = "https://account.jagex.com/";
+/// Answers plain HTTP with an empty 204, which a captive portal can't help
+/// replacing with its sign-in page or a redirect to it
+const PORTAL_CHECK_URL: &str = "http://connectivitycheck.gstatic.com/generate_204";
+/// Long enough for a slow link, short enough not to hold the login up
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether any error in the chain says `text`, as reqwest only tells a
+/// failed connection apart by what hyper and rustls underneath report
+fn caused_by(error: &reqwest::Error, text: &str) -> bool {
+    let mut source: Option<&dyn std::error::Error> = Some(error);
+    while let Some(error) = source {
+        if error.to_string().contains(text) {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// Where the internet gets to when Jagex can't be reached: nowhere, a
+/// captive portal, or the real site
+async fn diagnose(http: &reqwest::Client, jagex_error: reqwest::Error) -> AuthError {
+    let response = match http.get(PORTAL_CHECK_URL).timeout(TIMEOUT).send().await {
+        Ok(response) => response,
+        Err(_) => return AuthError::Offline,
+    };
+
+    if response.status() != reqwest::StatusCode::NO_CONTENT {
+        let portal = response.url().host_str().unwrap_or("The network").to_owned();
+        return AuthError::CaptivePortal {
+            details: format!("{portal} answered a request meant for the internet"),
+        };
+    }
+    // The internet is fine, so a certificate Jagex didn't sign is something
+    // on the network decrypting the traffic
+    if caused_by(&jagex_error, "certificate") {
+        return AuthError::CaptivePortal {
+            details: "Something on the network answered for Jagex with a certificate Jagex didn't sign".to_owned(),
+        };
+    }
+
+    let details = if caused_by(&jagex_error, "dns error") {
+        "The internet works, but Jagex's domains don't resolve".to_owned()
+    } else if jagex_error.is_timeout() {
+        "The internet works, but Jagex didn't answer in time".to_owned()
+    } else {
+        "The internet works, but connecting to Jagex failed".to_owned()
+    };
+    AuthError::JagexUnreachable { details }
+}
+
+/// Checks Jagex can be reached the way the session's requests go, before
+/// a login that would otherwise fail with a window that never loads. Tells
+/// no internet, a captive portal and Jagex alone being unreachable apart.
+pub async fn check(session_name: &Option<String>) -> Result<Duration> {
+    let http = client::http_for(session_name)?;
+    let started = Instant::now();
+    match http.get(JAGEX_URL).timeout(TIMEOUT).send().await {
+        Ok(_) => Ok(started.elapsed()),
+        Err(error) => Err(diagnose(http, error).await),
+    }
+}
@@ -0,0 +1,376 @@
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    process::Command,
+    time::Duration,
+};
+
+use console::style;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::{
+    batch,
+    client::{self, AccountsDiff, Client},
+    config, desktop,
+    error::{AuthError, Result},
+    launch,
+    metrics::Metrics,
+    output, paths,
+    schedule::Schedule,
+    sessions, theme, watch,
+    webhook::{self, Event},
+};
+
+const UNIT_NAME: &str = "auth-rs-daemon.service";
+/// How long `refresh-now` waits for the daemon to answer
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a connection to the daemon gets to send its request, so one that
+/// never does can't hold up the others
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+#[cfg(windows)]
+const TASK_NAME: &str = "auth-rs daemon";
+
+/// Refreshes every known session's character list about each `interval`,
+/// so sessions are kept in use and offline lists stay current. Sessions are
+/// refreshed on the [`Schedule`], which comes back sooner for sessions near
+/// expiry and backs off from failing ones, with those due at once refreshed
+/// `[api] parallel_refreshes` at a time. With `metrics_addr`, the results
+/// are served for Prometheus. With `once`, the sessions due are refreshed a
+/// single time for timers and scheduled tasks, failing with the last error
+/// if any of them failed.
+pub async fn run(interval: Duration, metrics_addr: Option<SocketAddr>, once: bool) -> Result<()> {
+    let metrics = Metrics::default();
+    if let Some(addr) = metrics_addr {
+        metrics.serve(addr)?;
+        output::renderer().status(&format!("Serving metrics on {}", theme::highlight(format!("http://{addr}/metrics"))));
+    }
+
+    // Sessions already reported as expiring, so the webhooks hear it once
+    let mut expiring = BTreeSet::new();
+    let mut schedule = Schedule::load();
+    let mut requests = if once { None } else { Some(listen()?) };
+    loop {
+        let session_names = sessions::list()?;
+        let names = session_names.iter().map(|s| s.clone().unwrap_or_else(|| "default".to_owned())).collect::<Vec<_>>();
+        schedule.retain(&names);
+
+        let due = session_names.into_iter().zip(&names).filter(|(_, name)| schedule.is_due(name));
+        let refreshed = batch::refresh(due.map(|(session_name, _)| session_name).collect(), None).await;
+        let mut failed = None;
+        for refreshed in refreshed {
+            let name = refreshed.name();
+            let result = refreshed.result;
+            metrics.record(&name, refreshed.elapsed, result.as_ref().ok().map(|(accounts, _)| accounts.len()));
+            let client = Client::new(refreshed.session_name);
+
+            let remaining = client.session().ok().and_then(|session| session.remaining());
+            let next = schedule.finished(&name, result.is_ok(), remaining, interval);
+            let clock = watch::clock();
+            let fields = [
+                ("time", clock.clone()),
+                ("session", name.clone()),
+                ("next_in_seconds", next.as_secs().to_string()),
+            ];
+            let next = style(format!("(next in {})", client::format_age(next))).dim();
+
+            let time = style(clock).dim();
+            let succeeded = result.is_ok();
+            match result {
+                Ok((accounts, diff)) => {
+                    let human = format!(
+                        "{time} {} {} {} {next}",
+                        theme::highlight("✓"),
+                        style(&name).bold(),
+                        style(format!("{} characters", accounts.len())).dim()
+                    );
+                    let status = [("status", "ok".to_owned()), ("characters", accounts.len().to_string())];
+                    output::renderer().record(&human, &[&fields[..], &status].concat());
+                    if !diff.is_empty() {
+                        webhook::notify(Event::AccountsChanged, &name, &describe_diff(&name, &diff)).await;
+                    }
+                }
+                Err(error) => {
+                    let human = format!("{time} {} {} {error} {next}", theme::error("✗"), style(&name).bold());
+                    let status = [("status", "failed".to_owned()), ("error", error.to_string())];
+                    output::renderer().record(&human, &[&fields[..], &status].concat());
+                    // An expired session is reported as that, once, rather
+                    // than as a failed refresh each round
+                    if !matches!(error, AuthError::SessionNotFound | AuthError::SessionExpired) {
+                        let message = format!("Refreshing session '{name}' failed: {error}");
+                        webhook::notify(Event::RefreshFailed, &name, &message).await;
+                    } else if expiring.insert(name.clone()) {
+                        let message = format!("Session '{name}' has expired, run 'auth-rs authorize' to log in again");
+                        webhook::notify(Event::SessionExpiring, &name, &message).await;
+                    }
+                    failed = Some(error);
+                }
+            }
+
+            if succeeded {
+                match remaining.filter(|r| r.as_secs() <= config::get().expiry.warn_minutes * 60) {
+                    Some(remaining) if expiring.insert(name.clone()) => {
+                        let message = if remaining.is_zero() {
+                            format!("Session '{name}' has probably expired, run 'auth-rs authorize' to log in again")
+                        } else {
+                            format!("Session '{name}' expires in about {}", client::format_age(remaining))
+                        };
+                        webhook::notify(Event::SessionExpiring, &name, &message).await;
+                    }
+                    Some(_) => (),
+                    None => {
+                        expiring.remove(&name);
+                    }
+                }
+            }
+        }
+
+        if let Err(error) = schedule.save() {
+            output::renderer()
+                .status(&format!("{} Couldn't save the refresh schedule: {error}", theme::warning("Warning:").bold()));
+        }
+        if once {
+            return failed.map_or(Ok(()), Err);
+        }
+        let wait = schedule.until_next(&names, interval);
+        match &mut requests {
+            Some(requests) => {
+                if let Ok(Some(request)) = tokio::time::timeout(wait, requests.recv()).await {
+                    // Everything asked for since is refreshed in the same round
+                    schedule.make_due(request.as_deref());
+                    while let Ok(request) = requests.try_recv() {
+                        schedule.make_due(request.as_deref());
+                    }
+                }
+            }
+            None => tokio::time::sleep(wait).await,
+        }
+    }
+}
+
+/// Where a running daemon can be reached, written to the runtime directory.
+/// The token keeps other users on the machine from sending it requests.
+#[derive(Serialize, Deserialize)]
+struct Endpoint {
+    port: u16,
+    token: String,
+}
+
+impl Endpoint {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::runtime_dir()?.join("daemon.json"))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(path)?;
+        serde_json::to_writer(file, self)?;
+        Ok(())
+    }
+}
+
+/// Answers one `<token> refresh-now [session]` line, queueing the refresh
+fn handle(stream: TcpStream, token: &str, requests: &UnboundedSender<Option<String>>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let mut words = line.split_whitespace();
+
+    let reply = if words.next() != Some(token) {
+        "error invalid token".to_owned()
+    } else if words.next() != Some("refresh-now") {
+        "error unknown request".to_owned()
+    } else {
+        let name = words.next().map(str::to_owned);
+        let known = sessions::list().unwrap_or_default();
+        if name.as_ref().is_some_and(|name| !known.iter().any(|s| s.as_deref().unwrap_or("default") == name)) {
+            format!("error no session named '{}'", name.unwrap_or_default())
+        } else {
+            let _ = requests.send(name);
+            "ok".to_owned()
+        }
+    };
+    writeln!(&stream, "{reply}")
+}
+
+/// Accepts `refresh-now` requests on a loopback port for as long as the
+/// process runs, passing on the session asked for, or `None` for all of them
+fn listen() -> Result<UnboundedReceiver<Option<String>>> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let endpoint = Endpoint { port: listener.local_addr()?.port(), token: uuid::Uuid::new_v4().simple().to_string() };
+    endpoint.save()?;
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            if let Err(e) = stream.and_then(|stream| handle(stream, &endpoint.token, &sender)) {
+                warn!("Daemon requests: {e}");
+            }
+        }
+    });
+    Ok(receiver)
+}
+
+/// Asks the running daemon to refresh the session, or every session, now
+/// rather than on its schedule. Returns once the daemon has queued it.
+pub fn refresh_now(session_name: &Option<String>) -> Result<()> {
+    let endpoint = Endpoint::path()
+        .ok()
+        .and_then(|path| std::fs::File::open(path).ok())
+        .and_then(|file| serde_json::from_reader::<_, Endpoint>(file).ok())
+        .ok_or(AuthError::DaemonNotRunning)?;
+
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, endpoint.port));
+    // A daemon that stopped leaves its endpoint behind
+    let stream = TcpStream::connect_timeout(&addr, REQUEST_TIMEOUT).map_err(|_| AuthError::DaemonNotRunning)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+
+    let name = session_name.as_deref().unwrap_or_default();
+    writeln!(&stream, "{} refresh-now {name}", endpoint.token)?;
+    let mut reply = String::new();
+    BufReader::new(&stream).read_line(&mut reply)?;
+
+    match reply.trim().strip_prefix("error ") {
+        Some(details) => Err(AuthError::DaemonRequestFailed { details: details.to_owned() }),
+        None => Ok(()),
+    }
+}
+
+/// A line about the characters that changed, for the webhooks
+fn describe_diff(name: &str, diff: &AccountsDiff) -> String {
+    let mut changes = vec![];
+    if !diff.added.is_empty() {
+        let names = diff.added.iter().map(|a| a.display_name.as_str()).collect::<Vec<_>>();
+        changes.push(format!("added {}", names.join(", ")));
+    }
+    if !diff.removed.is_empty() {
+        let names = diff.removed.iter().map(|a| a.display_name.as_str()).collect::<Vec<_>>();
+        changes.push(format!("removed {}", names.join(", ")));
+    }
+    for rename in &diff.renamed {
+        changes.push(format!("{} renamed to {}", rename.old_name, rename.new_name));
+    }
+    format!("Characters of session '{name}' changed: {}", changes.join("; "))
+}
+
+/// `daemon run` by the full path of this executable, as the login session's
+/// `$PATH` may not have it
+fn run_command() -> Result<Vec<String>> {
+    let exe = std::env::current_exe()?;
+    Ok(vec![exe.display().to_string(), "daemon".to_owned(), "run".to_owned()])
+}
+
+fn systemctl(args: &[&str]) -> Result<()> {
+    let status = Command::new("systemctl").arg("--user").args(args).status().map_err(|e| AuthError::ExecError {
+        program: "systemctl".to_owned(),
+        details: e.to_string(),
+    })?;
+    if !status.success() {
+        return Err(AuthError::ExecError {
+            program: "systemctl".to_owned(),
+            details: format!("'systemctl --user {}' exited with {status}", args.join(" ")),
+        });
+    }
+    Ok(())
+}
+
+/// Starts the daemon at login, through an XDG autostart entry or, with
+/// `systemd`, a user unit that's enabled straight away. Returns the file written.
+pub fn enable_autostart(systemd: bool) -> Result<PathBuf> {
+    if cfg!(windows) {
+        return Err(AuthError::UnsupportedPlatform {
+            command: "daemon enable-autostart",
+            details: "Windows has no autostart entries, use 'auth-rs daemon install' to register a scheduled task instead",
+        });
+    }
+
+    let config_dir = dirs::config_dir().ok_or(AuthError::NoConfigDir)?;
+    let command = run_command()?;
+
+    if systemd {
+        let path = config_dir.join("systemd").join("user").join(UNIT_NAME);
+        let exec_start = command.iter().map(|arg| launch::shell_quote(arg)).collect::<Vec<_>>().join(" ");
+        let contents = format!(
+            r#"[Unit]
+Description=auth-rs session refresh daemon
+
+[Service]
+ExecStart={exec_start}
+Restart=on-failure
+
+[Install]
+WantedBy=default.target
+"#
+        );
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, contents)?;
+        systemctl(&["daemon-reload"])?;
+        systemctl(&["enable", UNIT_NAME])?;
+        return Ok(path);
+    }
+
+    let path = config_dir.join("autostart").join("auth-rs-daemon.desktop");
+    let exec = command.iter().map(|arg| desktop::quote_exec_arg(arg)).collect::<Vec<_>>().join(" ");
+    let contents = format!(
+        r#"[Desktop Entry]
+Name=auth-rs daemon
+Comment=Keep auth-rs sessions refreshed
+Exec={exec}
+Terminal=false
+NoDisplay=true
+Type=Application
+X-GNOME-Autostart-enabled=true
+"#
+    );
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, contents)?;
+    Ok(path)
+}
+
+/// Registers a Scheduled Task running `daemon run --once` every `interval`
+/// from logon, as Windows has no user services to keep the daemon in
+#[cfg(windows)]
+pub fn install(interval: Duration) -> Result<()> {
+    let mut command = run_command()?;
+    command.push("--once".to_owned());
+    let task = command.iter().map(|arg| format!("\"{arg}\"")).collect::<Vec<_>>().join(" ");
+    let minutes = (interval.as_secs() / 60).clamp(1, 1439).to_string();
+
+    let status = Command::new("schtasks")
+        .args(["/Create", "/F", "/TN", TASK_NAME, "/SC", "MINUTE", "/MO", &minutes, "/TR", &task])
+        .status()
+        .map_err(|e| AuthError::ExecError {
+            program: "schtasks".to_owned(),
+            details: e.to_string(),
+        })?;
+    if !status.success() {
+        return Err(AuthError::ExecError {
+            program: "schtasks".to_owned(),
+            details: format!("Creating the task exited with {status}"),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn install(_interval: Duration) -> Result<()> {
+    Err(AuthError::UnsupportedPlatform {
+        command: "daemon install",
+        details: "Scheduled tasks are Windows only, use 'auth-rs daemon enable-autostart' instead",
+    })
+}
@@ -1,5 +1,114 @@
-use crate::error::{AuthError, Result};
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{avatars, client::{self, Client, Rename}, config, error::{AuthError, Result}, game::Game, launch, paths, presets::{self, Preset}};
+
+/// The `create-desktop-entry` options an entry was made with, kept so
+/// `desktop sync` can make it again from the current config
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EntrySpec {
+    pub session_name: Option<String>,
+    pub name: String,
+    pub character_id: Option<String>,
+    pub game: Option<Game>,
+    #[serde(default)]
+    pub all: bool,
+    #[serde(default)]
+    pub actions: bool,
+    pub preset: Option<Preset>,
+    pub exec: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub terminal: bool,
+    pub terminal_command: Option<String>,
+}
+
+/// An entry auth-rs made, with the files written for it
+#[derive(Serialize, Deserialize)]
+struct ManagedEntry {
+    spec: EntrySpec,
+    files: Vec<PathBuf>,
+}
+
+/// The entries auth-rs made, by name
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    entries: BTreeMap<String, ManagedEntry>,
+}
+
+impl Manifest {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join("desktop-entries.json"))
+    }
+
+    fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+/// What a desktop entry launches: a preset or an executable, with arguments
+#[derive(Debug, Clone)]
+pub enum Program {
+    Preset(Preset, Vec<String>),
+    Exec(String, Vec<String>),
+}
+
+impl Program {
+    pub fn new(preset: Option<Preset>, exec: Option<String>, args: Vec<String>) -> Result<Self> {
+        match (preset, exec) {
+            (Some(preset), exec) => Ok(Program::Preset(preset, presets::client_args(exec, args))),
+            (None, Some(exec)) => Ok(Program::Exec(exec, args)),
+            (None, None) => Err(AuthError::MissingLaunchOption { option: "<EXEC>" }),
+        }
+    }
+}
+
+/// Whether an entry runs in a terminal, so console clients and failures
+/// can be seen
+#[derive(Debug, Clone, Default)]
+pub enum Terminal {
+    #[default]
+    None,
+    /// `Terminal=true`, leaving the choice of emulator to the desktop
+    Desktop,
+    /// Wraps the launch in this terminal emulator command
+    Command(String),
+}
+
+impl Terminal {
+    /// Merges the command line flags over the `[desktop]` config
+    pub fn new(terminal: bool, terminal_command: Option<String>) -> Self {
+        let desktop = &config::get().desktop;
+        match terminal_command.or_else(|| desktop.terminal_command.clone()) {
+            Some(command) => Terminal::Command(command),
+            None if terminal || desktop.terminal => Terminal::Desktop,
+            None => Terminal::None,
+        }
+    }
+}
 
 fn get_applications_dir() -> Result<PathBuf> {
     // Equivalent of "${XDG_DATA_HOME:-$HOME/.local/share}"
@@ -17,10 +126,11 @@ fn get_applications_dir() -> Result<PathBuf> {
 fn build_exec_command(
     session_name: &Option<String>,
     character_id: &str,
-    exec: &str,
-    args: &[String],
+    program: &Program,
+    game: Option<Game>,
+    fingerprint: Option<&str>,
 ) -> String {
-    let mut exec_cmd = vec!["auth-rs".to_string(), "exec".to_string()];
+    let mut exec_cmd = vec!["auth-rs".to_string(), "launch".to_string()];
 
     if let Some(session) = session_name {
         exec_cmd.push("--session-name".to_string());
@@ -29,38 +139,173 @@ fn build_exec_command(
     
     exec_cmd.push("--character-id".to_string());
     exec_cmd.push(character_id.to_string());
-    exec_cmd.push(exec.to_string());
-    
-    if !args.is_empty() {
-        exec_cmd.push("--".to_string());
-        exec_cmd.extend(args.iter().cloned());
+
+    if let Some(value) = game.and_then(|game| game.to_possible_value()) {
+        exec_cmd.push("--game".to_string());
+        exec_cmd.push(value.get_name().to_string());
     }
-    
-    exec_cmd.join(" ")
+
+    // Clicking the entry again raises the running client rather than
+    // logging in a second time
+    exec_cmd.push("--focus-existing".to_string());
+
+    if let Some(fingerprint) = fingerprint {
+        exec_cmd.push("--fingerprint".to_string());
+        exec_cmd.push(fingerprint.to_string());
+    }
+
+    // `--` goes before the program, so nothing after it is read as an
+    // auth-rs option, the same as on the command line
+    match program {
+        Program::Preset(preset, args) => {
+            exec_cmd.push("--preset".to_string());
+            if let Some(value) = preset.to_possible_value() {
+                exec_cmd.push(value.get_name().to_string());
+            }
+            if !args.is_empty() {
+                exec_cmd.push("--".to_string());
+                exec_cmd.extend(args.iter().cloned());
+            }
+        }
+        Program::Exec(exec, args) => {
+            exec_cmd.push("--".to_string());
+            exec_cmd.push(exec.to_string());
+            exec_cmd.extend(launch::with_runelite_profile(character_id, exec, args));
+        }
+    };
+
+    exec_cmd
+        .iter()
+        .map(|arg| quote_exec_arg(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-pub fn create_entry(
+/// Splits an `Exec` value back into the arguments `quote_exec_arg` quoted
+fn split_exec(exec: &str) -> Vec<String> {
+    // The string value's escapes come off before the quoting's
+    let exec = exec.replace("\\\\", "\\");
+    let mut args = vec![];
+    let mut arg: Option<String> = None;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                arg.get_or_insert_default();
+            }
+            '\\' if quoted => arg.get_or_insert_default().extend(chars.next()),
+            ' ' | '\t' if !quoted => args.extend(arg.take()),
+            c => arg.get_or_insert_default().push(c),
+        }
+    }
+    args.extend(arg);
+    args.into_iter().map(|arg| arg.replace("%%", "%")).collect()
+}
+
+/// Quotes an argument for an `Exec` key as the Desktop Entry spec asks, and
+/// escapes `%` so it isn't read as a field code
+pub fn quote_exec_arg(arg: &str) -> String {
+    const RESERVED: &[char] = &[
+        ' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')', '`',
+    ];
+
+    let arg = arg.replace('%', "%%");
+    if !arg.is_empty() && !arg.contains(RESERVED) {
+        return arg;
+    }
+
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    // Backslashes are escaped once more by the string value the key holds
+    quoted.replace('\\', "\\\\")
+}
+
+/// The `Exec` key's value, run in the terminal emulator when there is one
+fn entry_exec(
+    session_name: &Option<String>,
+    character_id: &str,
+    program: &Program,
+    game: Option<Game>,
+    fingerprint: Option<&str>,
+    terminal: &Terminal,
+) -> String {
+    let exec_command = build_exec_command(session_name, character_id, program, game, fingerprint);
+    match terminal {
+        Terminal::Command(command) => format!("{command} {exec_command}"),
+        _ => exec_command,
+    }
+}
+
+pub async fn create_entry(
     session_name: Option<String>,
     name: String,
     character_id: String,
+    program: Program,
+    game: Option<Game>,
+    terminal: &Terminal,
+) -> Result<PathBuf> {
+    // Entries are usually made right after `ls`, so the offline list tells
+    // the account and the display name without asking Jagex
+    let accounts = Client::new(session_name.clone()).cached_accounts();
+    let fingerprint = client::fingerprint(&accounts);
+    let avatar = match accounts.iter().find(|account| account.account_id == character_id) {
+        Some(account) => avatars::fetch(&session_name, account, Game::resolve(game)).await,
+        None => None,
+    };
+    let exec_command = entry_exec(&session_name, &character_id, &program, game, fingerprint.as_deref(), terminal);
+    write_entry(&name, &exec_command, Game::resolve(game), avatar.as_deref(), terminal, &[])
+}
+
+/// A `[Desktop Action]` of an entry, offered in its right-click menu
+struct Action {
+    id: String,
+    name: String,
     exec: String,
-    args: Vec<String>,
+}
+
+/// Writes the entry, with the character's image as its icon when there is
+/// one and the game's otherwise
+fn write_entry(
+    name: &str,
+    exec_command: &str,
+    game: Game,
+    avatar: Option<&Path>,
+    terminal: &Terminal,
+    actions: &[Action],
 ) -> Result<PathBuf> {
     let applications_dir = get_applications_dir()?;
-    let exec_command = build_exec_command(&session_name, &character_id, &exec, &args);
-    // TODO: What to do about the RuneLite references below?
-    let contents = format!(
+    let mut contents = format!(
         r#"[Desktop Entry]
 Name={}
-Comment=Launch RuneLite
+Comment=Launch {}
 Exec={}
-Icon=runelite
-Terminal=false
+Icon={}
+Terminal={}
+StartupNotify=true
 Type=Application
 Categories=Game;
 "#,
-        name, exec_command
+        name,
+        game.title(),
+        exec_command,
+        avatar.map_or_else(|| game.icon().to_owned(), |avatar| avatar.display().to_string()),
+        matches!(terminal, Terminal::Desktop)
     );
+    if !actions.is_empty() {
+        let ids = actions.iter().map(|a| format!("{};", a.id)).collect::<String>();
+        contents.push_str(&format!("Actions={ids}\n"));
+    }
+    for action in actions {
+        contents.push_str(&format!("\n[Desktop Action {}]\nName={}\nExec={}\n", action.id, action.name, action.exec));
+    }
 
     let filename = name
         .chars()
@@ -73,4 +318,164 @@ Categories=Game;
     std::fs::write(&desktop_entry, contents)?;
     
     Ok(desktop_entry)
-}
\ No newline at end of file
+}
+
+/// Creates an entry per character of the session, named "<name> (<display name>)"
+pub async fn create_entries_for_all(
+    session_name: Option<String>,
+    name: String,
+    program: Program,
+    game: Option<Game>,
+    terminal: &Terminal,
+) -> Result<Vec<PathBuf>> {
+    let client = Client::new(session_name.clone());
+    let accounts = client.accounts(false, false).await?;
+    let fingerprint = client::fingerprint(&accounts);
+
+    let mut entries = vec![];
+    for account in &accounts {
+        let avatar = avatars::fetch(&session_name, account, Game::resolve(game)).await;
+        let exec_command =
+            entry_exec(&session_name, &account.account_id, &program, game, fingerprint.as_deref(), terminal);
+        let name = format!("{} ({})", name, account.display_name);
+        entries.push(write_entry(&name, &exec_command, Game::resolve(game), avatar.as_deref(), terminal, &[])?);
+    }
+    Ok(entries)
+}
+
+/// Creates a single entry with a "Launch as <display name>" action per
+/// character of the session. Opening the entry itself launches
+/// `default_character`, or the first character.
+pub async fn create_entry_with_actions(
+    session_name: Option<String>,
+    name: String,
+    default_character: Option<String>,
+    program: Program,
+    game: Option<Game>,
+    terminal: &Terminal,
+) -> Result<PathBuf> {
+    let client = Client::new(session_name.clone());
+    let accounts = client.accounts(false, false).await?;
+    let default_character = match default_character {
+        Some(character_id) => launch::find_account(&accounts, &character_id)?,
+        None => accounts.first().ok_or(AuthError::NoCharacters)?,
+    };
+
+    let fingerprint = client::fingerprint(&accounts);
+    let fingerprint = fingerprint.as_deref();
+
+    let actions = accounts
+        .iter()
+        .map(|account| Action {
+            id: format!("character-{}", account.account_id),
+            name: format!("Launch as {}", account.display_name),
+            exec: entry_exec(&session_name, &account.account_id, &program, game, fingerprint, terminal),
+        })
+        .collect::<Vec<_>>();
+    let exec_command = entry_exec(&session_name, &default_character.account_id, &program, game, fingerprint, terminal);
+    // The entry stands for every character, so it keeps the game's icon
+    write_entry(&name, &exec_command, Game::resolve(game), None, terminal, &actions)
+}
+
+/// Writes the entries `spec` asks for, with the current config and characters
+async fn render(spec: &EntrySpec) -> Result<Vec<PathBuf>> {
+    let program = Program::new(spec.preset, spec.exec.clone(), spec.args.clone())?;
+    let terminal = Terminal::new(spec.terminal, spec.terminal_command.clone());
+    let session_name = spec.session_name.clone();
+    let name = spec.name.clone();
+    let game = spec.game;
+
+    match &spec.character_id {
+        character_id if spec.actions => Ok(vec![
+            create_entry_with_actions(session_name, name, character_id.clone(), program, game, &terminal).await?,
+        ]),
+        Some(character_id) if !spec.all => {
+            Ok(vec![create_entry(session_name, name, character_id.clone(), program, game, &terminal).await?])
+        }
+        _ => create_entries_for_all(session_name, name, program, game, &terminal).await,
+    }
+}
+
+/// Creates the entries `spec` asks for and remembers it for `desktop sync`
+pub async fn create(spec: EntrySpec) -> Result<Vec<PathBuf>> {
+    let files = render(&spec).await?;
+
+    let mut manifest = Manifest::load()?;
+    manifest.entries.insert(spec.name.clone(), ManagedEntry { spec, files: files.clone() });
+    manifest.save()?;
+    Ok(files)
+}
+
+/// Makes every managed entry again, removing files no longer wanted, like
+/// those of a character that's gone. Returns the outcome by entry name.
+pub async fn sync() -> Result<Vec<(String, Result<Vec<PathBuf>>)>> {
+    let mut manifest = Manifest::load()?;
+    let mut results = vec![];
+
+    for (name, entry) in manifest.entries.iter_mut() {
+        let result = render(&entry.spec).await;
+        if let Ok(files) = &result {
+            for old in entry.files.iter().filter(|old| !files.contains(old)) {
+                if old.exists() {
+                    std::fs::remove_file(old)?;
+                }
+            }
+            entry.files = files.clone();
+        }
+        results.push((name.clone(), result));
+    }
+
+    manifest.save()?;
+    Ok(results)
+}
+
+/// Renames the character in the names of its entries, as made by
+/// `create-desktop-entry --all`, and in its `--actions` menu items,
+/// returning the entries updated
+pub fn rename_character(rename: &Rename) -> Result<Vec<PathBuf>> {
+    let applications_dir = get_applications_dir()?;
+    let old_suffix = format!("({})", rename.old_name);
+    let old_action = format!("Launch as {}", rename.old_name);
+    let mut updated = vec![];
+
+    for entry in std::fs::read_dir(applications_dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|e| e != "desktop") {
+            continue;
+        }
+
+        let Ok(contents) = std::fs::read_to_string(&path) else { continue };
+        let ours = contents.lines().filter_map(|l| l.strip_prefix("Exec=")).any(|exec| {
+            exec.contains("auth-rs launch")
+                && split_exec(exec).windows(2).any(|pair| pair[0] == "--character-id" && pair[1] == rename.account_id)
+        });
+        if !ours {
+            continue;
+        }
+
+        let mut changed = false;
+        let contents = contents
+            .lines()
+            .map(|line| match line.strip_prefix("Name=") {
+                Some(name) if name.ends_with(&old_suffix) => {
+                    changed = true;
+                    let base = &name[..name.len() - old_suffix.len()];
+                    format!("Name={base}({})", rename.new_name)
+                }
+                Some(name) if name == old_action => {
+                    changed = true;
+                    format!("Name=Launch as {}", rename.new_name)
+                }
+                _ => line.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        if changed {
+            std::fs::write(&path, contents + "\n")?;
+            updated.push(path);
+        }
+    }
+
+    Ok(updated)
+}
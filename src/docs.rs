@@ -0,0 +1,308 @@
+use std::{io::Write, path::PathBuf};
+
+use clap_mangen::Man;
+use roff::{bold, roman, Roff};
+
+use crate::error::Result;
+
+/// Troubleshooting notes per diagnostic code, one for each of `AuthError`'s
+/// in the same order
+const TROUBLESHOOTING: &[(&str, &str)] = &[
+    (
+        "auth_rs::create_webview",
+        "The login window could not be created. On Linux make sure webkit2gtk 4.1 is installed and a graphical session is running.",
+    ),
+    (
+        "auth_rs::network_error",
+        "Jagex servers could not be reached. Check your internet connection, proxy settings and firewall, then try again. If the ISP's DNS fails to resolve Jagex's domains, set [network] hosts or doh.",
+    ),
+    (
+        "auth_rs::offline",
+        "Neither Jagex nor any other site could be reached. Connect to a network and try again.",
+    ),
+    (
+        "auth_rs::captive_portal",
+        "The network intercepts connections until you sign in, as hotel and public Wi-Fi do. Open an http:// page in a browser, sign in, then try again.",
+    ),
+    (
+        "auth_rs::service_down",
+        "Jagex answered with a server error. Wait for it to recover; set [status] url to see the incident with the error.",
+    ),
+    (
+        "auth_rs::jagex_unreachable",
+        "The internet works but Jagex doesn't. Try again later, or check no firewall, VPN or DNS filter blocks Jagex's domains.",
+    ),
+    (
+        "auth_rs::json_error",
+        "Jagex returned a response auth-rs could not understand. This is usually temporary; report it if it persists.",
+    ),
+    (
+        "auth_rs::filesystem_error",
+        "A file auth-rs keeps could not be read or written. Check the permissions of its config, data and cache directories and that the disk isn't full.",
+    ),
+    (
+        "auth_rs::invalid_url",
+        "A URL from Jagex or the config file could not be parsed. Check URLs set in the config file, such as [status] url or the proxy settings.",
+    ),
+    (
+        "auth_rs::invalid_pkce",
+        "The login's PKCE verifier came out malformed, which is a bug. Try again and report it if it persists.",
+    ),
+    (
+        "auth_rs::token_rejected",
+        "Jagex refused to exchange the login for tokens, usually because the login page was left open too long. Run 'auth-rs authorize' again and finish the login promptly.",
+    ),
+    (
+        "auth_rs::invalid_response",
+        "A redirect or response didn't match the login in progress. Start the login again with 'auth-rs authorize'; report it if it keeps happening.",
+    ),
+    (
+        "auth_rs::not_authenticated",
+        "No session is stored for the selected session name. Run 'auth-rs authorize' (with the same --session-name) to log in.",
+    ),
+    (
+        "auth_rs::session_expired",
+        "The stored session was rejected by Jagex. Run 'auth-rs authorize' to log in again; desktop entries do this automatically.",
+    ),
+    (
+        "auth_rs::authorize_failed",
+        "The authorize window closed before a session was stored. Complete the login and consent pages before closing the window.",
+    ),
+    (
+        "auth_rs::character_not_found",
+        "The character ID does not belong to the session. Run 'auth-rs ls' to list the available IDs.",
+    ),
+    (
+        "auth_rs::invalid_character_id",
+        "The character ID can't name a directory of its own. Use one of the account IDs 'auth-rs ls' lists.",
+    ),
+    (
+        "auth_rs::character_in_other_session",
+        "The character belongs to another stored session. Pass that session with --session-name, or fix the session in the desktop entry or profile and run 'auth-rs desktop sync'.",
+    ),
+    (
+        "auth_rs::character_account_changed",
+        "The character's user hash no longer matches the offline list. If you didn't move it, check your account's security, then run 'auth-rs refresh' to accept the change.",
+    ),
+    (
+        "auth_rs::shortcut_for_other_login",
+        "The session was authorized with a different Jagex account since the desktop entry was made. Run 'auth-rs desktop sync' to recreate the entries, or authorize the original account again.",
+    ),
+    (
+        "auth_rs::exec_error",
+        "The program could not be started. Check it is installed and in $PATH, or pass the full path to the executable.",
+    ),
+    (
+        "auth_rs::keyring_error",
+        "The system credential store rejected the request. Make sure it is unlocked and try again.",
+    ),
+    (
+        "auth_rs::session_file_locked",
+        "The session was saved to a passphrase-protected file because the credential store failed. Run auth-rs in a terminal to enter the passphrase.",
+    ),
+    (
+        "auth_rs::credential_store_error",
+        "No credential store is available. On Linux install and start a Secret Service provider such as gnome-keyring or KWallet.",
+    ),
+    (
+        "auth_rs::keyring_locked",
+        "The keyring is locked or its unlock prompt was dismissed. Unlock it (Seahorse, KWallet Manager or KeePassXC) and try again.",
+    ),
+    (
+        "auth_rs::keyring_no_dbus",
+        "There is no D-Bus session bus to reach the keyring over. Run auth-rs from a desktop session, or wrap it in dbus-run-session.",
+    ),
+    (
+        "auth_rs::keyring_no_provider",
+        "No Secret Service provider is running. Install and start gnome-keyring, or enable Secret Service support in KWallet or KeePassXC.",
+    ),
+    (
+        "auth_rs::keyring_snap_not_connected",
+        "The snap's password-manager-service interface isn't connected. Run 'sudo snap connect auth-rs:password-manager-service'.",
+    ),
+    (
+        "auth_rs::clipboard_error",
+        "The clipboard could not be used. On Linux run auth-rs inside an X11 or Wayland session.",
+    ),
+    (
+        "auth_rs::no_characters",
+        "Jagex returned no characters. Create one in the Jagex Launcher, accept the consent page when authorizing, or check the selected session.",
+    ),
+    (
+        "auth_rs::no_offline_characters",
+        "No character list was saved for --offline. Run 'auth-rs ls --write-cache' or 'auth-rs refresh' while online first.",
+    ),
+    (
+        "auth_rs::offline_cache_stale",
+        "The character list saved for --offline is older than --max-age. Run 'auth-rs refresh' while online to update it.",
+    ),
+    (
+        "auth_rs::no_cache_dir",
+        "No cache directory could be determined. Make sure HOME (or XDG_CACHE_HOME) is set.",
+    ),
+    (
+        "auth_rs::no_config_dir",
+        "No config directory could be determined. Make sure HOME (or XDG_CONFIG_HOME) is set.",
+    ),
+    (
+        "auth_rs::config_error",
+        "The config file could not be parsed. Fix the reported line or run 'auth-rs init' to recreate it.",
+    ),
+    (
+        "auth_rs::tag_empty",
+        "No character or session carries the tag given with --tag. Add it with tags = [...] under [characters.\"<ID>\"] or [sessions.\"<name>\"] in the config file.",
+    ),
+    (
+        "auth_rs::profile_not_found",
+        "The profile named with --profile does not exist in the config file.",
+    ),
+    (
+        "auth_rs::missing_launch_option",
+        "The program to launch or the character wasn't given. Pass it on the command line or select a profile that sets it with --profile.",
+    ),
+    (
+        "auth_rs::java_not_found",
+        "The client needs a newer Java runtime. Install one from your package manager, or point JAVA_HOME or the preset's java option at it.",
+    ),
+    (
+        "auth_rs::preset_not_found",
+        "The client or runtime for the preset wasn't found, or doesn't suit the game. Install it, or set where it is under [presets.<name>] in the config file, as the message says.",
+    ),
+    (
+        "auth_rs::confirmation_required",
+        "The command needs a confirmation that can only be given in a terminal. Run it in one, or pass the flag the message names to go ahead without asking.",
+    ),
+    (
+        "auth_rs::already_running",
+        "The character is already logged in with a running client, and a second login would kick it to the lobby. Close it first, or pass --allow-multiple or --focus-existing.",
+    ),
+    (
+        "auth_rs::daemon_not_running",
+        "'daemon refresh-now' found no running daemon. Start it with 'auth-rs daemon run', or refresh in the foreground with 'auth-rs refresh'.",
+    ),
+    (
+        "auth_rs::daemon_request_failed",
+        "The daemon refused the request, usually for a session it doesn't know. Check the name with 'auth-rs sessions'.",
+    ),
+    (
+        "auth_rs::doctor_failed",
+        "Some of 'auth-rs doctor''s checks failed. Follow the guidance printed under each of them.",
+    ),
+    (
+        "auth_rs::cancelled",
+        "The command was cancelled at a prompt, or the login window was closed with Esc. Nothing was changed.",
+    ),
+    (
+        "auth_rs::launch_restricted",
+        "[restrictions] in the config file don't allow launching the character now. Wait for an allowed time, or pass --override-restrictions and type the passphrase set as override_sha256.",
+    ),
+    (
+        "auth_rs::executable_not_allowed",
+        "The program isn't listed under [policy] allowed_executables. Add its path, directory or \"sha256:<hex>\" digest to the list.",
+    ),
+    (
+        "auth_rs::container_not_allowed",
+        "What runs in a container can't be checked against [policy] allowed_executables. Launch without --container, or remove allowed_executables.",
+    ),
+    (
+        "auth_rs::executable_changed",
+        "The profile's executable no longer matches the SHA-256 pinned for it. If the client was updated on purpose, run 'auth-rs profile pin' again.",
+    ),
+    (
+        "auth_rs::completion_check_failed",
+        "The generated shell completion disagrees with 'auth-rs complete'. Regenerate the script, and report it if the check still fails.",
+    ),
+    (
+        "auth_rs::read_only",
+        "Read-only mode, from --read-only or read_only in the config file, keeps the stored sessions as they are. Run the command on a machine, or with a config, that isn't read-only.",
+    ),
+    (
+        "auth_rs::unsupported_platform",
+        "The command isn't available on this operating system. The message says what to use instead.",
+    ),
+];
+
+/// Troubleshooting section appended to the long `--help` output
+pub fn troubleshooting() -> String {
+    let mut text = String::from("Troubleshooting:");
+    for (code, help) in TROUBLESHOOTING {
+        text.push_str(&format!("\n  {code}\n      {help}"));
+    }
+    text
+}
+
+fn render_page(man: &Man, w: &mut dyn Write, troubleshooting: bool) -> Result<()> {
+    man.render(w)?;
+
+    if troubleshooting {
+        let mut roff = Roff::default();
+        roff.control("SH", ["TROUBLESHOOTING"]);
+        for (code, help) in TROUBLESHOOTING {
+            roff.control("TP", []);
+            roff.text([bold(*code)]);
+            roff.text([roman(*help)]);
+        }
+        roff.to_writer(w)?;
+    }
+
+    Ok(())
+}
+
+fn generate_pages(
+    cmd: clap::Command,
+    out_dir: &std::path::Path,
+    is_root: bool,
+    pages: &mut Vec<PathBuf>,
+) -> Result<()> {
+    for subcommand in cmd.get_subcommands().filter(|s| !s.is_hide_set()).cloned() {
+        generate_pages(subcommand, out_dir, false, pages)?;
+    }
+
+    let man = Man::new(cmd);
+    let path = out_dir.join(man.get_filename());
+    let mut file = std::fs::File::create(&path)?;
+    render_page(&man, &mut file, is_root)?;
+    pages.push(path);
+
+    Ok(())
+}
+
+/// Writes man pages for the command and all its subcommands to `out_dir`,
+/// or just the top-level page to stdout when no directory is given
+pub fn man(cmd: clap::Command, out_dir: Option<PathBuf>) -> Result<Vec<PathBuf>> {
+    // The troubleshooting text gets its own section rather than clap_mangen's EXTRA
+    let mut cmd = cmd
+        .disable_help_subcommand(true)
+        .after_long_help(None::<&'static str>);
+    cmd.build();
+
+    let Some(out_dir) = out_dir else {
+        let man = Man::new(cmd);
+        render_page(&man, &mut std::io::stdout().lock(), true)?;
+        return Ok(vec![]);
+    };
+
+    std::fs::create_dir_all(&out_dir)?;
+    let mut pages = vec![];
+    generate_pages(cmd, &out_dir, true, &mut pages)?;
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_error_code_has_troubleshooting() {
+        let codes = include_str!("error.rs")
+            .split("code(")
+            .skip(1)
+            .filter_map(|rest| rest.split_once(')').map(|(code, _)| code))
+            .filter(|code| code.starts_with("auth_rs::"))
+            .collect::<Vec<_>>();
+        assert!(!codes.is_empty());
+        for code in codes {
+            assert!(TROUBLESHOOTING.iter().any(|(known, _)| *known == code), "{code} has no troubleshooting entry");
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::PathBuf;
+
+use miette::Diagnostic;
+
+use crate::{
+    client::Client,
+    config::Config,
+    connectivity,
+    error::{AuthError, Result},
+    output, paths,
+    sandbox::{self, Sandbox},
+};
+
+/// Outcome of a single probe: a short description on success, or the error
+/// the user would otherwise hit
+struct Check {
+    name: &'static str,
+    result: Result<String>,
+}
+
+fn check_config() -> Check {
+    let result = Config::path().and_then(|path| {
+        Config::load_user()?;
+        Ok(if path.exists() {
+            path.display().to_string()
+        } else {
+            format!("{} (not created yet)", path.display())
+        })
+    });
+    Check { name: "Config file", result }
+}
+
+fn check_system_config() -> Check {
+    let result = match crate::config::system_path() {
+        Some(path) if path.exists() => Config::load().map(|_| path.display().to_string()),
+        Some(path) => Ok(format!("{} (none)", path.display())),
+        None => Ok("none".to_owned()),
+    };
+    let result = match (result, crate::config::extra_system_path()) {
+        (Ok(found), Some(extra)) => Ok(format!("{found}, with {} over it", extra.display())),
+        (result, _) => result,
+    };
+    Check { name: "System config", result }
+}
+
+#[cfg(target_os = "linux")]
+fn check_dbus() -> Check {
+    let result = std::env::var("DBUS_SESSION_BUS_ADDRESS").map_err(|_| AuthError::KeyringNoDbus);
+    Check { name: "D-Bus session", result }
+}
+
+fn check_sandbox(sandbox: Sandbox) -> Check {
+    let details = match sandbox {
+        Sandbox::Flatpak => "sessions sealed with the Secret portal, host programs run with flatpak-spawn --host",
+        Sandbox::Snap => "needs the password-manager-service interface connected",
+    };
+    Check { name: "Sandbox", result: Ok(format!("{} ({details})", sandbox.name())) }
+}
+
+fn check_credential_store() -> Check {
+    let result = Client::probe_credential_store().map(|()| {
+        if sandbox::uses_secret_portal() {
+            return "read OK (XDG Secret portal)".to_owned();
+        }
+        match &crate::config::get().keyring.collection {
+            Some(collection) if cfg!(target_os = "linux") => format!(
+                "read/write OK (service '{}', collection '{collection}')",
+                Client::credential_store_service()
+            ),
+            _ => format!("read/write OK (service '{}')", Client::credential_store_service()),
+        }
+    });
+    Check { name: "Credential store", result }
+}
+
+fn check_session(session_name: &Option<String>) -> Check {
+    let result = Client::new(session_name.clone()).session().map(|_| "stored".to_owned());
+    Check { name: "Session", result }
+}
+
+async fn check_network(session_name: &Option<String>) -> Check {
+    let result = connectivity::check(session_name)
+        .await
+        .map(|latency| format!("Jagex reachable ({} ms)", latency.as_millis()));
+    Check { name: "Network", result }
+}
+
+/// Whether auth-rs can write to one of the directories in [`paths`]
+fn check_dir(name: &'static str, dir: Result<PathBuf>) -> Check {
+    let result = dir
+        .and_then(|dir| {
+            std::fs::create_dir_all(&dir)?;
+            let probe = dir.join(".doctor-probe");
+            std::fs::write(&probe, b"")?;
+            std::fs::remove_file(&probe)?;
+            Ok(dir.display().to_string())
+        });
+    Check { name, result }
+}
+
+fn print_check(check: &Check) {
+    let fields = [("check", check.name.to_owned())];
+    match &check.result {
+        Ok(details) => output::succeeded(check.name, details, &[&fields[..], &[("details", details.clone())]].concat()),
+        Err(error) => {
+            output::failed(check.name, error, &fields);
+            if let Some(help) = error.help() {
+                for line in help.to_string().lines() {
+                    output::renderer().note(&format!("      {line}"));
+                }
+            }
+        }
+    }
+}
+
+/// Probes everything auth-rs depends on and prints targeted guidance for any failures
+pub async fn run(session_name: Option<String>) -> Result<()> {
+    let mut checks = vec![check_config(), check_system_config()];
+    if let Some(sandbox) = sandbox::detect() {
+        checks.push(check_sandbox(sandbox));
+    }
+    #[cfg(target_os = "linux")]
+    checks.push(check_dbus());
+    checks.push(check_credential_store());
+    checks.push(check_session(&session_name));
+    checks.push(check_network(&session_name).await);
+    checks.push(check_dir("Cache directory", paths::cache_dir()));
+    checks.push(check_dir("State directory", paths::state_dir()));
+    checks.push(check_dir("Log directory", paths::log_dir()));
+    checks.push(check_dir("Runtime directory", paths::runtime_dir()));
+
+    for check in &checks {
+        print_check(check);
+    }
+
+    let failed = checks.iter().filter(|c| c.result.is_err()).count();
+    if failed > 0 {
+        return Err(AuthError::DoctorFailed { failed });
+    }
+
+    Ok(())
+}
@@ -1,4 +1,77 @@
 
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, OAuthConfig};
+
 pub static ORIGIN: &str = "https://account.jagex.com";
+/// Where the account's profile, 2FA and linked characters are managed
+pub static ACCOUNT_SETTINGS: &str = "https://account.jagex.com/manage/profile";
 pub static REDIRECT: &str = "https://secure.runescape.com/m=weblogin/launcher-redirect";
-pub static CLIENT_ID: &str = "com_jagex_auth_desktop_launcher";
\ No newline at end of file
+/// Hosts the login step may redirect to with the code, in case Jagex moves
+/// the launcher redirect
+pub static REDIRECT_HOSTS: &[&str] = &["secure.runescape.com", "secure.jagex.com"];
+/// Hosts the consent redirect lands on
+pub static CONSENT_HOSTS: &[&str] = &["localhost", "127.0.0.1", "[::1]"];
+pub static CLIENT_ID: &str = "com_jagex_auth_desktop_launcher";
+pub static SCOPE: &str = "openid offline gamesso.token.create user.profile.read";
+pub static CONSENT_CLIENT_ID: &str = "1fddee4e-b100-4f4e-b2b0-097f9088f9d2";
+pub static CONSENT_REDIRECT: &str = "http://localhost";
+pub static CONSENT_SCOPE: &str = "openid offline";
+/// PKCE code verifier length the launcher uses
+pub static PKCE_VERIFIER_LENGTH: usize = 43;
+/// Minutes a login page is trusted to redirect with a usable code
+pub static FLOW_TIMEOUT_MINUTES: u64 = 10;
+
+/// Client IDs and scopes of the login and consent steps
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OAuth {
+    pub client_id: String,
+    pub scope: String,
+    pub consent_client_id: String,
+    pub consent_scope: String,
+    /// Where the consent step redirects to, replaced by a local listener's
+    /// address when one is used
+    pub consent_redirect: String,
+    pub consent_listener: bool,
+    pub pkce_verifier_length: usize,
+    /// How long after the login page opened its redirect is still accepted
+    pub flow_timeout: Duration,
+}
+
+/// The IDs and scopes for a session. Each comes from its `AUTH_RS_*`
+/// variable, then `[sessions.<name>.oauth]`, then `[oauth]`, before falling
+/// back to the launcher's own.
+pub fn oauth(session_name: &Option<String>) -> OAuth {
+    let config = config::get();
+    let session = config.session(session_name).map(|s| &s.oauth);
+    let pick = |var: &str, field: fn(&OAuthConfig) -> &Option<String>, default: &str| {
+        std::env::var(var)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .or_else(|| session.and_then(|s| field(s).clone()))
+            .or_else(|| field(&config.oauth).clone())
+            .unwrap_or_else(|| default.to_owned())
+    };
+
+    OAuth {
+        client_id: pick("AUTH_RS_CLIENT_ID", |o| &o.client_id, CLIENT_ID),
+        scope: pick("AUTH_RS_SCOPE", |o| &o.scope, SCOPE),
+        consent_client_id: pick("AUTH_RS_CONSENT_CLIENT_ID", |o| &o.consent_client_id, CONSENT_CLIENT_ID),
+        consent_scope: pick("AUTH_RS_CONSENT_SCOPE", |o| &o.consent_scope, CONSENT_SCOPE),
+        consent_redirect: CONSENT_REDIRECT.to_owned(),
+        consent_listener: config.oauth.consent_listener || session.is_some_and(|s| s.consent_listener),
+        pkce_verifier_length: session
+            .and_then(|s| s.pkce_verifier_length)
+            .or(config.oauth.pkce_verifier_length)
+            .unwrap_or(PKCE_VERIFIER_LENGTH),
+        flow_timeout: Duration::from_secs(
+            session
+                .and_then(|s| s.flow_timeout_minutes)
+                .or(config.oauth.flow_timeout_minutes)
+                .unwrap_or(FLOW_TIMEOUT_MINUTES)
+                * 60,
+        ),
+    }
+}
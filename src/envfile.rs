@@ -0,0 +1,55 @@
+use std::{path::Path, time::Duration};
+
+use crate::{error::{AuthError, Result}, launch};
+
+/// Formats the variables as `KEY=value` lines, which both dotenv loaders and
+/// `docker run --env-file` read without quoting
+pub fn dotenv(vars: &[(&str, String)]) -> Result<String> {
+    let mut text = String::new();
+    for (key, value) in vars {
+        if value.contains(['\n', '\r']) {
+            return Err(AuthError::InvalidResponse(format!("{key} contains a line break")));
+        }
+        text.push_str(&format!("{key}={value}\n"));
+    }
+    Ok(text)
+}
+
+/// Writes the env file readable by the current user only
+pub fn write(path: &Path, vars: &[(&str, String)]) -> Result<()> {
+    let contents = dotenv(vars)?;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to a new file, so one already there is locked
+    // down before it's emptied and written
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.set_len(0)?;
+    std::io::Write::write_all(&mut file, contents.as_bytes())?;
+
+    Ok(())
+}
+
+/// Removes the env file after `seconds` from a detached `auth-rs` process,
+/// so the caller is free to exit straight away
+pub fn schedule_removal(path: &Path, seconds: u64) -> Result<()> {
+    let path = std::path::absolute(path)?;
+    launch::spawn_helper([
+        "remove-env-file".as_ref(),
+        "--after".as_ref(),
+        seconds.to_string().as_ref(),
+        path.as_os_str(),
+    ])
+}
+
+pub fn remove_after(path: &Path, seconds: u64) -> Result<()> {
+    std::thread::sleep(Duration::from_secs(seconds));
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
@@ -1,6 +1,17 @@
 use miette::Diagnostic;
+use serde::Serialize;
 use thiserror::Error;
 
+use crate::{output, sandbox};
+
+/// A character that could have been meant, listed when the one asked for
+/// isn't found
+#[derive(Debug, Clone, Serialize)]
+pub struct AvailableCharacter {
+    pub account_id: String,
+    pub display_name: String,
+}
+
 #[derive(Debug, Error, Diagnostic)]
 pub enum AuthError {
     #[error("Failed to create webview")]
@@ -13,9 +24,43 @@ pub enum AuthError {
     #[error("Unable to connect to Jagex servers")]
     #[diagnostic(
         code(auth_rs::network_error),
-        help("• Check your internet connection\n• Try again in a few moments")
+        help("• Check your internet connection\n• Try again in a few moments\n• If Jagex's domains don't resolve reliably, set [network] hosts or doh in the config file")
     )]
     NetworkError(#[from] reqwest::Error),
+
+    #[error("There's no internet connection")]
+    #[diagnostic(
+        code(auth_rs::offline),
+        help("Neither Jagex nor any other site could be reached. Check the Wi-Fi is joined or the cable plugged in, then try again")
+    )]
+    Offline,
+
+    #[error("The network wants you to sign in before it lets you online")]
+    #[diagnostic(
+        code(auth_rs::captive_portal),
+        help("{details}, as hotel and public Wi-Fi do until you sign in or accept their terms. Open any http:// page in a browser, sign in there, then try again")
+    )]
+    CaptivePortal {
+        details: String,
+    },
+
+    #[error("Jagex login services appear to be down")]
+    #[diagnostic(
+        code(auth_rs::service_down),
+        help("{summary}\n\nThere's nothing to fix on your side; try again once it's resolved")
+    )]
+    ServiceDown {
+        summary: String,
+    },
+
+    #[error("Jagex can't be reached")]
+    #[diagnostic(
+        code(auth_rs::jagex_unreachable),
+        help("{details}.\n• Jagex may be down for a while; try again later\n• A firewall, VPN or DNS filter may block Jagex; [network] hosts or doh get past a resolver that won't resolve its domains")
+    )]
+    JagexUnreachable {
+        details: String,
+    },
     
     #[error("Invalid response from server")]
     #[diagnostic(
@@ -35,6 +80,26 @@ pub enum AuthError {
     #[diagnostic(code(auth_rs::invalid_url))]
     InvalidUrl(#[from] url::ParseError),
     
+    #[error("The PKCE parameters of the login are broken")]
+    #[diagnostic(
+        code(auth_rs::invalid_pkce),
+        help("{details}. Please report this bug")
+    )]
+    InvalidPkce {
+        details: String,
+    },
+
+    #[error("{details}")]
+    #[diagnostic(
+        code(auth_rs::token_rejected),
+        help("{hint}\n\nJagex said: {description}")
+    )]
+    TokenRejected {
+        details: &'static str,
+        description: String,
+        hint: &'static str,
+    },
+
     #[error("Unexpected response from authentication server")]
     #[diagnostic(
         code(auth_rs::invalid_response),
@@ -48,17 +113,69 @@ pub enum AuthError {
         help("Run 'auth-rs authorize' to log in with your Jagex account")
     )]
     SessionNotFound,
+
+    #[error("Session expired")]
+    #[diagnostic(
+        code(auth_rs::session_expired),
+        help("Run 'auth-rs authorize' to log in with your Jagex account again")
+    )]
+    SessionExpired,
+
+    #[error("Authorization did not complete")]
+    #[diagnostic(
+        code(auth_rs::authorize_failed),
+        help("The authorize window was closed before a session was stored, please try again")
+    )]
+    AuthorizeFailed,
     
     #[error("Character '{character_id}' not found")]
     #[diagnostic(
         code(auth_rs::character_not_found),
-        help("Available characters:\n{available_chars}\n\nUse one of the account IDs listed above with the --character-id option")
+        help("Available characters:\n{}\n\nUse one of the account IDs listed above with the --character-id option", output::available_characters(available))
     )]
     CharacterNotFound {
         character_id: String,
-        available_chars: String,
+        available: Vec<AvailableCharacter>,
+    },
+
+    #[error("'{character_id}' isn't a valid character ID")]
+    #[diagnostic(
+        code(auth_rs::invalid_character_id),
+        help("Character IDs are the account IDs 'auth-rs ls' lists, and can't contain path separators or '..'")
+    )]
+    InvalidCharacterId {
+        character_id: String,
     },
     
+    #[error("Character '{name}' belongs to session '{session}', not '{current}'")]
+    #[diagnostic(
+        code(auth_rs::character_in_other_session),
+        help("Launch it with '--session-name {session}'. If a desktop entry or profile points it at '{current}', fix the session there and run 'auth-rs desktop sync'")
+    )]
+    CharacterInOtherSession {
+        name: String,
+        session: String,
+        current: String,
+    },
+
+    #[error("Character '{name}' no longer belongs to the account it was saved for")]
+    #[diagnostic(
+        code(auth_rs::character_account_changed),
+        help("Its user hash differs from the offline list's. If you didn't move it yourself, check your account's security, then run 'auth-rs refresh' to accept the change")
+    )]
+    CharacterAccountChanged {
+        name: String,
+    },
+
+    #[error("This shortcut was created for a different login to session '{session}'")]
+    #[diagnostic(
+        code(auth_rs::shortcut_for_other_login),
+        help("The session has been authorized with another Jagex account since. Run 'auth-rs desktop sync' to recreate the shortcuts for this one, or authorize the original account again")
+    )]
+    ShortcutForOtherLogin {
+        session: String,
+    },
+
     #[error("Failed to launch program '{program}'")]
     #[diagnostic(
         code(auth_rs::exec_error),
@@ -76,6 +193,12 @@ pub enum AuthError {
     )]
     KeyringError(String),
     
+    #[error("The session is saved to a file encrypted with a passphrase")]
+    #[diagnostic(code(auth_rs::session_file_locked), help("{details}"))]
+    SessionFileLocked {
+        details: String,
+    },
+
     #[error("Credential store unavailable")]
     #[diagnostic(
         code(auth_rs::credential_store_error),
@@ -83,24 +206,337 @@ pub enum AuthError {
     )]
     CredentialStoreError(String),
 
+    #[error("Credential store is locked")]
+    #[diagnostic(
+        code(auth_rs::keyring_locked),
+        help("Unlock your keyring and try again:\n• GNOME: open 'Passwords and Keys' (Seahorse) and unlock the Login keyring\n• KDE: open KWallet Manager and open the wallet\n• KeePassXC: unlock the database exposed over Secret Service")
+    )]
+    KeyringLocked(String),
+
+    #[error("No D-Bus session found")]
+    #[diagnostic(
+        code(auth_rs::keyring_no_dbus),
+        help("The credential store is reached over the D-Bus session bus, but DBUS_SESSION_BUS_ADDRESS isn't set:\n• Run auth-rs from your desktop session rather than a bare TTY or SSH login\n• Or start one for this command: dbus-run-session -- auth-rs ...")
+    )]
+    KeyringNoDbus,
+
+    #[error("No Secret Service provider found")]
+    #[diagnostic(
+        code(auth_rs::keyring_no_provider),
+        help("Nothing is providing org.freedesktop.secrets on the session bus:\n• Install and start gnome-keyring (gnome-keyring-daemon --start --components=secrets)\n• Or enable KWallet's Secret Service support\n• Or enable Secret Service integration in KeePassXC")
+    )]
+    KeyringNoProvider(String),
+
+    #[error("The snap can't reach the credential store")]
+    #[diagnostic(
+        code(auth_rs::keyring_snap_not_connected),
+        help("Snaps reach the Secret Service through the password-manager-service interface, which isn't connected automatically:\n• sudo snap connect {snap}:password-manager-service")
+    )]
+    KeyringSnapNotConnected {
+        snap: String,
+    },
+
+    #[error("Failed to access the clipboard")]
+    #[diagnostic(
+        code(auth_rs::clipboard_error),
+        help("{0}\n\nOn Linux a graphical session (X11 or Wayland) is needed to use the clipboard")
+    )]
+    ClipboardError(String),
+
+    #[error("No characters were found for this Jagex account")]
+    #[diagnostic(
+        code(auth_rs::no_characters),
+        help("Jagex returned an empty character list. This usually means one of:\n  \
+              • the account has no game characters yet; create one in the Jagex Launcher or on the game's website\n  \
+              • the consent page wasn't accepted, so the session can't see them; run 'auth-rs authorize' again\n  \
+              • another session was meant; check --session-name against 'auth-rs sessions'")
+    )]
+    NoCharacters,
+
+    #[error("No offline character list is saved for this session")]
+    #[diagnostic(
+        code(auth_rs::no_offline_characters),
+        help("Run 'auth-rs ls --write-cache' or 'auth-rs refresh' while online to save one, or leave out --offline")
+    )]
+    NoOfflineCharacters,
+
+    #[error("The offline character list is {age} old")]
+    #[diagnostic(
+        code(auth_rs::offline_cache_stale),
+        help("It's older than --max-age ({max_age}). Run 'auth-rs refresh' or leave out --offline to fetch the characters again")
+    )]
+    OfflineCacheStale { age: String, max_age: String },
+
     #[error("No cache directory unavailable")]
     #[diagnostic(
         code(auth_rs::no_cache_dir),
         help("Please try again or report this bug if it persists")
     )]
     NoCacheDir,
+
+    #[error("No config directory available")]
+    #[diagnostic(
+        code(auth_rs::no_config_dir),
+        help("Please try again or report this bug if it persists")
+    )]
+    NoConfigDir,
+
+    #[error("Invalid config file '{path}'")]
+    #[diagnostic(
+        code(auth_rs::config_error),
+        help("{details}\n\nFix the file by hand or run 'auth-rs init' to recreate it")
+    )]
+    ConfigError {
+        path: String,
+        details: String,
+    },
+
+    #[error("No characters tagged '{tag}'")]
+    #[diagnostic(
+        code(auth_rs::tag_empty),
+        help("Tag characters with [characters.\"<ID>\"] tags = [\"{tag}\"] or whole sessions with [sessions.\"<name>\"] tags = [\"{tag}\"] in the config file")
+    )]
+    TagEmpty {
+        tag: String,
+    },
+
+    #[error("Profile '{name}' not found")]
+    #[diagnostic(
+        code(auth_rs::profile_not_found),
+        help("Check the profiles in your config file or run 'auth-rs init' to create one")
+    )]
+    ProfileNotFound {
+        name: String,
+    },
+
+    #[error("Missing required option '{option}'")]
+    #[diagnostic(
+        code(auth_rs::missing_launch_option),
+        help("Pass '{option}' on the command line, or select a profile that sets it with --profile")
+    )]
+    MissingLaunchOption {
+        option: &'static str,
+    },
+
+    #[error("No suitable Java runtime found")]
+    #[diagnostic(
+        code(auth_rs::java_not_found),
+        help("Java {min_version} or newer is required (found: {found})\n• Install a JRE from your package manager\n• Or set JAVA_HOME, or the preset's java option in the config file")
+    )]
+    JavaNotFound {
+        min_version: u32,
+        found: String,
+    },
+
+    #[error("Client for preset '{preset}' not found")]
+    #[diagnostic(
+        code(auth_rs::preset_not_found),
+        help("{details}")
+    )]
+    PresetNotFound {
+        preset: &'static str,
+        details: String,
+    },
+
+    #[error("Confirmation required")]
+    #[diagnostic(
+        code(auth_rs::confirmation_required),
+        help("{details}")
+    )]
+    ConfirmationRequired {
+        details: String,
+    },
+
+    #[error("{name} is already running (PID {pid})")]
+    #[diagnostic(
+        code(auth_rs::already_running),
+        help("Logging in twice kicks the running client to the lobby. Close it first, or pass --allow-multiple to launch anyway")
+    )]
+    AlreadyRunning {
+        name: String,
+        pid: u32,
+    },
+
+    #[error("No daemon is running")]
+    #[diagnostic(
+        code(auth_rs::daemon_not_running),
+        help("Start it with 'auth-rs daemon run', or refresh in the foreground with 'auth-rs refresh'")
+    )]
+    DaemonNotRunning,
+
+    #[error("The daemon refused the request: {details}")]
+    #[diagnostic(
+        code(auth_rs::daemon_request_failed),
+        help("Check the session name with 'auth-rs sessions'")
+    )]
+    DaemonRequestFailed {
+        details: String,
+    },
+
+    #[error("{failed} check(s) failed")]
+    #[diagnostic(
+        code(auth_rs::doctor_failed),
+        help("See the guidance printed for each failed check above")
+    )]
+    DoctorFailed {
+        failed: usize,
+    },
+
+    #[error("Cancelled")]
+    #[diagnostic(code(auth_rs::cancelled))]
+    Cancelled,
+
+    #[error("{reason}")]
+    #[diagnostic(
+        code(auth_rs::launch_restricted),
+        help("Set by [restrictions] in the config file. Pass --override-restrictions to launch anyway after typing the override passphrase, if one is set")
+    )]
+    LaunchRestricted {
+        reason: String,
+    },
+
+    #[error("'{program}' isn't allowed to be launched with the session")]
+    #[diagnostic(
+        code(auth_rs::executable_not_allowed),
+        help("Only the executables under [policy] allowed_executables in the config file may be launched. Add its path, or its hash as \"sha256:<hex>\"")
+    )]
+    ExecutableNotAllowed {
+        program: String,
+    },
+
+    #[error("Can't launch the container image '{image}' under [policy] allowed_executables")]
+    #[diagnostic(
+        code(auth_rs::container_not_allowed),
+        help("What runs inside a container can't be checked against the allowed executables. Launch the client without --container, or remove allowed_executables from the config file")
+    )]
+    ContainerNotAllowed {
+        image: String,
+    },
+
+    #[error("'{program}' doesn't match its pinned SHA-256")]
+    #[diagnostic(
+        code(auth_rs::executable_changed),
+        help("Expected {expected}, found {actual}. If the client was updated on purpose, run 'auth-rs profile pin' again; otherwise check where the change came from")
+    )]
+    ExecutableChanged {
+        program: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("{failed} of {total} command lines didn't complete as expected in {shell}")]
+    #[diagnostic(
+        code(auth_rs::completion_check_failed),
+        help("The generated {shell} script disagrees with 'auth-rs complete'. Check the script still matches the command line definitions")
+    )]
+    CompletionCheckFailed {
+        shell: &'static str,
+        failed: usize,
+        total: usize,
+    },
+
+    #[error("'{command}' is disabled in read-only mode")]
+    #[diagnostic(
+        code(auth_rs::read_only),
+        help("This machine's sessions are kept as they are, by --read-only or read_only in the config file. Only commands using the stored sessions, like 'ls' and 'exec', work")
+    )]
+    ReadOnly {
+        command: &'static str,
+    },
+
+    #[error("'{command}' isn't available on this platform")]
+    #[diagnostic(
+        code(auth_rs::unsupported_platform),
+        help("{details}")
+    )]
+    UnsupportedPlatform {
+        command: &'static str,
+        details: &'static str,
+    },
 }
 
 
 
+impl AuthError {
+    /// Whether the error came from the system credential store
+    pub fn is_credential_store_error(&self) -> bool {
+        matches!(
+            self,
+            AuthError::KeyringError(_)
+                | AuthError::CredentialStoreError(_)
+                | AuthError::KeyringLocked(_)
+                | AuthError::KeyringNoDbus
+                | AuthError::KeyringNoProvider(_)
+                | AuthError::KeyringSnapNotConnected { .. }
+        )
+    }
+}
+
+fn snap_not_connected() -> Option<AuthError> {
+    (sandbox::detect() == Some(sandbox::Sandbox::Snap)).then(|| AuthError::KeyringSnapNotConnected {
+        snap: std::env::var("SNAP_NAME").unwrap_or_else(|_| "auth-rs".to_owned()),
+    })
+}
+
+/// Picks out the Secret Service conditions that have a specific fix from the
+/// platform error's message, as keyring only passes it on boxed
+fn classify_keyring_failure(details: String) -> AuthError {
+    let message = details.to_lowercase();
+
+    if message.contains("locked") || message.contains("prompt was dismissed") {
+        return AuthError::KeyringLocked(details);
+    }
+
+    if message.contains("no dbus session or secret service provider")
+        || message.contains("serviceunknown")
+        || message.contains("org.freedesktop.secrets")
+    {
+        if cfg!(target_os = "linux") && std::env::var_os("DBUS_SESSION_BUS_ADDRESS").is_none() {
+            return AuthError::KeyringNoDbus;
+        }
+        if let Some(error) = snap_not_connected() {
+            return error;
+        }
+        return AuthError::KeyringNoProvider(details);
+    }
+
+    // AppArmor rejects the messages outright while the interface is unplugged
+    if message.contains("apparmor") || message.contains("accessdenied") {
+        if let Some(error) = snap_not_connected() {
+            return error;
+        }
+    }
+
+    if message.contains("dbus_session_bus_address") || message.contains("failed to connect to socket") {
+        return AuthError::KeyringNoDbus;
+    }
+
+    AuthError::CredentialStoreError(details)
+}
+
 impl From<keyring::Error> for AuthError {
     fn from(error: keyring::Error) -> Self {
         match error {
             keyring::Error::NoEntry => AuthError::SessionNotFound,
-            keyring::Error::PlatformFailure(e) => AuthError::CredentialStoreError(e.to_string()),
+            keyring::Error::PlatformFailure(e) => classify_keyring_failure(e.to_string()),
+            keyring::Error::NoStorageAccess(e) => classify_keyring_failure(e.to_string()),
             _ => AuthError::KeyringError(error.to_string()),
         }
     }
 }
 
+impl From<arboard::Error> for AuthError {
+    fn from(error: arboard::Error) -> Self {
+        AuthError::ClipboardError(error.to_string())
+    }
+}
+
+impl From<dialoguer::Error> for AuthError {
+    fn from(error: dialoguer::Error) -> Self {
+        match error {
+            dialoguer::Error::IO(e) => AuthError::FileSystemError(e),
+        }
+    }
+}
+
 pub type Result<T> = miette::Result<T, AuthError>;
\ No newline at end of file
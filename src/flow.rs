@@ -0,0 +1,495 @@
+use std::time::{Duration, SystemTime};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    client::Client,
+    config::Config,
+    env::OAuth,
+    error::{AuthError, Result},
+    output,
+    progress::{self, Event as Progress},
+    theme,
+};
+
+/// PKCE parameters and state of a login page
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuthOptions {
+    state: String,
+    challenge: String,
+    /// Secret until the code is exchanged
+    verifier: String,
+    /// When the login page was opened with these. Wall-clock time, as
+    /// monotonic clocks stop while a laptop sleeps.
+    issued_at: SystemTime,
+}
+
+/// Verifier lengths RFC 7636 allows
+const PKCE_VERIFIER_LENGTHS: std::ops::RangeInclusive<usize> = 43..=128;
+
+/// Fails unless the challenge is the S256 of the verifier, and the verifier
+/// only uses the characters RFC 7636 allows, so a bad pair is caught before
+/// the login rather than as a rejected token exchange after it
+fn verify_pkce(verifier: &[u8], challenge: &str) -> Result<()> {
+    let unreserved = |c: &u8| c.is_ascii_alphanumeric() || b"-._~".contains(c);
+    if !verifier.iter().all(unreserved) {
+        return Err(AuthError::InvalidPkce { details: "The code verifier has characters RFC 7636 doesn't allow".to_owned() });
+    }
+
+    let digest = ring::digest::digest(&ring::digest::SHA256, verifier);
+    if URL_SAFE_NO_PAD.encode(digest) != challenge {
+        return Err(AuthError::InvalidPkce { details: "The code challenge isn't the S256 of the verifier".to_owned() });
+    }
+    Ok(())
+}
+
+impl AuthOptions {
+    fn new(verifier_length: usize) -> Result<Self> {
+        if !PKCE_VERIFIER_LENGTHS.contains(&verifier_length) {
+            return Err(AuthError::ConfigError {
+                path: Config::path().map(|p| p.display().to_string()).unwrap_or_default(),
+                details: format!(
+                    "pkce_verifier_length must be between {} and {} characters (RFC 7636), not {verifier_length}",
+                    PKCE_VERIFIER_LENGTHS.start(),
+                    PKCE_VERIFIER_LENGTHS.end()
+                ),
+            });
+        }
+
+        let state = Uuid::new_v4();
+        let code_verify = pkce::code_verifier(verifier_length);
+        let code_challenge = pkce::code_challenge(&code_verify);
+        verify_pkce(&code_verify, &code_challenge)?;
+        let verifier = String::from_utf8(code_verify)
+            .map_err(|e| AuthError::InvalidResponse(format!("Invalid UTF-8 in code verifier: {e}")))?;
+
+        Ok(Self {
+            state: state.to_string(),
+            challenge: code_challenge,
+            verifier,
+            issued_at: SystemTime::now(),
+        })
+    }
+
+    /// Whether a redirect made with these is too old to trust. A clock set
+    /// back counts as expired too.
+    fn is_expired(&self, timeout: Duration) -> bool {
+        self.issued_at.elapsed().map_or(true, |age| age > timeout)
+    }
+}
+
+/// Consent step awaiting its redirect, with the time and lifetime of the
+/// tokens it was started from
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PendingConsent {
+    state: String,
+    issued_at: SystemTime,
+    expires_in: Duration,
+}
+
+/// A redirect a driver caught, from the login page or the consent page
+#[derive(Debug, Clone)]
+pub enum Redirect {
+    Auth {
+        code: String,
+        state: String,
+    },
+    Consent {
+        id_token: String,
+        state: String,
+    }
+}
+
+pub fn parse_redirect(url: &str) -> Option<Redirect> {
+    let parsed_url = Url::parse(url).ok()?;
+    try_parse_auth_redirect(&parsed_url).or_else(|| try_parse_consent_redirect(&parsed_url))
+}
+
+/// A non-empty parameter from the query or, as the consent step sends them,
+/// the fragment. Values come back percent-decoded.
+fn redirect_param(url: &Url, name: &str) -> Option<String> {
+    let fragment = url.fragment().unwrap_or_default();
+    url.query_pairs()
+        .chain(url::form_urlencoded::parse(fragment.as_bytes()))
+        .find(|(key, value)| key == name && !value.is_empty())
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Compares paths ignoring percent-encoding and a trailing slash
+fn same_path(a: &str, b: &str) -> bool {
+    let normalize = |path: &str| percent_encoding::percent_decode_str(path).decode_utf8_lossy().trim_end_matches('/').to_owned();
+    normalize(a) == normalize(b)
+}
+
+fn try_parse_auth_redirect(url: &Url) -> Option<Redirect> {
+    if url.scheme() != "https" {
+        return None;
+    }
+
+    // Hosts are lowercased when the URL is parsed
+    let host = url.host_str()?;
+    if !crate::env::REDIRECT_HOSTS.contains(&host) {
+        return None;
+    }
+
+    let redirect = Url::parse(crate::env::REDIRECT).ok()?;
+    if !same_path(url.path(), redirect.path()) {
+        return None;
+    }
+
+    Some(Redirect::Auth {
+        code: redirect_param(url, "code")?,
+        state: redirect_param(url, "state")?,
+    })
+}
+
+fn try_parse_consent_redirect(url: &Url) -> Option<Redirect> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+
+    let host = url.host_str()?;
+    if !crate::env::CONSENT_HOSTS.contains(&host) {
+        return None;
+    }
+
+    Some(Redirect::Consent {
+        id_token: redirect_param(url, "id_token")?,
+        state: redirect_param(url, "state")?,
+    })
+}
+
+fn create_auth_url(oauth: &OAuth, email: Option<&str>) -> Result<(String, AuthOptions)> {
+    let auth_options = AuthOptions::new(oauth.pkce_verifier_length)?;
+    let mut url = Url::parse(crate::env::ORIGIN)?
+        .join("/oauth2/auth")?;
+    let mut query = url.query_pairs_mut();
+    query.append_pair("flow", "launcher");
+    query.append_pair("response_type", "code");
+    query.append_pair("client_id", &oauth.client_id);
+    query.append_pair("redirect_uri", crate::env::REDIRECT);
+    query.append_pair("code_challenge", &auth_options.challenge);
+    query.append_pair("code_challenge_method", "S256");
+    query.append_pair("prompt", "login");
+    query.append_pair("scope", &oauth.scope);
+    if let Some(email) = email {
+        query.append_pair("login_hint", email);
+    }
+    query.append_pair("state", &auth_options.state);
+    drop(query);
+
+    Ok((url.as_str().to_owned(), auth_options))
+}
+
+fn create_consent_url(oauth: &OAuth, id_token: &str) -> Result<(String, String)> {
+    let state = Uuid::new_v4().to_string();
+    let nonce = Uuid::new_v4().to_string();
+    let mut url = Url::parse(crate::env::ORIGIN)?
+        .join("/oauth2/auth")?;
+    let mut query = url.query_pairs_mut();
+    query.append_pair("id_token_hint", id_token);
+    query.append_pair("nonce", &nonce);
+    query.append_pair("prompt", "consent");
+    query.append_pair("response_type", "id_token code");
+    query.append_pair("client_id", &oauth.consent_client_id);
+    query.append_pair("redirect_uri", &oauth.consent_redirect);
+    query.append_pair("scope", &oauth.consent_scope);
+    query.append_pair("state", &state);
+    drop(query);
+
+    Ok((url.as_str().to_owned(), state))
+}
+
+/// Where a login is at
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "step", rename_all = "kebab-case")]
+pub enum FlowState {
+    /// Not started yet
+    New,
+    /// The login page is open, waiting for its redirect with the code
+    AwaitingCode(AuthOptions),
+    /// The code was exchanged for tokens and the consent page is open,
+    /// waiting for its redirect with the ID token
+    AwaitingConsent(PendingConsent),
+    /// The session is stored
+    Done {
+        /// When the tokens behind the session expire
+        expires_at: SystemTime,
+    },
+}
+
+/// What the driver does next
+#[derive(Debug)]
+pub enum Step {
+    /// Show this page
+    Open(String),
+    /// The session is stored, with the tokens behind it expiring then
+    Done { expires_at: SystemTime },
+}
+
+/// An authorize sequence: the login page, its code exchanged for tokens, the
+/// consent page, and its ID token exchanged for a session. Drivers show the
+/// pages and hand over the redirects they catch, wherever they catch them,
+/// and the flow checks each against where it's at. It serializes with its
+/// state, so a driver can keep it and carry on from there later. That
+/// includes the PKCE verifier, which with a stolen code is enough to log in,
+/// so a serialized flow is a secret and must be kept like the session.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuthFlow {
+    session_name: Option<String>,
+    oauth: OAuth,
+    /// Prefilled on the login page
+    email: Option<String>,
+    state: FlowState,
+}
+
+impl AuthFlow {
+    pub fn new(session_name: Option<String>, oauth: OAuth, email: Option<String>) -> Self {
+        Self { session_name, oauth, email, state: FlowState::New }
+    }
+
+    /// Starts the flow, or over, with a fresh login page to open
+    pub fn start(&mut self) -> Result<String> {
+        let (url, options) = create_auth_url(&self.oauth, self.email.as_deref())?;
+        self.state = FlowState::AwaitingCode(options);
+        Ok(url)
+    }
+
+    /// Takes the next redirect. One that doesn't belong to the current step,
+    /// or whose state doesn't match the one sent, fails the flow, as
+    /// anything else could be a forged redirect.
+    pub async fn advance(&mut self, client: &Client, redirect: Redirect) -> Result<Step> {
+        match (&self.state, redirect) {
+            (FlowState::AwaitingCode(options), Redirect::Auth { code, state }) => {
+                // A login page left open, or a laptop resumed, delivers a code
+                // the token endpoint rejects, so start over instead
+                if options.is_expired(self.oauth.flow_timeout) {
+                    output::renderer().status(&theme::warning("The login page was open too long, starting the login over..."));
+                    return Ok(Step::Open(self.start()?));
+                }
+                if state != options.state {
+                    return Err(AuthError::InvalidResponse("Auth state parameter mismatch - possible CSRF attack".to_string()));
+                }
+                progress::emit(Progress::CodeReceived);
+
+                let token_response = client.token(&code, &options.verifier).await?;
+                progress::emit(Progress::TokenExchanged);
+                let (consent_url, consent_state) = create_consent_url(&self.oauth, &token_response.tokens.id_token)?;
+                self.state = FlowState::AwaitingConsent(PendingConsent {
+                    state: consent_state,
+                    issued_at: token_response.time,
+                    expires_in: Duration::from_secs(token_response.tokens.expires_in as u64),
+                });
+                Ok(Step::Open(consent_url))
+            }
+            (FlowState::AwaitingConsent(expected), Redirect::Consent { id_token, state }) => {
+                if expected.state != state {
+                    return Err(AuthError::InvalidResponse("Consent state parameter mismatch - possible CSRF attack".to_string()));
+                }
+                client.create_session(&id_token, expected.issued_at, expected.expires_in).await?;
+                progress::emit(Progress::SessionCreated { session_name: client.session_name() });
+
+                let expires_at = expected.issued_at + expected.expires_in;
+                self.state = FlowState::Done { expires_at };
+                Ok(Step::Done { expires_at })
+            }
+            (_, Redirect::Consent { .. }) => {
+                Err(AuthError::InvalidResponse("No consent state found - possible CSRF attack".to_string()))
+            }
+            (_, Redirect::Auth { .. }) => {
+                Err(AuthError::InvalidResponse("Unexpected login redirect - possible CSRF attack".to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl AuthFlow {
+    /// Backdates the open login page past the flow's timeout
+    pub fn expire(&mut self) {
+        if let FlowState::AwaitingCode(options) = &mut self.state {
+            options.issued_at -= self.oauth.flow_timeout + Duration::from_secs(60);
+        }
+    }
+
+    /// The state sent with the open login page
+    pub fn login_state(&self) -> Option<String> {
+        match &self.state {
+            FlowState::AwaitingCode(options) => Some(options.state.clone()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The kind of redirect and its two values, for comparing
+    fn describe(redirect: Option<Redirect>) -> Option<(&'static str, String, String)> {
+        match redirect? {
+            Redirect::Auth { code, state } => Some(("auth", code, state)),
+            Redirect::Consent { id_token, state } => Some(("consent", id_token, state)),
+        }
+    }
+
+    #[test]
+    fn parses_redirects() {
+        let auth = Some(("auth", "abc".to_owned(), "xyz".to_owned()));
+        let consent = Some(("consent", "token".to_owned(), "xyz".to_owned()));
+        let cases = [
+            // Query and fragment parameters
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect?code=abc&state=xyz", &auth),
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect#code=abc&state=xyz", &auth),
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect?code=abc#state=xyz", &auth),
+            ("http://localhost/#id_token=token&state=xyz", &consent),
+            ("http://localhost/?id_token=token&state=xyz", &consent),
+            ("http://127.0.0.1:4567/#id_token=token&state=xyz", &consent),
+            ("http://[::1]/#id_token=token&state=xyz", &consent),
+            // A trailing slash and percent-encoded paths
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect/?code=abc&state=xyz", &auth),
+            ("https://secure.runescape.com/m%3Dweblogin/launcher-redirect?code=abc&state=xyz", &auth),
+            ("https://secure.runescape.com/m=weblogin/launcher%2Dredirect?code=abc&state=xyz", &auth),
+            ("https://secure.runescape.com/m=weblogin/other-redirect?code=abc&state=xyz", &None),
+            // Percent-encoded values come back decoded
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect?code=a%62c&state=x%79z", &auth),
+            // Uppercase and wrong hosts
+            ("https://SECURE.RuneScape.com/m=weblogin/launcher-redirect?code=abc&state=xyz", &auth),
+            ("https://secure.jagex.com/m=weblogin/launcher-redirect?code=abc&state=xyz", &auth),
+            ("http://LOCALHOST/#id_token=token&state=xyz", &consent),
+            ("https://evil.example/m=weblogin/launcher-redirect?code=abc&state=xyz", &None),
+            ("https://secure.runescape.com.evil.example/m=weblogin/launcher-redirect?code=abc&state=xyz", &None),
+            ("http://evil.example/#id_token=token&state=xyz", &None),
+            // http on the auth redirect
+            ("http://secure.runescape.com/m=weblogin/launcher-redirect?code=abc&state=xyz", &None),
+            // Empty and missing code and state
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect?code=&state=xyz", &None),
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect?code=abc&state=", &None),
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect?state=xyz", &None),
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect?code=abc", &None),
+            ("https://secure.runescape.com/m=weblogin/launcher-redirect", &None),
+            ("http://localhost/#id_token=&state=xyz", &None),
+            ("http://localhost/#id_token=token", &None),
+            // Not URLs at all
+            ("", &None),
+            ("launcher-redirect?code=abc&state=xyz", &None),
+        ];
+
+        for (url, expected) in cases {
+            assert_eq!(&describe(parse_redirect(url)), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn checks_pkce_verifier_lengths() {
+        for (length, allowed) in [(42, false), (43, true), (128, true), (129, false)] {
+            match AuthOptions::new(length) {
+                Ok(options) => {
+                    assert!(allowed, "{length} was allowed");
+                    assert_eq!(options.verifier.len(), length);
+                    verify_pkce(options.verifier.as_bytes(), &options.challenge).unwrap();
+                }
+                Err(error) => {
+                    assert!(!allowed, "{length} was refused: {error}");
+                    assert!(matches!(error, AuthError::ConfigError { .. }));
+                }
+            }
+        }
+    }
+
+    /// RFC 7636, Appendix B
+    const RFC_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const RFC_CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    #[test]
+    fn matches_the_rfc_7636_example() {
+        assert_eq!(pkce::code_challenge(RFC_VERIFIER.as_bytes()), RFC_CHALLENGE);
+        verify_pkce(RFC_VERIFIER.as_bytes(), RFC_CHALLENGE).unwrap();
+        assert!(verify_pkce(RFC_VERIFIER.as_bytes(), &RFC_CHALLENGE.replace('E', "F")).is_err());
+    }
+
+    #[test]
+    fn refuses_disallowed_verifier_characters() {
+        for verifier in [
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjX+",
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjX/",
+            "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjX=",
+            "dBjftJeZ4CVP mB92K27uhbUJU1p1r_wW1gFWFOEjXk",
+        ] {
+            let challenge = pkce::code_challenge(verifier.as_bytes());
+            assert!(
+                matches!(verify_pkce(verifier.as_bytes(), &challenge), Err(AuthError::InvalidPkce { .. })),
+                "{verifier}"
+            );
+        }
+    }
+
+    fn flow() -> AuthFlow {
+        AuthFlow::new(None, crate::env::oauth(&None), None)
+    }
+
+    fn awaiting_consent(state: &str) -> FlowState {
+        FlowState::AwaitingConsent(PendingConsent {
+            state: state.to_owned(),
+            issued_at: SystemTime::now(),
+            expires_in: Duration::from_secs(3600),
+        })
+    }
+
+    fn is_refused(result: Result<Step>, message: &str) -> bool {
+        matches!(result, Err(AuthError::InvalidResponse(details)) if details.contains(message))
+    }
+
+    #[tokio::test]
+    async fn refuses_a_login_state_mismatch() {
+        let mut flow = flow();
+        flow.start().unwrap();
+        let redirect = Redirect::Auth { code: "abc".to_owned(), state: "forged".to_owned() };
+        assert!(is_refused(flow.advance(&Client::new(None), redirect).await, "Auth state parameter mismatch"));
+    }
+
+    #[tokio::test]
+    async fn refuses_a_consent_state_mismatch() {
+        let mut flow = flow();
+        flow.state = awaiting_consent("expected");
+        let redirect = Redirect::Consent { id_token: "token".to_owned(), state: "forged".to_owned() };
+        assert!(is_refused(flow.advance(&Client::new(None), redirect).await, "Consent state parameter mismatch"));
+    }
+
+    #[tokio::test]
+    async fn refuses_a_consent_redirect_while_awaiting_the_code() {
+        let mut flow = flow();
+        flow.start().unwrap();
+        let FlowState::AwaitingCode(options) = &flow.state else { panic!("not awaiting the code") };
+        let redirect = Redirect::Consent { id_token: "token".to_owned(), state: options.state.clone() };
+        assert!(is_refused(flow.advance(&Client::new(None), redirect).await, "No consent state found"));
+    }
+
+    #[tokio::test]
+    async fn refuses_a_login_redirect_while_awaiting_consent() {
+        let mut flow = flow();
+        flow.state = awaiting_consent("expected");
+        let redirect = Redirect::Auth { code: "abc".to_owned(), state: "expected".to_owned() };
+        assert!(is_refused(flow.advance(&Client::new(None), redirect).await, "Unexpected login redirect"));
+    }
+
+    #[tokio::test]
+    async fn restarts_an_expired_login() {
+        let mut flow = flow();
+        flow.start().unwrap();
+        flow.expire();
+        let old_state = flow.login_state().unwrap();
+
+        let redirect = Redirect::Auth { code: "abc".to_owned(), state: old_state.clone() };
+        let Step::Open(url) = flow.advance(&Client::new(None), redirect).await.unwrap() else {
+            panic!("the login wasn't restarted")
+        };
+        let FlowState::AwaitingCode(options) = &flow.state else { panic!("not awaiting the code") };
+        assert_ne!(options.state, old_state);
+        assert!(!options.is_expired(flow.oauth.flow_timeout));
+        assert!(url.contains(&format!("state={}", options.state)));
+    }
+}
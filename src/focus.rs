@@ -0,0 +1,84 @@
+use std::process::{Command, Stdio};
+
+/// Runs a helper, quietly, reporting whether it succeeded
+fn run(program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The process and everything it started, as the RuneLite launcher runs the
+/// client in a JVM of its own
+#[cfg(target_os = "linux")]
+fn process_tree(pid: u32) -> Vec<u32> {
+    let parents = std::fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| {
+                    let child = entry.file_name().to_str()?.parse::<u32>().ok()?;
+                    let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+                    // The command name in parentheses may hold spaces
+                    let parent = stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse::<u32>().ok()?;
+                    Some((child, parent))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let mut tree = vec![pid];
+    let mut i = 0;
+    while let Some(&current) = tree.get(i) {
+        tree.extend(parents.iter().filter(|(_, parent)| *parent == current).map(|(child, _)| *child));
+        i += 1;
+    }
+    tree
+}
+
+/// Raises a window of the process with `xdotool` or `wmctrl`. Neither can
+/// raise windows on Wayland, where compositors keep that to the window's
+/// own client.
+#[cfg(target_os = "linux")]
+pub fn bring_to_front(pid: u32) -> bool {
+    for pid in process_tree(pid) {
+        let pid = pid.to_string();
+        if run("xdotool", &["search", "--onlyvisible", "--pid", &pid, "windowactivate"]) {
+            return true;
+        }
+
+        let Ok(output) = Command::new("wmctrl").arg("-lp").stderr(Stdio::null()).output() else {
+            continue;
+        };
+        let windows = String::from_utf8_lossy(&output.stdout);
+        // `<window ID> <desktop> <pid> <host> <title>`
+        let window = windows.lines().find_map(|line| {
+            let mut fields = line.split_whitespace();
+            let id = fields.next()?;
+            (fields.nth(1)? == pid).then_some(id)
+        });
+        if window.is_some_and(|window| run("wmctrl", &["-ia", window])) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(target_os = "macos")]
+pub fn bring_to_front(pid: u32) -> bool {
+    let script = format!("tell application \"System Events\" to set frontmost of (first process whose unix id is {pid}) to true");
+    run("osascript", &["-e", &script])
+}
+
+#[cfg(windows)]
+pub fn bring_to_front(pid: u32) -> bool {
+    let script = format!("exit -not (New-Object -ComObject WScript.Shell).AppActivate({pid})");
+    run("powershell", &["-NoProfile", "-NonInteractive", "-Command", &script])
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn bring_to_front(_pid: u32) -> bool {
+    false
+}
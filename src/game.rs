@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+/// The Jagex game a client is for. The login is the same, but the clients,
+/// presets and icons differ.
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Game {
+    /// Old School RuneScape
+    #[default]
+    Osrs,
+    /// RuneScape 3
+    Rs3,
+}
+
+impl Game {
+    /// The game given on the command line or by a profile, then `game` in
+    /// the config file, then Old School RuneScape
+    pub fn resolve(game: Option<Game>) -> Game {
+        game.or(config::get().game).unwrap_or_default()
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Game::Osrs => "Old School RuneScape",
+            Game::Rs3 => "RuneScape",
+        }
+    }
+
+    /// Icon desktop entries use, from the icon theme
+    pub fn icon(&self) -> &'static str {
+        match self {
+            Game::Osrs => "runelite",
+            Game::Rs3 => "runescape",
+        }
+    }
+}
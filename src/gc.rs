@@ -0,0 +1,244 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use crate::{config, error::Result, output, paths, sessions, theme};
+
+/// Limits `auth-rs gc` enforces, under `[gc]`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct GcConfig {
+    /// Size the offline character lists may take up together, after which
+    /// the least recently written are removed
+    pub max_cache_mb: Option<u64>,
+    /// Size a log grows to before it's rotated
+    pub max_log_mb: u64,
+    /// Rotated logs kept per log
+    pub kept_logs: u32,
+}
+
+impl Default for GcConfig {
+    fn default() -> Self {
+        Self { max_cache_mb: None, max_log_mb: 10, kept_logs: 3 }
+    }
+}
+
+/// Something `gc` removed or rotated
+struct Pruned {
+    path: PathBuf,
+    reason: &'static str,
+    bytes: u64,
+}
+
+/// Total size of a file, or of everything in a directory
+fn size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| size(&entry.path())).sum())
+        .unwrap_or_default()
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+fn remove(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+fn modified(path: &Path) -> SystemTime {
+    std::fs::metadata(path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+/// The per-session cache directories, oldest first
+fn session_caches(cache_dir: &Path) -> Vec<(PathBuf, SystemTime)> {
+    let Ok(entries) = std::fs::read_dir(cache_dir) else {
+        return vec![];
+    };
+
+    let runtime_dir = paths::runtime_dir().ok();
+    let mut caches = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && runtime_dir.as_deref() != Some(path.as_path()))
+        .map(|path| {
+            let modified = modified(&path);
+            (path, modified)
+        })
+        .collect::<Vec<_>>();
+    caches.sort_by_key(|(_, modified)| *modified);
+    caches
+}
+
+/// The files of a session's offline character list, leaving its avatars to
+/// the desktop entries that use them
+fn offline_list(cache: &Path) -> Vec<PathBuf> {
+    ["accounts.json", "accounts.etag"].iter().map(|name| cache.join(name)).filter(|path| path.exists()).collect()
+}
+
+/// Caches of sessions that no longer exist, then the oldest offline lists
+/// of the rest until they fit in `max_cache_mb`
+fn prune_caches() -> Result<Vec<Pruned>> {
+    let known = sessions::known_keys()?;
+    let mut kept = vec![];
+    let mut pruned = vec![];
+
+    for (path, _) in session_caches(&paths::cache_dir()?) {
+        let key = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if known.contains(&key) {
+            kept.extend(offline_list(&path).into_iter().map(|path| {
+                let bytes = size(&path);
+                (path, bytes)
+            }));
+        } else {
+            let bytes = size(&path);
+            pruned.push(Pruned { path, reason: "session no longer exists", bytes });
+        }
+    }
+
+    if let Some(max_mb) = config::get().gc.max_cache_mb {
+        kept.sort_by_key(|(path, _)| modified(path));
+        let mut total = kept.iter().map(|(_, bytes)| bytes).sum::<u64>();
+        for (path, bytes) in kept {
+            if total <= max_mb * 1_048_576 {
+                break;
+            }
+            total -= bytes;
+            pruned.push(Pruned { path, reason: "over the cache size limit", bytes });
+        }
+    }
+
+    Ok(pruned)
+}
+
+/// `<name>.log.<n>` as the log it was rotated from and `n`
+fn rotated_log(file_name: &str) -> Option<(&str, u32)> {
+    let (log, n) = file_name.rsplit_once('.')?;
+    log.ends_with(".log").then_some(())?;
+    Some((log, n.parse().ok()?))
+}
+
+/// Logs over `max_log_mb` to rotate, and rotated logs past `kept_logs`
+fn prune_logs() -> Result<(Vec<PathBuf>, Vec<Pruned>)> {
+    let gc = &config::get().gc;
+    let Ok(entries) = std::fs::read_dir(paths::log_dir()?) else {
+        return Ok((vec![], vec![]));
+    };
+
+    let mut rotate = vec![];
+    let mut pruned = vec![];
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if file_name.ends_with(".log") && size(&path) > gc.max_log_mb * 1_048_576 {
+            rotate.push(path);
+        } else if rotated_log(&file_name).is_some_and(|(_, n)| n > gc.kept_logs) {
+            let bytes = size(&path);
+            pruned.push(Pruned { path, reason: "old rotated log", bytes });
+        }
+    }
+    Ok((rotate, pruned))
+}
+
+/// Renames `<name>.log` to `<name>.log.1`, shifting the older ones up and
+/// dropping the one that falls past `kept_logs`
+fn rotate(log: &Path) -> Result<Option<Pruned>> {
+    let kept = config::get().gc.kept_logs;
+    let numbered = |n: u32| PathBuf::from(format!("{}.{n}", log.display()));
+
+    if kept == 0 {
+        let bytes = size(log);
+        std::fs::remove_file(log)?;
+        return Ok(Some(Pruned { path: log.to_owned(), reason: "log over the size limit", bytes }));
+    }
+
+    let oldest = numbered(kept);
+    let dropped = oldest.exists().then(|| Pruned { bytes: size(&oldest), path: oldest, reason: "old rotated log" });
+    for n in (1..kept).rev() {
+        if numbered(n).exists() {
+            std::fs::rename(numbered(n), numbered(n + 1))?;
+        }
+    }
+    std::fs::rename(log, numbered(1))?;
+    Ok(dropped)
+}
+
+/// Removes caches of sessions that are gone, rotates large logs and keeps
+/// the caches under `[gc] max_cache_mb`, printing what went. `dry_run` only
+/// prints it.
+pub fn run(dry_run: bool) -> Result<()> {
+    let (logs, mut pruned) = prune_logs()?;
+    pruned.extend(prune_caches()?);
+
+    let mut failed = None;
+    let mut freed = 0;
+    let renderer = output::renderer();
+    for log in &logs {
+        let fields = [("action", "rotate".to_owned()), ("path", log.display().to_string())];
+        if dry_run {
+            renderer.record(&format!("  {} Would rotate {}", theme::accent("•"), style(log.display()).bold()), &fields);
+            continue;
+        }
+        match rotate(log) {
+            Ok(dropped) => {
+                renderer.record(&format!("  {} Rotated {}", theme::highlight("✓"), style(log.display()).bold()), &fields);
+                pruned.extend(dropped);
+            }
+            Err(error) => {
+                output::failed(&log.display().to_string(), &error, &fields);
+                failed = Some(error);
+            }
+        }
+    }
+
+    for item in &pruned {
+        let details = style(format!("{}, {}", item.reason, format_size(item.bytes))).dim();
+        let fields = [
+            ("action", "remove".to_owned()),
+            ("path", item.path.display().to_string()),
+            ("reason", item.reason.to_owned()),
+            ("bytes", item.bytes.to_string()),
+        ];
+        if dry_run {
+            let human = format!("  {} Would remove {} {details}", theme::accent("•"), style(item.path.display()).bold());
+            renderer.record(&human, &fields);
+            continue;
+        }
+        match remove(&item.path) {
+            Ok(()) => {
+                let human = format!("  {} Removed {} {details}", theme::highlight("✓"), style(item.path.display()).bold());
+                renderer.record(&human, &fields);
+                freed += item.bytes;
+            }
+            Err(error) => {
+                output::failed(&item.path.display().to_string(), &error, &fields);
+                failed = Some(error);
+            }
+        }
+    }
+
+    if logs.is_empty() && pruned.is_empty() {
+        renderer.note(&"Nothing to clean up");
+    } else if !dry_run {
+        renderer.note(&format!("Freed {}", theme::highlight(format_size(freed))));
+    }
+    failed.map_or(Ok(()), Err)
+}
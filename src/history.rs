@@ -0,0 +1,160 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{client::Account, error::Result, launch, paths};
+
+/// The last time a character was launched
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Launch {
+    /// Unix timestamp of the launch
+    pub at: u64,
+    /// The client it was launched with, e.g. "RuneLite"
+    pub client: String,
+}
+
+impl Launch {
+    pub fn age(&self) -> Duration {
+        let at = UNIX_EPOCH + Duration::from_secs(self.at);
+        SystemTime::now().duration_since(at).unwrap_or_default()
+    }
+}
+
+/// A client run from start to exit, recorded by `--spawn`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Play {
+    pub character_id: String,
+    /// Display name at the time, so renamed and forgotten characters still
+    /// show up by name
+    pub display_name: String,
+    pub client: String,
+    /// Unix timestamp the client started at
+    pub started_at: u64,
+    pub seconds: u64,
+}
+
+/// Launches of a character on one local day
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct DailyLaunches {
+    /// `YYYY-MM-DD`
+    date: String,
+    count: u32,
+}
+
+fn today() -> String {
+    jiff::Zoned::now().date().to_string()
+}
+
+/// Last launches by character ID, and the time played through `--spawn`
+#[derive(Serialize, Deserialize, Default)]
+struct History {
+    launches: BTreeMap<String, Launch>,
+    /// Today's launch counts by character ID, for `[restrictions]`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    daily: BTreeMap<String, DailyLaunches>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    plays: Vec<Play>,
+}
+
+impl History {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join("launches.json"))
+    }
+
+    fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+/// A name for the client a program and its arguments start
+pub fn client_name(program: &str, args: &[String]) -> String {
+    if launch::is_runelite(program, args) {
+        return "RuneLite".to_owned();
+    }
+
+    Path::new(program)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| program.to_owned())
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Remembers the character as launched just now with `client`
+pub fn record(character_id: &str, client: String) -> Result<()> {
+    let mut history = History::load()?;
+    let at = unix_time(SystemTime::now());
+    history.launches.insert(character_id.to_owned(), Launch { at, client });
+
+    let date = today();
+    let daily = history.daily.entry(character_id.to_owned()).or_insert(DailyLaunches { date: date.clone(), count: 0 });
+    if daily.date != date {
+        *daily = DailyLaunches { date, count: 0 };
+    }
+    daily.count += 1;
+    history.save()
+}
+
+/// How often the character was launched today
+pub fn launches_today(character_id: &str) -> u32 {
+    let Ok(history) = History::load() else { return 0 };
+    history
+        .daily
+        .get(character_id)
+        .filter(|daily| daily.date == today())
+        .map_or(0, |daily| daily.count)
+}
+
+/// Adds a finished client run to the play time
+pub fn record_play(account: &Account, client: String, started_at: SystemTime, played: Duration) -> Result<()> {
+    let mut history = History::load()?;
+    history.plays.push(Play {
+        character_id: account.account_id.clone(),
+        display_name: account.display_name.clone(),
+        client,
+        started_at: unix_time(started_at),
+        seconds: played.as_secs(),
+    });
+    history.save()
+}
+
+/// Every client run recorded, oldest first
+pub fn plays() -> Result<Vec<Play>> {
+    Ok(History::load()?.plays)
+}
+
+/// Every character's last launch, empty when nothing was ever launched or
+/// the history can't be read
+pub fn launches() -> BTreeMap<String, Launch> {
+    History::load().map(|history| history.launches).unwrap_or_default()
+}
+
+/// Sorts items most recently launched first, leaving those never launched
+/// at the end in their order
+pub fn sort_by_recency<T>(items: &mut [T], character_id: impl Fn(&T) -> &str) {
+    let launches = launches();
+    items.sort_by_key(|item| std::cmp::Reverse(launches.get(character_id(item)).map(|launch| launch.at)));
+}
@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+
+use console::style;
+use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
+
+use crate::{
+    client::Client,
+    config::{self, Config, Profile},
+    desktop,
+    error::{AuthError, Result},
+    game::Game,
+    history,
+    launch,
+    presets::Preset,
+    theme,
+};
+
+/// A game client found on this machine, as a program and its arguments
+struct ClientCandidate {
+    label: String,
+    preset: Option<Preset>,
+    exec: Option<String>,
+    args: Vec<String>,
+}
+
+fn discover_clients() -> Vec<ClientCandidate> {
+    let mut candidates = vec![];
+
+    if let Some(path) = launch::find_executable("runelite") {
+        candidates.push(ClientCandidate {
+            label: format!("RuneLite ({})", path.display()),
+            preset: None,
+            exec: Some(path.display().to_string()),
+            args: vec![],
+        });
+    }
+
+    let flatpak_installed = [
+        dirs::data_dir().map(|d| d.join("flatpak/app/net.runelite.RuneLite")),
+        Some(PathBuf::from("/var/lib/flatpak/app/net.runelite.RuneLite")),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|path| path.exists());
+
+    if flatpak_installed {
+        candidates.push(ClientCandidate {
+            label: "RuneLite (Flatpak)".to_owned(),
+            preset: None,
+            exec: Some("flatpak".to_owned()),
+            args: vec!["run".to_owned(), "net.runelite.RuneLite".to_owned()],
+        });
+    }
+
+    let appimage_dirs = [
+        dirs::home_dir().map(|h| h.join("Applications")),
+        dirs::executable_dir(),
+        dirs::download_dir(),
+    ];
+    for dir in appimage_dirs.into_iter().flatten() {
+        let path = dir.join("RuneLite.AppImage");
+        if path.exists() {
+            candidates.push(ClientCandidate {
+                label: format!("RuneLite AppImage ({})", path.display()),
+                preset: None,
+                exec: Some(path.display().to_string()),
+                args: vec![],
+            });
+        }
+    }
+
+    if Preset::Hdos.command(Game::Osrs, &[]).is_ok() {
+        candidates.push(ClientCandidate {
+            label: "HDOS".to_owned(),
+            preset: Some(Preset::Hdos),
+            exec: None,
+            args: vec![],
+        });
+    }
+
+    candidates
+}
+
+fn select_client(theme: &ColorfulTheme) -> Result<ClientCandidate> {
+    let mut candidates = discover_clients();
+    let mut items = candidates.iter().map(|c| c.label.clone()).collect::<Vec<_>>();
+    items.push("Other (enter a command)".to_owned());
+
+    let selection = Select::with_theme(theme)
+        .with_prompt("Which game client do you want to launch?")
+        .items(&items)
+        .default(0)
+        .interact()?;
+
+    if selection < candidates.len() {
+        return Ok(candidates.swap_remove(selection));
+    }
+
+    let exec: String = Input::with_theme(theme)
+        .with_prompt("Name or path of the executable")
+        .interact_text()?;
+    let args: String = Input::with_theme(theme)
+        .with_prompt("Arguments (space separated)")
+        .allow_empty(true)
+        .interact_text()?;
+
+    Ok(ClientCandidate {
+        label: exec.clone(),
+        preset: None,
+        exec: Some(exec),
+        args: args.split_whitespace().map(str::to_owned).collect(),
+    })
+}
+
+pub async fn run() -> Result<()> {
+    let theme = theme::prompts();
+    // A config file that doesn't load is set up afresh, and kept aside
+    let (config, broken) = match Config::load_user() {
+        Ok(config) => (config, None),
+        Err(error @ AuthError::ConfigError { .. }) => {
+            let path = Config::path()?;
+            let backup = path.with_extension("toml.bak");
+            eprintln!(
+                "{} {error}. It's replaced once setup finishes and kept as {}",
+                theme::warning("Warning:").bold(),
+                style(backup.display()).bold()
+            );
+            (Config::default(), Some((path, backup)))
+        }
+        Err(error) => return Err(error),
+    };
+
+    println!("{}", style("Welcome to auth-rs! Let's get you set up.").bold());
+
+    let session_name: String = Input::with_theme(&theme)
+        .with_prompt("Session name (leave empty for the default session)")
+        .allow_empty(true)
+        .interact_text()?;
+    let session_name = Some(session_name.trim().to_owned()).filter(|s| !s.is_empty());
+
+    let client = Client::new(session_name.clone());
+    let authorized = client.session().is_ok();
+    let reauthorize = !authorized
+        || Confirm::with_theme(&theme)
+            .with_prompt("This session is already authorized, log in again?")
+            .default(false)
+            .interact()?;
+
+    if reauthorize {
+        println!("Opening the authorize window...");
+        launch::reauthorize(&session_name)?;
+    }
+
+    let accounts = match client.accounts(false, true).await {
+        Err(AuthError::SessionExpired) => {
+            println!("{}", theme::warning("Session expired, opening the authorize window..."));
+            launch::reauthorize(&session_name)?;
+            client.accounts(false, true).await?
+        }
+        result => result?,
+    };
+
+    if accounts.is_empty() {
+        return Err(AuthError::NoCharacters);
+    }
+    let mut accounts = accounts;
+    history::sort_by_recency(&mut accounts, |a| &a.account_id);
+
+    let items = accounts
+        .iter()
+        .map(|a| format!("{} (ID: {})", a.display_name, a.account_id))
+        .collect::<Vec<_>>();
+    let selection = Select::with_theme(&theme)
+        .with_prompt("Default character")
+        .items(&items)
+        .default(0)
+        .interact()?;
+    let account = &accounts[selection];
+
+    let game_client = select_client(&theme)?;
+
+    let profile_name: String = Input::with_theme(&theme)
+        .with_prompt("Profile name")
+        .default("default".to_owned())
+        .interact_text()?;
+
+    if Confirm::with_theme(&theme)
+        .with_prompt("Create a desktop entry?")
+        .default(true)
+        .interact()?
+    {
+        let name: String = Input::with_theme(&theme)
+            .with_prompt("Desktop entry name")
+            .default(format!("RuneLite ({})", account.display_name))
+            .interact_text()?;
+        let desktop_entry = desktop::create_entry(
+            session_name.clone(),
+            name,
+            account.account_id.clone(),
+            desktop::Program::new(game_client.preset, game_client.exec.clone(), game_client.args.clone())?,
+            None,
+            &desktop::Terminal::new(false, None),
+        )
+        .await?;
+        println!(
+            "Desktop entry created: {}",
+            theme::highlight(desktop_entry.display())
+        );
+    }
+
+    let profile = Profile {
+        session_name,
+        character_id: Some(account.account_id.clone()),
+        game: None,
+        preset: game_client.preset,
+        exec: game_client.exec,
+        args: game_client.args,
+        env: Default::default(),
+        sha256: None,
+    };
+    if let Some((path, backup)) = broken {
+        std::fs::rename(path, backup)?;
+    }
+    let path = Config::edit_user(|table| {
+        config::table_mut(table, "profiles")?.insert(profile_name.clone(), config::to_value(&profile)?);
+        if config.default_profile.is_none() {
+            table.insert("default_profile".to_owned(), profile_name.clone().into());
+        }
+        Ok(())
+    })?;
+
+    println!(
+        "Config written to {}",
+        theme::highlight(path.display())
+    );
+    println!(
+        "Launch with: {}",
+        style(format!("auth-rs exec --profile {profile_name}")).bold()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::path::PathBuf;
+
+use console::style;
+use dialoguer::Confirm;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::Account,
+    error::{AuthError, Result},
+    focus, output, paths, prompt, theme,
+};
+
+/// A client started by auth-rs that may still be running, kept in the
+/// runtime directory per character
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Instance {
+    pub pid: u32,
+    pub display_name: String,
+    pub client: String,
+}
+
+fn path(character_id: &str) -> Result<PathBuf> {
+    Ok(paths::runtime_dir()?.join("instances").join(format!("{character_id}.json")))
+}
+
+#[cfg(unix)]
+fn is_alive(pid: u32) -> bool {
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(windows)]
+fn is_alive(pid: u32) -> bool {
+    use windows_sys::Win32::{
+        Foundation::{CloseHandle, STILL_ACTIVE},
+        System::Threading::{GetExitCodeProcess, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION},
+    };
+
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return false;
+        }
+        let mut code = 0;
+        let alive = GetExitCodeProcess(process, &mut code) != 0 && code == STILL_ACTIVE as u32;
+        CloseHandle(process);
+        alive
+    }
+}
+
+/// Remembers the client as running the character as `pid`
+pub fn record(account: &Account, client: &str, pid: u32) -> Result<()> {
+    let path = path(&account.account_id)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let instance = Instance { pid, display_name: account.display_name.clone(), client: client.to_owned() };
+    let file = std::fs::File::create(path)?;
+    serde_json::to_writer(file, &instance)?;
+    Ok(())
+}
+
+/// Forgets the client once it exited, unless another launch took its place
+pub fn forget(character_id: &str, pid: u32) {
+    let Ok(path) = path(character_id) else { return };
+    if running_file(&path).is_some_and(|instance| instance.pid == pid) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn running_file(path: &PathBuf) -> Option<Instance> {
+    let file = std::fs::File::open(path).ok()?;
+    serde_json::from_reader(file).ok()
+}
+
+/// The client running the character, if one is. Records of clients that
+/// exited are cleaned up on the way.
+pub fn running(character_id: &str) -> Option<Instance> {
+    let path = path(character_id).ok()?;
+    let instance = running_file(&path)?;
+    if is_alive(instance.pid) {
+        return Some(instance);
+    }
+    let _ = std::fs::remove_file(path);
+    None
+}
+
+/// What to do when the character is already running
+#[derive(Debug, Clone, Copy)]
+pub enum OnRunning {
+    /// Ask in a terminal, refuse without one
+    Ask,
+    /// Launch another client regardless
+    Allow,
+    /// Bring the running client's window to the front and launch nothing,
+    /// asking as with `Ask` when the window can't be raised
+    Focus,
+}
+
+/// Whether to go ahead with the launch when a client started by auth-rs is
+/// still running the character, as a second login kicks the first to the
+/// lobby. Returns false once the running client was brought to the front
+/// instead.
+pub fn check(character_id: &str, on_running: OnRunning) -> Result<bool> {
+    if matches!(on_running, OnRunning::Allow) {
+        return Ok(true);
+    }
+    let Some(instance) = running(character_id) else {
+        return Ok(true);
+    };
+    if matches!(on_running, OnRunning::Focus) && focus::bring_to_front(instance.pid) {
+        output::renderer()
+            .status(&format!("{} is already running, brought its window to the front", style(&instance.display_name).bold()));
+        return Ok(false);
+    }
+
+    if prompt::is_interactive() {
+        let prompt = format!(
+            "{} is already running in {} (PID {}). Launch it again? The other client will be logged out",
+            style(&instance.display_name).bold(),
+            instance.client,
+            instance.pid
+        );
+        if Confirm::with_theme(&theme::prompts()).with_prompt(prompt).default(false).interact()? {
+            return Ok(true);
+        }
+    }
+    Err(AuthError::AlreadyRunning { name: instance.display_name, pid: instance.pid })
+}
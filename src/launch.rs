@@ -0,0 +1,445 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, SystemTime},
+};
+
+use log::warn;
+
+use crate::{
+    client::{self, Account, Client, Session},
+    config,
+    error::{AuthError, AvailableCharacter, Result},
+    history, instances, output, paths,
+    progress::{self, ProgressFormat},
+    prompt, restrictions, sandbox, sessions, theme,
+};
+
+/// Looks up an executable by name in `$PATH`
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Whether the program is RuneLite, which runs through `java` and shell
+/// scripts so it's looked for in the arguments too
+pub fn is_runelite(program: &str, args: &[String]) -> bool {
+    std::iter::once(program)
+        .chain(args.iter().map(String::as_str))
+        .any(|arg| arg.to_lowercase().contains("runelite"))
+}
+
+/// Appends the character's mapped RuneLite `--profile`, unless the program
+/// isn't RuneLite or a profile was already passed
+pub fn with_runelite_profile(character_id: &str, program: &str, args: &[String]) -> Vec<String> {
+    let mut args = args.to_vec();
+    let profile = config::get()
+        .character(character_id)
+        .and_then(|c| c.runelite_profile.as_ref());
+
+    if let Some(profile) = profile {
+        let has_profile = args.iter().any(|a| a == "--profile" || a.starts_with("--profile="));
+        if is_runelite(program, &args) && !has_profile {
+            args.push(format!("--profile={profile}"));
+        }
+    }
+
+    args
+}
+
+/// Quotes an argument for a POSIX shell command line, leaving plain words as they are
+pub fn shell_quote(arg: &str) -> String {
+    let plain = !arg.is_empty()
+        && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// The user's shell, running `exec`/`args` as a command line when given and
+/// interactively otherwise
+pub fn shell_command(exec: Option<String>, args: Vec<String>) -> (String, Vec<String>) {
+    #[cfg(windows)]
+    let (shell, command_flag) = (std::env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_owned()), "/C");
+    #[cfg(not(windows))]
+    let (shell, command_flag) = (std::env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_owned()), "-c");
+
+    match exec {
+        Some(exec) => {
+            let command_line = std::iter::once(exec)
+                .chain(args)
+                .map(|arg| shell_quote(&arg))
+                .collect::<Vec<_>>()
+                .join(" ");
+            (shell, vec![command_flag.to_owned(), command_line])
+        }
+        None => (shell, vec![]),
+    }
+}
+
+pub fn find_account<'a>(accounts: &'a [Account], character_id: &str) -> Result<&'a Account> {
+    if accounts.is_empty() {
+        return Err(AuthError::NoCharacters);
+    }
+    accounts.iter().find(|a| a.account_id == character_id).ok_or_else(|| {
+        let available = accounts
+            .iter()
+            .map(|a| AvailableCharacter { account_id: a.account_id.clone(), display_name: a.display_name.clone() })
+            .collect();
+
+        AuthError::CharacterNotFound {
+            character_id: character_id.to_owned(),
+            available,
+        }
+    })
+}
+
+/// The character's own home directory when asked for on the command line or
+/// in the config file, created on first use
+pub fn isolated_home(character_id: &str, requested: bool) -> Result<Option<PathBuf>> {
+    let configured = config::get().character(character_id).is_some_and(|c| c.isolate_home);
+    if !requested && !configured {
+        return Ok(None);
+    }
+
+    // The ID names a directory under `homes`, so it mustn't lead out of it
+    let plain = !character_id.is_empty()
+        && !character_id.contains("..")
+        && !character_id.contains(['/', '\\', ':'])
+        && character_id != ".";
+    if !plain {
+        return Err(AuthError::InvalidCharacterId { character_id: character_id.to_owned() });
+    }
+
+    let home = paths::data_dir()?.join("homes").join(character_id);
+    std::fs::create_dir_all(&home)?;
+    Ok(Some(home))
+}
+
+/// Points the client's home and XDG directories at `home`. Java reads its
+/// home from the user database rather than `$HOME`, so it's passed through
+/// `JAVA_TOOL_OPTIONS` as well.
+fn use_home(home: &Path) {
+    std::env::set_var("HOME", home);
+    #[cfg(windows)]
+    std::env::set_var("USERPROFILE", home);
+    std::env::set_var("XDG_CONFIG_HOME", home.join(".config"));
+    std::env::set_var("XDG_DATA_HOME", home.join(".local").join("share"));
+    std::env::set_var("XDG_CACHE_HOME", home.join(".cache"));
+
+    let user_home = format!("-Duser.home={}", home.display());
+    let java_options = match std::env::var("JAVA_TOOL_OPTIONS") {
+        Ok(options) if !options.is_empty() => format!("{options} {user_home}"),
+        _ => user_home,
+    };
+    std::env::set_var("JAVA_TOOL_OPTIONS", java_options);
+}
+
+/// Startup notification tokens the desktop hands a launched entry, which the
+/// first window to appear should consume
+pub const STARTUP_VARS: &[&str] = &["DESKTOP_STARTUP_ID", "XDG_ACTIVATION_TOKEN"];
+
+/// The variables game clients read the session and character from
+pub fn session_env(session: Session, account: &Account) -> Vec<(&'static str, String)> {
+    vec![
+        ("JX_SESSION_ID", session.session_id),
+        ("JX_CHARACTER_ID", account.account_id.clone()),
+        ("JX_DISPLAY_NAME", account.display_name.clone()),
+    ]
+}
+
+/// The name of the session a character is cached under, other than `current`
+fn other_session(current: &Option<String>, character_id: &str) -> Option<AuthError> {
+    let name = |session: &Option<String>| session.clone().unwrap_or_else(|| "default".to_owned());
+    sessions::list().ok()?.into_iter().filter(|session| session != current).find_map(|session| {
+        let account = Client::new(session.clone()).cached_account(character_id)?;
+        Some(AuthError::CharacterInOtherSession { name: account.display_name, session: name(&session), current: name(current) })
+    })
+}
+
+/// Finds the character among the session's like [`find_account`], telling a
+/// character cached for another session apart from an unknown one, and
+/// checking its user hash against the session's offline list
+pub fn find_session_account<'a>(client: &Client, accounts: &'a [Account], character_id: &str) -> Result<&'a Account> {
+    let account = match find_account(accounts, character_id) {
+        Err(error @ AuthError::CharacterNotFound { .. }) => {
+            return Err(other_session(client.session_name(), character_id).unwrap_or(error));
+        }
+        result => result?,
+    };
+
+    let cached = client.cached_account(character_id);
+    if cached.is_some_and(|cached| !cached.user_hash.is_empty() && cached.user_hash != account.user_hash) {
+        return Err(AuthError::CharacterAccountChanged { name: account.display_name.clone() });
+    }
+    Ok(account)
+}
+
+/// Checks the characters belong to the login a desktop entry was created for,
+/// going by the fingerprint embedded in it. With a terminal the user can
+/// launch anyway; without one it fails, as the click would otherwise
+/// silently start whichever character the new login has under that ID.
+pub fn check_fingerprint(client: &Client, accounts: &[Account], expected: Option<&str>) -> Result<()> {
+    let (Some(expected), Some(actual)) = (expected, client::fingerprint(accounts)) else {
+        return Ok(());
+    };
+    if expected.eq_ignore_ascii_case(&actual) {
+        return Ok(());
+    }
+
+    let session = client.session_name().clone().unwrap_or_else(|| "default".to_owned());
+    if !prompt::is_interactive() {
+        return Err(AuthError::ShortcutForOtherLogin { session });
+    }
+    output::renderer().status(&format!(
+        "{} This shortcut was created for a different login to session '{session}'",
+        theme::warning("Warning:").bold()
+    ));
+    if prompt::ask("Launch anyway?")? { Ok(()) } else { Err(AuthError::Cancelled) }
+}
+
+/// Finds a character by its 1-based position in the list, display name
+/// (ignoring case) or ID
+pub fn select_account<'a>(accounts: &'a [Account], selector: &str) -> Result<&'a Account> {
+    let by_index = selector
+        .parse::<usize>()
+        .ok()
+        .and_then(|index| index.checked_sub(1))
+        .and_then(|index| accounts.get(index));
+
+    match by_index {
+        Some(account) => Ok(account),
+        None => accounts
+            .iter()
+            .find(|a| a.display_name.eq_ignore_ascii_case(selector))
+            .map_or_else(|| find_account(accounts, selector), Ok),
+    }
+}
+
+fn env_pattern_matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => pattern == name,
+    }
+}
+
+/// Drops the variables `[env]` keeps from the client. `PATH` always stays,
+/// as the program is looked up in it.
+fn filter_env() {
+    let env = &config::get().env;
+    for (name, _) in std::env::vars_os() {
+        let name_str = name.to_string_lossy();
+        if name_str == "PATH" {
+            continue;
+        }
+
+        let allowed = env
+            .allow
+            .as_ref()
+            .is_none_or(|allow| allow.iter().any(|p| env_pattern_matches(p, &name_str)));
+        let denied = env.deny.iter().any(|p| env_pattern_matches(p, &name_str));
+        if !allowed || denied {
+            std::env::remove_var(&name);
+        }
+    }
+}
+
+/// Replaces this process with the client, or with `spawn` runs it as a child
+/// to record the time played. Its environment is ours filtered through
+/// `[env]`, then the character's home directory, the profile's own
+/// variables and the session's.
+pub fn exec(
+    session: Session,
+    account: &Account,
+    program: &str,
+    args: &[String],
+    extra_env: &BTreeMap<String, String>,
+    home: Option<&Path>,
+    spawn: bool,
+) -> Result<()> {
+    filter_env();
+    if let Some(home) = home {
+        use_home(home);
+    }
+    for (key, value) in extra_env {
+        std::env::set_var(key, value);
+    }
+    let vars = session_env(session, account);
+    for (key, value) in &vars {
+        std::env::set_var(key, value);
+    }
+
+    let args = args.to_vec();
+    let client_name = history::client_name(program, &args);
+    if let Err(e) = history::record(&account.account_id, client_name.clone()) {
+        warn!("Failed to record the launch: {e}");
+    }
+    if sandbox::needs_host_spawn(program) {
+        let mut vars = vars;
+        vars.extend(extra_env.iter().map(|(key, value)| (key.as_str(), value.clone())));
+        for key in STARTUP_VARS {
+            if let Ok(value) = std::env::var(key) {
+                vars.push((key, value));
+            }
+        }
+        let (host_program, host_args) = sandbox::host_command(program, args, &vars)?;
+        return start_client(&host_program, host_args, account, client_name, spawn);
+    }
+    start_client(program, args, account, client_name, spawn)
+}
+
+fn start_client(program: &str, args: Vec<String>, account: &Account, client_name: String, spawn: bool) -> Result<()> {
+    #[cfg(unix)]
+    if !spawn {
+        // The client keeps this process' ID
+        if let Err(e) = instances::record(account, &client_name, std::process::id()) {
+            warn!("Failed to record the running client: {e}");
+        }
+        return replace_process(program, args);
+    }
+    // Without exec(3) the client always runs as a child
+    #[cfg(not(unix))]
+    let _ = spawn;
+
+    let started_at = SystemTime::now();
+    let mut child = Command::new(program)
+        .args(args)
+        .spawn()
+        .map_err(|e| AuthError::ExecError {
+            program: program.to_owned(),
+            details: e.to_string(),
+        })?;
+    if let Err(e) = instances::record(account, &client_name, child.id()) {
+        warn!("Failed to record the running client: {e}");
+    }
+    let status = child.wait()?;
+    instances::forget(&account.account_id, child.id());
+    let played = started_at.elapsed().unwrap_or_default();
+    if let Err(e) = history::record_play(account, client_name, started_at, played) {
+        warn!("Failed to record the play time: {e}");
+    }
+    std::process::exit(status.code().unwrap_or(1));
+}
+
+#[cfg(unix)]
+fn replace_process(program: &str, args: Vec<String>) -> Result<()> {
+    let mut args_with_program = args;
+    args_with_program.insert(0, program.to_owned());
+    let error = exec::execvp(program, args_with_program);
+    Err(AuthError::ExecError {
+        program: program.to_owned(),
+        details: format!("System error (errno: {error})"),
+    })
+}
+
+/// Starts a hidden `auth-rs` helper command that outlives this process
+pub fn spawn_helper<I, S>(args: I) -> Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<std::ffi::OsStr>,
+{
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // Keep the helper out of the terminal's process group so closing it
+    // doesn't take the helper down too
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    command.spawn()?;
+    Ok(())
+}
+
+/// Runs the authorize flow in a child process, whose exit status says
+/// whether a session was stored
+pub fn reauthorize(session_name: &Option<String>) -> Result<()> {
+    if config::read_only() {
+        return Err(AuthError::ReadOnly { command: "authorize" });
+    }
+    let mut command = Command::new(std::env::current_exe()?);
+    command.arg("authorize");
+    if progress::format() == Some(ProgressFormat::JsonLines) {
+        command.arg("--progress").arg("json-lines");
+    }
+    if let Some(session_name) = session_name {
+        command.arg("--session-name").arg(session_name);
+    }
+
+    if !command.status()?.success() {
+        return Err(AuthError::AuthorizeFailed);
+    }
+
+    Ok(())
+}
+
+/// Re-authorizes ahead of starting a client. The authorize window shows
+/// first, so it gets the desktop's startup token and the client isn't handed
+/// a spent one.
+fn reauthorize_before_launch(session_name: &Option<String>) -> Result<()> {
+    reauthorize(session_name)?;
+    for key in STARTUP_VARS {
+        std::env::remove_var(key);
+    }
+    Ok(())
+}
+
+/// Warns when the session is estimated to expire within `[expiry]
+/// warn_minutes`, as the client would be logged out partway through. With a
+/// terminal to ask on, offers to log in again first.
+pub fn check_expiry(client: &Client) -> Result<()> {
+    let Some(remaining) = client.session().ok().and_then(|session| session.remaining()) else {
+        return Ok(());
+    };
+    if remaining > Duration::from_secs(config::get().expiry.warn_minutes * 60) {
+        return Ok(());
+    }
+
+    let message = if remaining.is_zero() {
+        "The session has probably expired".to_owned()
+    } else {
+        format!("The session expires in about {}", client::format_age(remaining))
+    };
+    output::renderer()
+        .status(&format!("{} {message}, the client may be logged out mid-login", theme::warning("Warning:").bold()));
+    if !config::read_only() && prompt::ask("Log in again first?")? {
+        reauthorize_before_launch(client.session_name())?;
+    }
+    Ok(())
+}
+
+/// Entry point used by desktop entries: validates the session against the
+/// accounts endpoint and re-authorizes when it's missing or expired
+pub async fn launch(
+    target: config::LaunchTarget,
+    fingerprint: Option<&str>,
+    spawn: bool,
+    override_restrictions: bool,
+) -> Result<()> {
+    let config::LaunchTarget { session_name, character_id, exec: program, args, env, .. } = target;
+    restrictions::check(&character_id, override_restrictions)?;
+    let client = Client::new(session_name.clone());
+    let accounts = match client.accounts(false, false).await {
+        Err(AuthError::SessionNotFound | AuthError::SessionExpired) => {
+            output::renderer().status(&theme::warning("Session expired, opening the authorize window..."));
+            reauthorize_before_launch(&session_name)?;
+            client.accounts(false, false).await?
+        }
+        result => {
+            let accounts = result?;
+            check_expiry(&client)?;
+            accounts
+        }
+    };
+
+    check_fingerprint(&client, &accounts, fingerprint)?;
+    let account = find_session_account(&client, &accounts, &character_id)?;
+    let home = isolated_home(&character_id, false)?;
+    exec(client.session()?, account, &program, &args, &env, home.as_deref(), spawn)
+}
@@ -0,0 +1,301 @@
+use std::{sync::{Arc, Mutex}, time::{SystemTime, UNIX_EPOCH}};
+
+use log::{debug, error, warn};
+use serde::Serialize;
+use tokio::{
+    runtime::Handle,
+    sync::{
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot,
+    },
+    task::JoinHandle,
+};
+use url::Url;
+
+use crate::{
+    client::{Account, Client},
+    error::{AuthError, Result},
+    flow::{parse_redirect, AuthFlow, Redirect, Step},
+    output,
+    progress::{self, Event as Progress},
+    theme,
+};
+
+/// What the login needs from the window showing its pages, so the flow can
+/// be driven without a display
+pub trait LoginWindow: Send + 'static {
+    /// Shows the page, failing when the window can't take it any more
+    fn open(&self, url: String) -> Result<()>;
+    /// Closes the window, the login being over either way
+    fn close(&self);
+}
+
+/// Summary printed by `authorize --print-only` once the session is stored
+#[derive(Serialize)]
+struct AuthorizeResult {
+    session_name: Option<String>,
+    accounts: usize,
+    /// Unix timestamp the tokens behind the session expire at
+    expires_at: u64,
+}
+
+fn print_result(client: &Client, accounts: &[Account], expires_at: SystemTime) -> Result<()> {
+    let result = AuthorizeResult {
+        session_name: client.session_name().clone(),
+        accounts: accounts.len(),
+        expires_at: expires_at.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default(),
+    };
+    println!("{}", serde_json::to_string(&result)?);
+    Ok(())
+}
+
+/// Once the session is stored, warms the accounts cache straight away, so
+/// `ls` and offline launches right after logging in don't wait on the network
+async fn finish(client: &Client, expires_at: SystemTime, print_only: bool) -> Result<()> {
+    let accounts = match client.accounts(false, true).await {
+        Ok(accounts) => accounts,
+        Err(e) if !print_only => {
+            warn!("Failed to prefetch characters: {e}");
+            vec![]
+        }
+        Err(e) => return Err(e),
+    };
+    if accounts.is_empty() {
+        warn!("Jagex returned no characters for this account; 'auth-rs ls' explains the likely causes");
+    }
+    if print_only {
+        print_result(client, &accounts, expires_at)?;
+    }
+    Ok(())
+}
+
+/// Pages Jagex shows instead of finishing the login, as a keyword in the
+/// lowercased URL or page title and what to tell the user
+const INTERVENTIONS: &[(&str, &str)] = &[
+    ("locked", "The account is locked. Follow the recovery steps in the window, or at support.runescape.com"),
+    ("unusual activity", "Jagex noticed unusual activity and wants to confirm it's you. Follow the steps in the window"),
+    ("suspicious", "Jagex noticed unusual activity and wants to confirm it's you. Follow the steps in the window"),
+    ("verify", "Jagex is asking to verify the account. Check your email or authenticator, then continue in the window"),
+    ("verification", "Jagex is asking to verify the account. Check your email or authenticator, then continue in the window"),
+    ("authenticator", "Enter the code from your authenticator app in the window to continue"),
+];
+
+/// Reports pages that need the user's attention, once each time a new one shows
+#[derive(Clone, Default)]
+struct InterventionNotice {
+    last: Arc<Mutex<Option<&'static str>>>,
+}
+
+impl InterventionNotice {
+    fn check(&self, text: &str) {
+        let text = text.to_lowercase();
+        let Some((_, reason)) = INTERVENTIONS.iter().find(|(keyword, _)| text.contains(keyword)) else {
+            return;
+        };
+
+        let Ok(mut last) = self.last.lock() else {
+            return;
+        };
+        if *last != Some(*reason) {
+            *last = Some(*reason);
+            output::renderer().status(&format!("{} {reason}", theme::warning("Action needed:").bold()));
+            progress::emit(Progress::ActionRequired { reason });
+        }
+    }
+}
+
+/// What the window does with each page it navigates to: redirects go to the
+/// flow instead of loading, and pages asking for the user's attention are
+/// pointed out
+#[derive(Clone)]
+pub struct Navigation {
+    redirects: UnboundedSender<Redirect>,
+    /// The consent redirect is answered by the local listener, and loads
+    consent_listener: bool,
+    notice: InterventionNotice,
+}
+
+impl Navigation {
+    /// Whether the window should go on to load the page
+    pub fn allow(&self, url: &str) -> bool {
+        // Only the path, as redirect parameters are long random strings
+        if let Ok(url) = Url::parse(url) {
+            self.notice.check(url.path());
+        }
+        match parse_redirect(url) {
+            Some(Redirect::Consent { .. }) if self.consent_listener => true,
+            Some(redirect) => {
+                self.redirect(redirect);
+                false
+            }
+            None => true,
+        }
+    }
+
+    pub fn title_changed(&self, title: &str) {
+        self.notice.check(title);
+    }
+
+    /// Hands over a redirect caught outside the window, like the consent
+    /// listener's
+    pub fn redirect(&self, redirect: Redirect) {
+        if let Err(e) = self.redirects.send(redirect) {
+            error!("Failed to send redirect message: {e}");
+        }
+    }
+}
+
+/// The navigation decisions for a window, and the redirects they hand over
+/// for [`drive`]
+pub fn navigation(consent_listener: bool) -> (Navigation, UnboundedReceiver<Redirect>) {
+    let (redirects, receiver) = unbounded_channel();
+    (Navigation { redirects, consent_listener, notice: InterventionNotice::default() }, receiver)
+}
+
+/// The login [`drive`] runs in the background, until it's done or the window
+/// is gone
+pub struct Driver {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<Result<()>>,
+    runtime: Handle,
+}
+
+impl Driver {
+    /// Stops the login wherever it's at, waiting on a redirect or on Jagex,
+    /// and waits for it to wind down. Called once the window has closed, so
+    /// nothing is left running after it. Gives how the login ended, closing
+    /// the window before it finished being [`AuthError::AuthorizeFailed`].
+    pub fn shutdown(self) -> Result<()> {
+        let _ = self.shutdown.send(());
+        self.runtime
+            .block_on(self.task)
+            .unwrap_or_else(|e| Err(AuthError::InvalidResponse(format!("The login stopped unexpectedly: {e}"))))
+    }
+}
+
+/// Drives the flow with the redirects the window and the consent listener
+/// catch, telling the window which page to show next, and closing it once
+/// the login is over either way. Must be called from within the runtime.
+pub fn drive(
+    client: Client,
+    mut flow: AuthFlow,
+    mut redirects: UnboundedReceiver<Redirect>,
+    window: impl LoginWindow,
+    print_only: bool,
+) -> Driver {
+    let (shutdown, stopped) = oneshot::channel();
+    let task = tokio::spawn(async move {
+        let login = async move {
+            let result = loop {
+                let Some(redirect) = redirects.recv().await else {
+                    break Err(AuthError::AuthorizeFailed);
+                };
+                match flow.advance(&client, redirect).await {
+                    Ok(Step::Open(url)) => {
+                        if let Err(e) = window.open(url) {
+                            break Err(e);
+                        }
+                    }
+                    Ok(Step::Done { expires_at }) => break finish(&client, expires_at, print_only).await,
+                    Err(e) => break Err(e),
+                }
+            };
+            window.close();
+            result
+        };
+
+        tokio::select! {
+            result = login => result,
+            _ = stopped => {
+                debug!("The login window closed, stopping the login");
+                Err(AuthError::AuthorizeFailed)
+            }
+        }
+    });
+
+    Driver { shutdown, task, runtime: Handle::current() }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{runtime::Runtime, sync::mpsc::UnboundedSender};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Shown {
+        Page(String),
+        Closed,
+    }
+
+    /// Reports what the login asked of it instead of showing anything
+    struct FakeWindow(UnboundedSender<Shown>);
+
+    impl LoginWindow for FakeWindow {
+        fn open(&self, url: String) -> Result<()> {
+            let _ = self.0.send(Shown::Page(url));
+            Ok(())
+        }
+
+        fn close(&self) {
+            let _ = self.0.send(Shown::Closed);
+        }
+    }
+
+    /// A started flow, the channel its redirects go in, and what the window
+    /// was asked to show
+    fn login(runtime: &Runtime, flow: AuthFlow) -> (Driver, UnboundedSender<Redirect>, UnboundedReceiver<Shown>) {
+        let (redirects, redirect_receiver) = unbounded_channel();
+        let (shown, shown_receiver) = unbounded_channel();
+        let _guard = runtime.enter();
+        let driver = drive(Client::new(None), flow, redirect_receiver, FakeWindow(shown), false);
+        (driver, redirects, shown_receiver)
+    }
+
+    fn started_flow() -> AuthFlow {
+        let mut flow = AuthFlow::new(None, crate::env::oauth(&None), None);
+        flow.start().unwrap();
+        flow
+    }
+
+    #[test]
+    fn opens_the_pages_the_flow_asks_for() {
+        let runtime = Runtime::new().unwrap();
+        let mut flow = started_flow();
+        // An expired login page restarts the login without asking Jagex
+        flow.expire();
+        let state = flow.login_state().unwrap();
+        let (driver, redirects, mut shown) = login(&runtime, flow);
+
+        redirects.send(Redirect::Auth { code: "abc".to_owned(), state }).unwrap();
+        let Some(Shown::Page(url)) = runtime.block_on(shown.recv()) else {
+            panic!("no page was opened")
+        };
+        assert!(url.starts_with(crate::env::ORIGIN), "{url}");
+
+        // Nothing left to catch redirects ends the login
+        drop(redirects);
+        assert_eq!(runtime.block_on(shown.recv()), Some(Shown::Closed));
+        assert!(matches!(driver.shutdown(), Err(AuthError::AuthorizeFailed)));
+    }
+
+    #[test]
+    fn closes_the_window_on_an_error() {
+        let runtime = Runtime::new().unwrap();
+        let (driver, redirects, mut shown) = login(&runtime, started_flow());
+
+        redirects.send(Redirect::Auth { code: "abc".to_owned(), state: "forged".to_owned() }).unwrap();
+        assert_eq!(runtime.block_on(shown.recv()), Some(Shown::Closed));
+        assert!(matches!(driver.shutdown(), Err(AuthError::InvalidResponse(_))));
+    }
+
+    #[test]
+    fn stops_when_shut_down() {
+        let runtime = Runtime::new().unwrap();
+        let (driver, _redirects, mut shown) = login(&runtime, started_flow());
+
+        assert!(matches!(driver.shutdown(), Err(AuthError::AuthorizeFailed)));
+        // The window is already gone, so it isn't closed again
+        assert!(shown.try_recv().is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use log::warn;
+
+use crate::error::Result;
+
+/// Served at the redirect URI. The tokens arrive in the fragment, which
+/// browsers never send, so the page sends them back as a query instead.
+const FORWARD_PAGE: &str = "<!doctype html>\
+<title>auth-rs</title>\
+<script>location.replace('/callback?' + location.hash.slice(1))</script>";
+
+const DONE_PAGE: &str = "<!doctype html>\
+<title>auth-rs</title>\
+<p>Signed in. You can close this window.</p>";
+
+/// Requests larger than this are dropped, an ID token is a few kilobytes
+const MAX_REQUEST: usize = 64 * 1024;
+/// Connections that don't send their request in time are dropped, so a
+/// stalled one can't keep the redirect from being heard
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// The path of the request, after reading its head
+fn read_request(stream: &TcpStream) -> std::io::Result<Option<String>> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut read = request_line.len();
+    let mut line = String::new();
+    while read < MAX_REQUEST {
+        line.clear();
+        let n = reader.read_line(&mut line)?;
+        if n == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+        read += n;
+    }
+
+    let mut parts = request_line.split_whitespace();
+    Ok(match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if read < MAX_REQUEST => Some(path.to_owned()),
+        _ => None,
+    })
+}
+
+fn handle(mut stream: TcpStream, base: &str, on_redirect: &impl Fn(String)) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    match read_request(&stream)? {
+        Some(path) if path.starts_with("/callback?") => {
+            on_redirect(format!("{base}{path}"));
+            respond(&mut stream, "200 OK", DONE_PAGE)
+        }
+        Some(path) if path == "/" || path.starts_with("/?") => respond(&mut stream, "200 OK", FORWARD_PAGE),
+        Some(_) => respond(&mut stream, "404 Not Found", ""),
+        None => respond(&mut stream, "400 Bad Request", ""),
+    }
+}
+
+/// Listens on a random localhost port for the consent redirect, calling
+/// `on_redirect` with the full callback URL. Returns the redirect URI to
+/// send to Jagex; the listener lives until the process exits.
+pub fn start(on_redirect: impl Fn(String) + Send + 'static) -> Result<String> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let base = format!("http://localhost:{}", listener.local_addr()?.port());
+
+    let redirect_uri = base.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.and_then(|stream| handle(stream, &base, &on_redirect));
+            if let Err(e) = result {
+                warn!("Consent listener: {e}");
+            }
+        }
+    });
+
+    Ok(redirect_uri)
+}
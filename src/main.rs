@@ -1,40 +1,175 @@
-use clap::{Parser, Subcommand};
-use client::Client;
+use std::{future::Future, net::SocketAddr, path::PathBuf, sync::OnceLock, time::{Duration, Instant}};
+
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use client::{Account, Client};
+use config::{ClientOptions, Config, LaunchTarget};
+use game::Game;
 use console::style;
-use error::AuthError;
+use output::Format;
+use presets::Preset;
+use progress::ProgressFormat;
 
+mod aliases;
+mod avatars;
+mod batch;
 mod browser;
 mod client;
+mod completions;
+mod clipboard;
+mod config;
+mod connectivity;
+mod daemon;
 mod desktop;
+mod docs;
+mod doctor;
 mod env;
+mod envfile;
+mod flow;
+mod focus;
 mod error;
+mod game;
+mod gc;
+mod history;
+mod init;
+mod instances;
+mod launch;
+mod login;
+mod loopback;
+mod metrics;
+mod network;
+mod output;
+mod paths;
+mod placement;
+mod policy;
+mod presets;
+mod progress;
+mod proxy;
+mod prompt;
+mod recovery;
+mod restrictions;
+mod sandbox;
+mod schedule;
+mod sessions;
+mod stats;
+mod status;
+mod tags;
+mod theme;
+mod watch;
+mod webhook;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, after_long_help = docs::troubleshooting())]
 struct CommandLineArgs {
+    /// Print detailed help, including troubleshooting for each error
+    #[arg(long, global = true, action = ArgAction::HelpLong)]
+    help_long: Option<bool>,
+
+    /// Output format; table lays `ls` out in columns, json and csv print the
+    /// results for programs (with json, errors too, as a JSON object on
+    /// stderr), and quiet prints only the essential values
+    #[arg(long, global = true, value_enum, default_value_t = Format::Human)]
+    format: Format,
+
+    /// Print only the values scripts need and no status messages, like
+    /// `--format quiet`
+    #[arg(short, long, global = true, conflicts_with = "format")]
+    quiet: bool,
+
+    /// Report the steps of the authorize flow on stderr as they happen
+    #[arg(long, global = true, value_enum)]
+    progress: Option<ProgressFormat>,
+
+    /// Another admin-managed config layered over `/etc/auth-rs/config.toml`,
+    /// which still applies, and whose `[enforce]` still wins
+    #[arg(long, global = true, value_name = "PATH")]
+    system_config: Option<PathBuf>,
+
+    /// Refuse commands that change the stored sessions, caches or desktop
+    /// entries, for shared machines
+    #[arg(long, global = true)]
+    read_only: bool,
+
+    /// Local IP address or network interface the requests to Jagex leave
+    /// from, over `[network] bind`. Interfaces work on Linux and macOS only.
+    #[arg(long, global = true, value_name = "ADDRESS|INTERFACE")]
+    bind: Option<String>,
+
+    /// IP version to connect to Jagex over, over `[network] ip_version`;
+    /// `4` skips a broken IPv6 route instead of waiting on it
+    #[arg(long, global = true, value_enum, value_name = "VERSION")]
+    ip_version: Option<network::IpVersion>,
+
     #[command(subcommand)]
     command: AppCommand,
 }
 
 #[derive(Subcommand, Debug)]
 enum AppCommand {
+    /// Set up auth-rs interactively: authorize, pick a character and client, and write a config file
+    Init,
+
     /// Start the authentication flow to authorize with your Jagex account
     Authorize {
         #[arg(short, long)]
         session_name: Option<String>,
+        /// Log in to several sessions one after another, e.g. `main,alt1,alt2`
+        /// (`default` is the unnamed session)
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["session_name", "print_only", "consent_listener", "email", "placement", "always_on_top", "zoom"])]
+        sessions: Vec<String>,
+        /// Print a JSON summary of the session to stdout when done, for scripts
+        #[arg(long)]
+        print_only: bool,
+        /// Receive the consent redirect on a local HTTP listener with a random
+        /// port, instead of catching it in the window
+        #[arg(long)]
+        consent_listener: bool,
+        /// Prefill the login page with this email, and remember it for the session
+        #[arg(long)]
+        email: Option<String>,
+        /// Where the login window opens, instead of `[window] placement`
+        #[arg(long, value_enum)]
+        placement: Option<placement::Placement>,
+        /// Keep the login window above the others
+        #[arg(long)]
+        always_on_top: bool,
+        /// Show the login page this many times bigger, e.g. `1.5`, instead of
+        /// `[window] zoom`
+        #[arg(long)]
+        zoom: Option<f64>,
     },
 
     /// List all characters associated with the authorized Jagex account
     #[command(name = "ls")]
     ListCharacters {
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "tag")]
         session_name: Option<String>,
+        /// List the characters tagged in the config file, across sessions
+        #[arg(short, long)]
+        tag: Option<String>,
         /// Use offline cache to fetch characters
         #[arg(short, long)]
         offline: bool,
+        /// Fail instead of using an offline character list older than DAYS
+        #[arg(long, value_name = "DAYS", requires = "offline")]
+        max_age: Option<u64>,
         /// Stores list of characters for offline use
         #[arg(short, long)]
         write_cache: bool,
+        /// Copy a character's ID to the clipboard, by its position in the list
+        /// (starting at 1), display name or ID
+        #[arg(long, value_name = "INDEX|NAME", conflicts_with = "watch")]
+        copy: Option<String>,
+        /// Keep refreshing the list, every SECONDS (60 by default)
+        #[arg(long, value_name = "SECONDS", num_args = 0..=1, default_missing_value = "60", conflicts_with_all = ["tag", "offline"])]
+        watch: Option<u64>,
+        /// Show more columns in the table: tags, RuneLite profile, user hash
+        /// and last launch. Implies `--format table`.
+        #[arg(long, conflicts_with = "watch")]
+        long: bool,
+        /// Also point out characters added to or removed from the account
+        /// since the offline list was written
+        #[arg(long, conflicts_with_all = ["watch", "offline", "tag"])]
+        diff: bool,
     },
 
     /// Execute a program with Jagex session credentials (e.g., RuneLite, OSRS client)
@@ -44,20 +179,219 @@ enum AppCommand {
         /// Use offline cache to fetch characters
         #[arg(short, long)]
         offline: bool,
+        /// Fail instead of using an offline character list older than DAYS
+        #[arg(long, value_name = "DAYS", requires = "offline")]
+        max_age: Option<u64>,
+        /// Launch profile from the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+        /// Character ID to use for authentication
+        #[arg(short, long, help = "Character ID from 'ls' command")]
+        character_id: Option<String>,
+        /// Pick one of the characters with this tag, asking if there are several
+        #[arg(short, long, conflicts_with_all = ["session_name", "character_id"])]
+        tag: Option<String>,
+        /// Game the client is for, picking the preset's client (`osrs` unless
+        /// the profile or config file says otherwise)
+        #[arg(long, value_enum)]
+        game: Option<Game>,
+        /// Launch a known client instead of naming the executable, any
+        /// remaining arguments are passed to the client
+        #[arg(long, value_enum)]
+        preset: Option<Preset>,
+        /// Run the client as a child instead of replacing auth-rs, to record
+        /// the time played for `stats`
+        #[arg(long)]
+        spawn: bool,
+        /// Launch even when `[restrictions]` don't allow it, after typing
+        /// the override passphrase
+        #[arg(long)]
+        override_restrictions: bool,
+        /// Launch even when the character is already running in a client
+        /// auth-rs started
+        #[arg(long)]
+        allow_multiple: bool,
+        /// Bring the character's running client to the front instead of
+        /// launching another, when it can be found
+        #[arg(long, conflicts_with = "allow_multiple")]
+        focus_existing: bool,
+        /// Launch without asking Jagex whether the character belongs to the
+        /// session, even when it isn't in the offline list
+        #[arg(long, conflicts_with = "offline")]
+        no_verify_character: bool,
+        /// Ask Jagex for the characters even when this one is in the offline
+        /// list, and check it still belongs to the same account
+        #[arg(long, conflicts_with_all = ["offline", "no_verify_character"])]
+        verify_character: bool,
+        /// Fingerprint of the login a desktop entry was created for, to
+        /// warn when the session now belongs to another account
+        #[arg(long, hide = true)]
+        fingerprint: Option<String>,
+        /// Start your shell with the session variables set, running the
+        /// program through `$SHELL -c` if one is given
+        #[arg(long, conflicts_with = "preset")]
+        shell: bool,
+        /// Run the client from this Docker/Podman image, re-authorizing first
+        /// if the session has expired. The program and arguments are passed
+        /// to the image
+        #[arg(long, value_name = "IMAGE", conflicts_with_all = ["preset", "shell", "offline"])]
+        container: Option<String>,
+        /// Run the client in a bubblewrap sandbox that only sees a private
+        /// home directory, the display and the network
+        #[arg(long)]
+        sandbox: bool,
+        /// Give the character its own home directory, keeping client settings,
+        /// caches and screenshots apart from other characters
+        #[arg(long)]
+        isolate_home: bool,
+        /// Name or path of the executable to run
+        exec: Option<String>,
+        /// Arguments to pass to the program. Everything after the program is
+        /// passed on, flags included; use `--` before arguments for a preset
+        #[arg(
+            help = "Additional arguments for the program",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
+        args: Vec<String>,
+    },
+
+    /// Validate the session, re-authorizing if it has expired, then execute a program
+    #[command(hide = true)]
+    Launch {
+        #[arg(short, long)]
+        session_name: Option<String>,
+        /// Launch profile from the config file
+        #[arg(short, long)]
+        profile: Option<String>,
         /// Character ID to use for authentication
         #[arg(short, long, help = "Character ID from 'ls' command")]
-        character_id: String,
+        character_id: Option<String>,
+        /// Game the client is for, picking the preset's client (`osrs` unless
+        /// the profile or config file says otherwise)
+        #[arg(long, value_enum)]
+        game: Option<Game>,
+        /// Launch a known client instead of naming the executable, any
+        /// remaining arguments are passed to the client
+        #[arg(long, value_enum)]
+        preset: Option<Preset>,
+        /// Run the client as a child instead of replacing auth-rs, to record
+        /// the time played for `stats`
+        #[arg(long)]
+        spawn: bool,
+        /// Launch even when `[restrictions]` don't allow it, after typing
+        /// the override passphrase
+        #[arg(long)]
+        override_restrictions: bool,
+        /// Launch even when the character is already running in a client
+        /// auth-rs started
+        #[arg(long)]
+        allow_multiple: bool,
+        /// Bring the character's running client to the front instead of
+        /// launching another, when it can be found
+        #[arg(long, conflicts_with = "allow_multiple")]
+        focus_existing: bool,
+        /// Fingerprint of the login a desktop entry was created for, to
+        /// warn when the session now belongs to another account
+        #[arg(long, hide = true)]
+        fingerprint: Option<String>,
         /// Name or path of the executable to run
-        exec: String,
-        /// Arguments to pass to the program
-        #[arg(help = "Additional arguments for the program")]
+        exec: Option<String>,
+        /// Arguments to pass to the program. Everything after the program is
+        /// passed on, flags included; use `--` before arguments for a preset
+        #[arg(
+            help = "Additional arguments for the program",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
         args: Vec<String>,
     },
 
+    /// Check sessions against Jagex and update their offline character lists
+    Refresh {
+        #[arg(short, long, conflicts_with_all = ["tag", "all"])]
+        session_name: Option<String>,
+        /// Refresh every session with characters carrying this tag
+        #[arg(short, long, conflicts_with = "all")]
+        tag: Option<String>,
+        /// Refresh every stored session
+        #[arg(short, long)]
+        all: bool,
+        /// Sessions refreshed at once, over `[api] parallel_refreshes`
+        #[arg(short, long, value_name = "N")]
+        jobs: Option<usize>,
+        /// Print the characters added, removed or renamed since the last refresh
+        #[arg(long)]
+        diff: bool,
+    },
+
+    /// Print the session variables in dotenv format, or write them to an env
+    /// file for containers and launchers that read one
+    Env {
+        #[arg(short, long)]
+        session_name: Option<String>,
+        /// Use offline cache to fetch characters
+        #[arg(long)]
+        offline: bool,
+        /// Fail instead of using an offline character list older than DAYS
+        #[arg(long, value_name = "DAYS", requires = "offline")]
+        max_age: Option<u64>,
+        /// Launch profile from the config file
+        #[arg(short, long)]
+        profile: Option<String>,
+        /// Character ID to use for authentication
+        #[arg(short, long, help = "Character ID from 'ls' command")]
+        character_id: Option<String>,
+        /// File to write the variables to, readable only by you
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Delete the file again after this many seconds
+        #[arg(long, value_name = "SECONDS", requires = "output")]
+        delete_after: Option<u64>,
+    },
+
+    /// Serve text on the clipboard until something else is copied
+    #[command(hide = true)]
+    HoldClipboard {
+        text: String,
+    },
+
+    /// Delete an env file once its delay has passed
+    #[command(hide = true)]
+    RemoveEnvFile {
+        #[arg(long)]
+        after: u64,
+        path: PathBuf,
+    },
+
+    /// Print the game session ID, for use in your own tooling
+    Token {
+        #[arg(short, long)]
+        session_name: Option<String>,
+        /// Print only the session ID
+        #[arg(short, long)]
+        raw: bool,
+        /// Skip the confirmation prompt
+        #[arg(long)]
+        i_understand_the_risk: bool,
+    },
+
+    /// List all stored sessions
+    Sessions,
+
     /// Clear all stored authentication tokens and sessions
     Logout {
-        #[arg(short, long)]
+        #[arg(short, long, conflicts_with = "all")]
         session_name: Option<String>,
+        /// Log out of every stored session
+        #[arg(short, long)]
+        all: bool,
+        /// Only delete the offline character list, keeping the login
+        #[arg(long)]
+        cache_only: bool,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
     },
 
     /// Create a desktop entry for launching a game client
@@ -68,97 +402,802 @@ enum AppCommand {
         #[arg(short, long, help = "Display name for the desktop entry")]
         name: String,
         /// Character ID to use for authentication
-        #[arg(short, long, help = "Character ID from 'ls' command")]
-        character_id: String,
+        #[arg(short, long, help = "Character ID from 'ls' command", required_unless_present_any = ["all", "actions"])]
+        character_id: Option<String>,
+        /// Create an entry for every character, named "<NAME> (<display name>)"
+        #[arg(short, long, conflicts_with = "character_id")]
+        all: bool,
+        /// Create one entry with a "Launch as" action per character in its
+        /// right-click menu. Opening it launches `--character-id`, or the
+        /// first character.
+        #[arg(long, conflicts_with = "all")]
+        actions: bool,
+        /// Run the entry in a terminal, to see console output and errors
+        #[arg(long)]
+        terminal: bool,
+        /// Terminal emulator to run the entry in, e.g. "foot" or "gnome-terminal --"
+        #[arg(long, value_name = "COMMAND")]
+        terminal_command: Option<String>,
+        /// Game the client is for, picking the preset's client (`osrs` unless
+        /// the profile or config file says otherwise)
+        #[arg(long, value_enum)]
+        game: Option<Game>,
+        /// Launch a known client instead of naming the executable, any
+        /// remaining arguments are passed to the client
+        #[arg(long, value_enum)]
+        preset: Option<Preset>,
         /// Name or path of the executable to run
-        exec: String,
-        /// Arguments to pass to the program
-        #[arg(help = "Additional arguments for the program")]
+        #[arg(required_unless_present = "preset")]
+        exec: Option<String>,
+        /// Arguments to pass to the program. Everything after the program is
+        /// passed on, flags included; use `--` before arguments for a preset
+        #[arg(
+            help = "Additional arguments for the program",
+            trailing_var_arg = true,
+            allow_hyphen_values = true
+        )]
         args: Vec<String>,
     },
+
+    /// Summarize the time played per character and week, as recorded by
+    /// launching with `--spawn`
+    Stats {
+        /// Number of recent weeks to list
+        #[arg(long, default_value_t = 8)]
+        weeks: u64,
+    },
+
+    /// Remove caches of sessions that no longer exist, rotate logs and
+    /// enforce the `[gc]` size limits
+    Gc {
+        /// Print what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Manage launch profiles
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+
+    /// Manage the desktop entries auth-rs made
+    Desktop {
+        #[command(subcommand)]
+        command: DesktopCommand,
+    },
+
+    /// Check the credential store, config and session for problems
+    Doctor {
+        #[arg(short, long)]
+        session_name: Option<String>,
+    },
+
+    /// Open the Jagex account settings, e.g. to fix 2FA or linked characters
+    OpenAccountSettings {
+        #[arg(short, long)]
+        session_name: Option<String>,
+        /// Open them in the system's browser, which isn't signed in, instead
+        /// of a window sharing the login window's cookies
+        #[arg(long)]
+        system_browser: bool,
+    },
+
+    /// Generate documentation for packagers
+    Docs {
+        #[command(subcommand)]
+        command: DocsCommand,
+    },
+
+    /// Print a shell completion script, e.g. `auth-rs completions bash >> ~/.bashrc`
+    Completions {
+        #[arg(value_enum)]
+        shell: completions::Shell,
+        /// Instead of printing the script, source it in the shell and check
+        /// it completes a set of command lines as expected
+        #[arg(long)]
+        check: bool,
+    },
+
+    /// Print the candidates for the last of the words, for completion scripts
+    #[command(hide = true)]
+    Complete {
+        #[arg(last = true)]
+        words: Vec<String>,
+    },
+
+    /// Keep every session refreshed in the background
+    Daemon {
+        #[command(subcommand)]
+        command: DaemonCommand,
+    },
+}
+
+impl AppCommand {
+    /// Whether the command edits the user's config file
+    fn edits_config(&self) -> bool {
+        matches!(self, AppCommand::Init | AppCommand::Profile { .. })
+    }
+
+    /// The command's name when it changes stored sessions, caches or files
+    /// outside of launching, so read-only mode can refuse it
+    fn writes_state(&self) -> Option<&'static str> {
+        match self {
+            AppCommand::Init => Some("init"),
+            AppCommand::ListCharacters { write_cache: true, .. } => Some("ls --write-cache"),
+            AppCommand::Authorize { .. } => Some("authorize"),
+            AppCommand::Refresh { .. } => Some("refresh"),
+            AppCommand::Logout { .. } => Some("logout"),
+            AppCommand::CreateDesktopEntry { .. } => Some("create-desktop-entry"),
+            AppCommand::Desktop { .. } => Some("desktop"),
+            AppCommand::Gc { dry_run: false } => Some("gc"),
+            AppCommand::Profile { .. } => Some("profile"),
+            AppCommand::Daemon { .. } => Some("daemon"),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum ProfileCommand {
+    /// Record the SHA-256 of the profile's executable, so launches refuse a
+    /// changed binary
+    Pin {
+        /// Name of the profile in the config file
+        name: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum DesktopCommand {
+    /// Make every entry again from the current config and characters, after
+    /// changing a client path or renaming characters
+    Sync,
+}
+
+#[derive(Subcommand, Debug)]
+enum DaemonCommand {
+    /// Refresh each session's character list on an interval until stopped
+    Run {
+        /// Seconds between refreshes (30 minutes by default)
+        #[arg(long, value_name = "SECONDS")]
+        interval: Option<u64>,
+        /// Serve Prometheus metrics on this address, e.g. `127.0.0.1:9464`
+        #[arg(long, value_name = "ADDR")]
+        metrics_addr: Option<SocketAddr>,
+        /// Refresh every session once and exit, for timers and cron
+        #[arg(long, conflicts_with = "metrics_addr")]
+        once: bool,
+    },
+    /// Ask the running daemon to refresh now instead of on its schedule
+    RefreshNow {
+        /// Only refresh this session
+        #[arg(short, long)]
+        session_name: Option<String>,
+    },
+    /// Register a Windows Scheduled Task that refreshes every session on
+    /// the daemon's interval
+    Install,
+    /// Start the daemon at login, with its settings from the config file
+    EnableAutostart {
+        /// Write and enable a systemd user unit instead of an XDG autostart entry
+        #[arg(long)]
+        systemd: bool,
+    },
 }
 
-#[tokio::main]
-async fn main() -> miette::Result<()> {
+#[derive(Subcommand, Debug)]
+enum DocsCommand {
+    /// Generate man pages from the command line definitions
+    Man {
+        /// Directory to write a page per command to, instead of printing the top-level page
+        #[arg(short, long)]
+        output_dir: Option<PathBuf>,
+    },
+}
+
+fn main() -> miette::Result<()> {
     miette::set_panic_hook();
     env_logger::init();
-    let cli = CommandLineArgs::parse();
+    let args = aliases::expand(CommandLineArgs::command(), std::env::args_os().collect())?;
+    let cli = CommandLineArgs::parse_from(args);
+    progress::init(cli.progress);
+    config::set_read_only(cli.read_only);
+    if let Some(path) = cli.system_config {
+        config::set_system_path(path);
+    }
+    if let Some(bind) = cli.bind {
+        network::set_bind(bind);
+    }
+    if let Some(ip_version) = cli.ip_version {
+        network::set_ip_version(ip_version);
+    }
+
+    output::init(cli.format, cli.quiet);
+
+    if let Err(error) = run(cli.command) {
+        if output::renderer().error(&error) {
+            std::process::exit(1);
+        }
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+/// Drives an async command on a single-threaded runtime, which only the
+/// commands that talk to Jagex pay for. The runtime is kept for the rest of
+/// the process, as pooled connections belong to the runtime that opened them.
+fn block_on<T>(future: impl Future<Output = error::Result<T>>) -> error::Result<T> {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    let runtime = match RUNTIME.get() {
+        Some(runtime) => runtime,
+        None => {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            RUNTIME.get_or_init(|| runtime)
+        }
+    };
+    runtime.block_on(future)
+}
+
+fn set_max_age(days: Option<u64>) {
+    if let Some(days) = days {
+        client::set_max_cache_age(Duration::from_secs(days * 24 * 60 * 60));
+    }
+}
 
-    match cli.command {
-        AppCommand::Authorize { session_name } => browser::authorize(session_name),
+/// What `--allow-multiple` and `--focus-existing` ask for when the
+/// character is already running
+fn on_running(allow_multiple: bool, focus_existing: bool) -> instances::OnRunning {
+    match (allow_multiple, focus_existing) {
+        (true, _) => instances::OnRunning::Allow,
+        (_, true) => instances::OnRunning::Focus,
+        _ => instances::OnRunning::Ask,
+    }
+}
+
+/// The daemon's refresh interval, 30 minutes unless set
+fn daemon_interval(interval: Option<u64>) -> Duration {
+    let interval = interval.or(config::get().daemon.interval).unwrap_or(30 * 60);
+    Duration::from_secs(interval.max(1))
+}
+
+fn run(command: AppCommand) -> error::Result<()> {
+    // The doctor reports a broken config file itself rather than failing on it
+    match Config::load() {
+        Ok(config) => config::init(config),
+        // Nor does completion, which falls back to the defaults, or the
+        // commands editing the user's config file, which read it themselves.
+        // `init` is how a broken one gets recreated.
+        Err(_) if command.edits_config() || matches!(command, AppCommand::Doctor { .. } | AppCommand::Complete { .. }) => (),
+        Err(error) => return Err(error),
+    }
+    if let Some(command) = command.writes_state().filter(|_| config::read_only()) {
+        return Err(error::AuthError::ReadOnly { command });
+    }
+    network::check()?;
+
+    match command {
+        AppCommand::Authorize { sessions, .. } if !sessions.is_empty() => {
+            let mut failed = None;
+            for (i, name) in sessions.iter().enumerate() {
+                output::renderer().note(&format!(
+                    "{} Logging in to session {}...",
+                    style(format!("[{}/{}]", i + 1, sessions.len())).dim(),
+                    style(name).bold()
+                ));
+                let session_name = Some(name.clone()).filter(|name| name != "default");
+                let fields = [("session", name.clone())];
+                match launch::reauthorize(&session_name) {
+                    Ok(()) => output::succeeded(name, "stored", &fields),
+                    Err(error) => {
+                        output::failed(name, &error, &fields);
+                        failed = Some(error);
+                    }
+                }
+            }
+
+            failed.map_or(Ok(()), Err)
+        }
+        AppCommand::Authorize { session_name, print_only, consent_listener, email, placement, always_on_top, zoom, .. } => {
+            // The webview's event loop takes over this thread, so the flow's
+            // requests run on worker threads instead
+            let runtime = tokio::runtime::Runtime::new()?;
+            let _guard = runtime.enter();
+            // A login page that can't load looks like a broken login, so
+            // the network is checked first
+            runtime.block_on(connectivity::check(&session_name))?;
+            let window = &config::get().window;
+            let placement = placement.unwrap_or(window.placement);
+            browser::authorize(
+                session_name,
+                print_only,
+                consent_listener,
+                email,
+                placement,
+                always_on_top || window.always_on_top,
+                zoom.unwrap_or(window.zoom),
+            )
+        }
+        AppCommand::ListCharacters {
+            tag: Some(tag),
+            offline,
+            max_age,
+            long,
+            ..
+        } => {
+            set_max_age(max_age);
+            let members = block_on(tags::members(&tag, offline))?;
+            let cache_ages = members
+                .iter()
+                .map(|m| Client::new(m.session_name.clone()).accounts_cache_age().ok().flatten())
+                .collect::<Vec<_>>();
+            let rows = members
+                .iter()
+                .zip(cache_ages)
+                .map(|(m, cache_age)| output::Row { account: &m.account, session_name: &m.session_name, cache_age })
+                .collect::<Vec<_>>();
+            output::renderer().accounts(&rows, long, true);
+            Ok(())
+        }
         AppCommand::ListCharacters { 
             session_name, 
             offline,
-            write_cache 
+            write_cache,
+            copy,
+            watch,
+            max_age,
+            long,
+            diff,
+            ..
         } => {
+            set_max_age(max_age);
             let client = Client::new(session_name);
-            let accounts = client.accounts(offline, write_cache).await?;
-            for account in accounts {
-                println!(
-                    "  {} {} (ID: {})",
-                    style("•").cyan(),
-                    style(&account.display_name).green().bold(),
-                    style(account.account_id.to_string()).bold()
-                );
+            if let Some(interval) = watch {
+                return block_on(watch::run(&client, Duration::from_secs(interval.max(1)), write_cache));
+            }
+
+            let (accounts, changes) = block_on(client.accounts_with_diff(offline, write_cache))?;
+            if accounts.is_empty() {
+                return Err(error::AuthError::NoCharacters);
+            }
+            let cache_age = client.accounts_cache_age().ok().flatten();
+            let rows = accounts
+                .iter()
+                .map(|account| output::Row { account, session_name: client.session_name(), cache_age })
+                .collect::<Vec<_>>();
+            output::renderer().accounts(&rows, long, false);
+
+            for rename in &changes.renamed {
+                output::print_rename(rename);
+                if config::read_only() {
+                    continue;
+                }
+                for entry in desktop::rename_character(rename)? {
+                    output::renderer().record(
+                        &format!("    Desktop entry updated: {}", style(entry.display()).dim()),
+                        &[("desktop_entry", entry.display().to_string())],
+                    );
+                }
+            }
+            if diff {
+                output::print_diff(&changes, false);
+            }
+
+            if let Some(selector) = copy {
+                let account = launch::select_account(&accounts, &selector)?;
+                clipboard::copy(&account.account_id)?;
+                output::renderer()
+                    .status(&format!("Copied the ID of {} to the clipboard", theme::highlight(&account.display_name)));
             }
             Ok(())
         }
+        AppCommand::Init => block_on(init::run()),
         AppCommand::Exec {
             session_name,
             offline,
+            max_age,
+            profile,
             character_id,
+            tag,
+            game,
+            preset,
+            spawn,
+            override_restrictions,
+            allow_multiple,
+            focus_existing,
+            no_verify_character,
+            verify_character,
+            fingerprint,
+            shell,
+            container,
+            sandbox,
+            isolate_home,
             exec,
             args,
         } => {
-            let client = Client::new(session_name);
-            let session = client.session()?;
-            let accounts = client.accounts(offline, false).await?;
-
-            if let Some(account) = accounts.iter().find(|a| a.account_id == character_id) {
-                std::env::set_var("JX_SESSION_ID", session.session_id);
-                std::env::set_var("JX_CHARACTER_ID", &account.account_id);
-                std::env::set_var("JX_DISPLAY_NAME", &account.display_name);
-
-                let mut args_with_program = args.clone();
-                args_with_program.insert(0, exec.clone());
-                let error = exec::execvp(&exec, args_with_program);
-                Err(AuthError::ExecError {
-                    program: exec.clone(),
-                    details: format!("System error (errno: {error})"),
-                })
+            set_max_age(max_age);
+            let (session_name, character_id) = match tag {
+                Some(tag) => {
+                    let member = block_on(tags::pick(&tag, offline))?;
+                    (member.session_name, Some(member.account.account_id))
+                }
+                None => (session_name, character_id),
+            };
+
+            if let Some(image) = container {
+                let (session_name, character_id) =
+                    config::get().resolve_character(profile, session_name, character_id)?;
+                if !instances::check(&character_id, on_running(allow_multiple, focus_existing))? {
+                    return Ok(());
+                }
+                policy::check_container(&image)?;
+                let args = launch::with_runelite_profile(&character_id, &image, &presets::client_args(exec, args));
+                let (runtime, args) = presets::container_command(&image, &args)?;
+                let target = LaunchTarget {
+                    session_name,
+                    character_id,
+                    exec: runtime,
+                    args,
+                    env: Default::default(),
+                    sha256: None,
+                };
+                block_on(launch::launch(target, fingerprint.as_deref(), spawn, override_restrictions))
             } else {
-                let available_chars = accounts
-                    .iter()
-                    .map(|a| format!("  • {} (ID: {})", a.display_name, a.account_id))
-                    .collect::<Vec<_>>()
-                    .join("\n");
+                // Without a command, the shell itself is the client
+                let (exec, args, shell) = match exec {
+                    None if shell && preset.is_none() => {
+                        let (shell, args) = launch::shell_command(None, args);
+                        (Some(shell), args, false)
+                    }
+                    exec => (exec, args, shell),
+                };
+                let client = ClientOptions { game, preset, exec, args };
+                let target = config::get().resolve_launch(profile, session_name, character_id, client)?;
+                restrictions::check(&target.character_id, override_restrictions)?;
+                if !instances::check(&target.character_id, on_running(allow_multiple, focus_existing))? {
+                    return Ok(());
+                }
+                // The client is checked, not what it's wrapped in below
+                policy::check(&target.exec)?;
+                policy::verify_pin(&target.exec, &target.sha256)?;
+                let args = launch::with_runelite_profile(&target.character_id, &target.exec, &target.args);
+                let (exec, args) = if shell {
+                    launch::shell_command(Some(target.exec), args)
+                } else {
+                    (target.exec, args)
+                };
+                let home = launch::isolated_home(&target.character_id, isolate_home)?;
+                // The sandbox mounts the home directory itself
+                let (exec, args, home) = if sandbox {
+                    let (exec, args) = sandbox::bwrap_command(&exec, args, home.as_deref())?;
+                    (exec, args, None)
+                } else {
+                    (exec, args, home)
+                };
+                let client = Client::new(target.session_name);
+                launch::check_expiry(&client)?;
+                let session = client.session()?;
+                // A character already in the offline list is trusted as it is,
+                // saving a request to Jagex on every launch
+                let account = match client.cached_account(&target.character_id) {
+                    Some(account) if !offline && !verify_character => {
+                        launch::check_fingerprint(&client, &client.cached_accounts(), fingerprint.as_deref())?;
+                        account
+                    }
+                    _ if no_verify_character => {
+                        output::renderer().status(&format!(
+                            "{} {} isn't in the offline list, launching it without a display name",
+                            theme::warning("Warning:").bold(),
+                            style(&target.character_id).bold()
+                        ));
+                        Account { account_id: target.character_id.clone(), display_name: String::new(), user_hash: String::new() }
+                    }
+                    _ => {
+                        let accounts = if offline {
+                            client.offline_accounts()?
+                        } else {
+                            block_on(client.accounts(false, false))?
+                        };
+                        launch::check_fingerprint(&client, &accounts, fingerprint.as_deref())?;
+                        launch::find_session_account(&client, &accounts, &target.character_id)?.clone()
+                    }
+                };
+                launch::exec(session, &account, &exec, &args, &target.env, home.as_deref(), spawn)
+            }
+        }
+        AppCommand::Launch {
+            session_name,
+            profile,
+            character_id,
+            game,
+            preset,
+            spawn,
+            override_restrictions,
+            allow_multiple,
+            focus_existing,
+            fingerprint,
+            exec,
+            args,
+        } => {
+            let client = ClientOptions { game, preset, exec, args };
+            let target = config::get().resolve_launch(profile, session_name, character_id, client)?;
+            if !instances::check(&target.character_id, on_running(allow_multiple, focus_existing))? {
+                return Ok(());
+            }
+            policy::check(&target.exec)?;
+            policy::verify_pin(&target.exec, &target.sha256)?;
+            let args = launch::with_runelite_profile(&target.character_id, &target.exec, &target.args);
+            let target = LaunchTarget { args, ..target };
+            block_on(launch::launch(target, fingerprint.as_deref(), spawn, override_restrictions))
+        }
+        AppCommand::Refresh { session_name, tag, all, jobs, diff } => {
+            let session_names = match tag {
+                Some(tag) => tags::sessions(&tag)?,
+                None if all => sessions::list()?,
+                None => vec![session_name],
+            };
 
-                Err(AuthError::CharacterNotFound {
-                    character_id: character_id.clone(),
-                    available_chars,
-                })
+            let started = Instant::now();
+            let refreshed = block_on(async { Ok(batch::refresh(session_names, jobs).await) })?;
+            // Padded to line the results up as a table
+            let width = refreshed.iter().map(|refreshed| refreshed.name().chars().count()).max().unwrap_or_default();
+            let total = refreshed.len();
+            let mut failed = None;
+            let mut succeeded = 0;
+            for refreshed in refreshed {
+                let name = refreshed.name();
+                let subject = format!("{name:<width$}");
+                let elapsed = ("milliseconds", refreshed.elapsed.as_millis().to_string());
+                match refreshed.result {
+                    Ok((accounts, changes)) => {
+                        succeeded += 1;
+                        let fields = [("session", name.clone()), ("characters", accounts.len().to_string()), elapsed];
+                        output::succeeded(&subject, &format!("{} characters", accounts.len()), &fields);
+                        if diff && changes.is_empty() {
+                            output::renderer().note(&format!("    {}", style("No changes since the offline list").dim()));
+                        } else if diff {
+                            output::print_diff(&changes, true);
+                        }
+                    }
+                    Err(error) => {
+                        output::failed(&subject, &error, &[("session", name.clone()), elapsed]);
+                        failed = Some(error);
+                    }
+                }
             }
+
+            if total > 1 {
+                output::renderer().note(&format!(
+                    "{} of {total} sessions refreshed in {:.1}s",
+                    theme::highlight(succeeded),
+                    started.elapsed().as_secs_f64()
+                ));
+            }
+            failed.map_or(Ok(()), Err)
         }
-        AppCommand::Logout { session_name } => {
+        AppCommand::Env {
+            session_name,
+            offline,
+            max_age,
+            profile,
+            character_id,
+            output,
+            delete_after,
+        } => {
+            set_max_age(max_age);
+            let (session_name, character_id) =
+                config::get().resolve_character(profile, session_name, character_id)?;
             let client = Client::new(session_name);
-            client.logout()
+            let session = client.session()?;
+            let accounts = block_on(client.accounts(offline, false))?;
+            let account = launch::find_account(&accounts, &character_id)?;
+            let vars = launch::session_env(session, account);
+
+            let Some(output) = output else {
+                print!("{}", envfile::dotenv(&vars)?);
+                return Ok(());
+            };
+
+            envfile::write(&output, &vars)?;
+            if let Some(seconds) = delete_after {
+                envfile::schedule_removal(&output, seconds)?;
+            }
+            output::renderer().status(&format!("Env file written to {}", theme::highlight(output.display())));
+            Ok(())
+        }
+        AppCommand::HoldClipboard { text } => clipboard::hold(&text),
+        AppCommand::RemoveEnvFile { after, path } => envfile::remove_after(&path, after),
+        AppCommand::Token {
+            session_name,
+            raw,
+            i_understand_the_risk,
+        } => {
+            let session = Client::new(session_name).session()?;
+            if !i_understand_the_risk {
+                prompt::confirm(
+                    "The session ID gives full access to your characters, never share it. Print it?",
+                    "--i-understand-the-risk",
+                )?;
+            }
+
+            if raw {
+                println!("{}", session.session_id);
+            } else {
+                let human = format!("Session ID: {}", style(&session.session_id).bold());
+                output::renderer().value(&human, "session_id", &session.session_id);
+            }
+            Ok(())
+        }
+        AppCommand::Sessions => {
+            for session_name in sessions::list()? {
+                let name = match &session_name {
+                    Some(name) => theme::highlight(name.as_str()),
+                    None => theme::highlight("(default)"),
+                };
+                let remaining = Client::new(session_name.clone()).session().ok().and_then(|session| session.remaining());
+                let expiry = match remaining {
+                    Some(remaining) if remaining.is_zero() => format!(" {}", theme::warning("(probably expired)")),
+                    Some(remaining) => format!(" {}", style(format!("(expires in about {})", client::format_age(remaining))).dim()),
+                    None => String::new(),
+                };
+                let route = proxy::route(&session_name)?;
+                let via = match &route {
+                    proxy::Route::Environment => String::new(),
+                    proxy::Route::Direct => format!(" {}", style("direct").dim()),
+                    proxy::Route::Proxy(_) => format!(" {}", style(format!("via {}", route.describe().unwrap_or_default())).dim()),
+                };
+                let fields = [
+                    ("session", session_name.clone().unwrap_or_else(|| "default".to_owned())),
+                    ("expires_in_seconds", remaining.map(|r| r.as_secs().to_string()).unwrap_or_default()),
+                    ("proxy", match &route {
+                        proxy::Route::Direct => "direct".to_owned(),
+                        route => route.describe().unwrap_or_default(),
+                    }),
+                ];
+                output::renderer().record(&format!("  {} {}{}{}", theme::accent("•"), name, expiry, via), &fields);
+            }
+            Ok(())
+        }
+        AppCommand::Logout { session_name, all, cache_only, yes } => {
+            let session_names = if all { sessions::list()? } else { vec![session_name] };
+            if !yes {
+                let sessions = match &session_names[..] {
+                    [session_name] => format!("session '{}'", session_name.as_deref().unwrap_or("default")),
+                    session_names => format!("all {} sessions", session_names.len()),
+                };
+                let prompt = if cache_only {
+                    format!("Delete the offline character list of {sessions}?")
+                } else {
+                    format!("Log out of {sessions}? Getting back in takes a full login, 2FA included")
+                };
+                prompt::confirm(&prompt, "--yes")?;
+            }
+            for session_name in session_names {
+                let client = Client::new(session_name);
+                if cache_only {
+                    client.clear_accounts_cache()?;
+                } else {
+                    client.logout()?;
+                }
+            }
+            Ok(())
         }
         AppCommand::CreateDesktopEntry {
             session_name,
             name,
             character_id,
+            all,
+            actions,
+            terminal,
+            terminal_command,
+            game,
+            preset,
             exec,
             args,
         } => {
-            let desktop_entry = desktop::create_entry(session_name, name, character_id, exec, args)?;
-            println!(
-                "Desktop entry created: {}",
-                style(desktop_entry.display()).green().bold()
+            let spec = desktop::EntrySpec {
+                session_name,
+                name,
+                character_id,
+                game,
+                all,
+                actions,
+                preset,
+                exec,
+                args,
+                terminal,
+                terminal_command,
+            };
+            let desktop_entries = block_on(desktop::create(spec))?;
+            for desktop_entry in desktop_entries {
+                output::renderer().record(
+                    &format!("Desktop entry created: {}", theme::highlight(desktop_entry.display())),
+                    &[("desktop_entry", desktop_entry.display().to_string())],
+                );
+            }
+            Ok(())
+        }
+        AppCommand::Stats { weeks } => stats::run(weeks),
+        AppCommand::Gc { dry_run } => gc::run(dry_run),
+        AppCommand::Profile { command: ProfileCommand::Pin { name } } => {
+            let (executable, digest) = policy::pin(&name)?;
+            let human = format!("Pinned {} to {}", theme::highlight(executable.display()), style(&digest).bold());
+            output::renderer().record(&human, &[("executable", executable.display().to_string()), ("sha256", digest)]);
+            Ok(())
+        }
+        AppCommand::Desktop { command: DesktopCommand::Sync } => {
+            let mut failed = None;
+            for (name, result) in block_on(desktop::sync())? {
+                match result {
+                    Ok(files) => {
+                        let paths = files.iter().map(|file| file.display().to_string()).collect::<Vec<_>>();
+                        output::succeeded(&name, "", &[("entry", name.clone()), ("files", paths.join(";"))]);
+                        for path in &paths {
+                            output::renderer().note(&format!("    {}", style(path).dim()));
+                        }
+                    }
+                    Err(error) => {
+                        output::failed(&name, &error, &[("entry", name.clone())]);
+                        failed = Some(error);
+                    }
+                }
+            }
+
+            failed.map_or(Ok(()), Err)
+        }
+        AppCommand::Doctor { session_name } => block_on(doctor::run(session_name)),
+        AppCommand::OpenAccountSettings { system_browser: true, .. } => browser::open_external(env::ACCOUNT_SETTINGS),
+        AppCommand::OpenAccountSettings { session_name, .. } => browser::account_settings(&session_name),
+        AppCommand::Daemon { command: DaemonCommand::Run { interval, metrics_addr, once } } => {
+            let metrics_addr = if once { None } else { metrics_addr.or(config::get().daemon.metrics_addr) };
+            block_on(daemon::run(daemon_interval(interval), metrics_addr, once))
+        }
+        AppCommand::Daemon { command: DaemonCommand::RefreshNow { session_name } } => {
+            daemon::refresh_now(&session_name)?;
+            let human = match &session_name {
+                Some(name) => format!("Asked the daemon to refresh session {}", theme::highlight(name)),
+                None => "Asked the daemon to refresh every session".to_owned(),
+            };
+            output::renderer().record(&human, &[("requested", session_name.unwrap_or_else(|| "all".to_owned()))]);
+            Ok(())
+        }
+        AppCommand::Daemon { command: DaemonCommand::Install } => {
+            daemon::install(daemon_interval(None))?;
+            output::renderer().record(
+                &format!("Scheduled task {} registered", theme::highlight("auth-rs daemon")),
+                &[("scheduled_task", "auth-rs daemon".to_owned())],
             );
             Ok(())
         }
-    }.map_err(|error| {
-        error.into()
-    })
+        AppCommand::Daemon { command: DaemonCommand::EnableAutostart { systemd } } => {
+            let path = daemon::enable_autostart(systemd)?;
+            output::renderer().record(
+                &format!("Autostart enabled: {}", theme::highlight(path.display())),
+                &[("autostart", path.display().to_string())],
+            );
+            Ok(())
+        }
+        AppCommand::Completions { shell, check: true } => completions::check(shell, CommandLineArgs::command()),
+        AppCommand::Completions { shell, check: false } => {
+            print!("{}", completions::script(shell, "auth-rs"));
+            Ok(())
+        }
+        AppCommand::Complete { words } => {
+            for candidate in completions::candidates(CommandLineArgs::command(), &words) {
+                println!("{candidate}");
+            }
+            Ok(())
+        }
+        AppCommand::Docs { command: DocsCommand::Man { output_dir } } => {
+            let pages = docs::man(CommandLineArgs::command(), output_dir)?;
+            for page in pages {
+                output::renderer().record(
+                    &format!("Man page written: {}", theme::highlight(page.display())),
+                    &[("man_page", page.display().to_string())],
+                );
+            }
+            Ok(())
+        }
+    }
 }
 
@@ -0,0 +1,134 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+
+use crate::error::Result;
+
+/// Scrapers that don't send their request in time are dropped, as requests
+/// are answered one at a time
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Default)]
+struct SessionMetrics {
+    successes: u64,
+    failures: u64,
+    valid: bool,
+    characters: usize,
+    /// Unix timestamp of the last refresh
+    last_refresh: u64,
+    latency_sum: f64,
+    latency_count: u64,
+}
+
+/// Refresh results of the daemon, by session name, served in the Prometheus
+/// text format
+#[derive(Clone, Default)]
+pub struct Metrics {
+    sessions: Arc<Mutex<BTreeMap<String, SessionMetrics>>>,
+}
+
+/// A metric's samples for a session, as extra labels and values, or as a
+/// summary's `sum` and `count`
+type Samples = fn(&SessionMetrics) -> Vec<(&'static str, String)>;
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+impl Metrics {
+    /// Records a refresh that took `latency`, with the number of characters
+    /// on success or `None` on failure
+    pub fn record(&self, session_name: &str, latency: Duration, characters: Option<usize>) {
+        let Ok(mut sessions) = self.sessions.lock() else {
+            return;
+        };
+        let session = sessions.entry(session_name.to_owned()).or_default();
+        match characters {
+            Some(characters) => {
+                session.successes += 1;
+                session.characters = characters;
+            }
+            None => session.failures += 1,
+        }
+        session.valid = characters.is_some();
+        session.last_refresh = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        session.latency_sum += latency.as_secs_f64();
+        session.latency_count += 1;
+    }
+
+    fn render(&self) -> String {
+        let Ok(sessions) = self.sessions.lock() else {
+            return String::new();
+        };
+
+        let families: &[(&str, &str, &str, Samples)] = &[
+            ("auth_rs_refresh_total", "counter", "Session refreshes by result", |s| {
+                vec![("result=\"success\"", s.successes.to_string()), ("result=\"failure\"", s.failures.to_string())]
+            }),
+            ("auth_rs_session_valid", "gauge", "Whether the last refresh found the session valid", |s| {
+                vec![("", u8::from(s.valid).to_string())]
+            }),
+            ("auth_rs_characters", "gauge", "Characters seen by the last successful refresh", |s| {
+                vec![("", s.characters.to_string())]
+            }),
+            ("auth_rs_last_refresh_timestamp_seconds", "gauge", "When the session was last refreshed", |s| {
+                vec![("", s.last_refresh.to_string())]
+            }),
+            ("auth_rs_api_request_duration_seconds", "summary", "Time taken by the accounts request", |s| {
+                vec![("sum", s.latency_sum.to_string()), ("count", s.latency_count.to_string())]
+            }),
+        ];
+
+        let mut text = String::new();
+        for (name, kind, help, values) in families {
+            text.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+            for (session_name, session) in sessions.iter() {
+                let session_label = format!("session=\"{}\"", escape_label(session_name));
+                for (extra, value) in values(session) {
+                    match extra {
+                        "sum" | "count" => text.push_str(&format!("{name}_{extra}{{{session_label}}} {value}\n")),
+                        "" => text.push_str(&format!("{name}{{{session_label}}} {value}\n")),
+                        label => text.push_str(&format!("{name}{{{session_label},{label}}} {value}\n")),
+                    }
+                }
+            }
+        }
+        text
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+
+        let (status, body) = match request_line.split_whitespace().nth(1) {
+            Some("/metrics") => ("200 OK", self.render()),
+            _ => ("404 Not Found", String::new()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// Serves `/metrics` on `addr` for as long as the process runs
+    pub fn serve(&self, addr: SocketAddr) -> Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        let metrics = self.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Err(e) = stream.and_then(|stream| metrics.handle(stream)) {
+                    warn!("Metrics endpoint: {e}");
+                }
+            }
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, OnceLock},
+};
+
+use log::debug;
+use reqwest::dns::{Name, Resolve, Resolving};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    config::{self, Config},
+    error::{AuthError, Result},
+    output, theme,
+};
+
+/// How requests to Jagex connect, under `[network]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Local address, or network interface on Linux and macOS, the requests
+    /// leave from, e.g. `192.168.1.20` or `wg0`
+    pub bind: Option<String>,
+    /// Addresses to use for hostnames instead of looking them up, e.g.
+    /// `"auth.jagex.com" = ["203.0.113.7"]`
+    pub hosts: BTreeMap<String, Vec<IpAddr>>,
+    /// DNS-over-HTTPS resolver to look the other names up with, answering
+    /// in JSON, e.g. `https://1.1.1.1/dns-query`
+    pub doh: Option<String>,
+    /// Which IP version connections use
+    pub ip_version: IpVersion,
+}
+
+/// The IP version connections are made over
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IpVersion {
+    /// IPv4 only, for networks whose IPv6 route to Jagex is broken
+    #[value(name = "4")]
+    #[serde(rename = "4")]
+    V4,
+    /// IPv6 only
+    #[value(name = "6")]
+    #[serde(rename = "6")]
+    V6,
+    /// Either, trying IPv6 and falling back to IPv4 after a moment
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+}
+
+/// Where outgoing connections are bound
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bind {
+    Address(IpAddr),
+    #[cfg(not(windows))]
+    Interface(String),
+}
+
+impl std::fmt::Display for Bind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Bind::Address(address) => write!(f, "{address}"),
+            #[cfg(not(windows))]
+            Bind::Interface(interface) => write!(f, "interface {interface}"),
+        }
+    }
+}
+
+static BIND: OnceLock<String> = OnceLock::new();
+static IP_VERSION: OnceLock<IpVersion> = OnceLock::new();
+
+/// Sets the `--bind` given on the command line, which wins over the config
+pub fn set_bind(bind: String) {
+    let _ = BIND.set(bind);
+}
+
+/// Sets the `--ip-version` given on the command line
+pub fn set_ip_version(ip_version: IpVersion) {
+    let _ = IP_VERSION.set(ip_version);
+}
+
+/// The IP version of `--ip-version`, then `[network] ip_version`
+pub fn ip_version() -> IpVersion {
+    IP_VERSION.get().copied().unwrap_or(config::get().network.ip_version)
+}
+
+/// Where `--bind` or `[network] bind` binds connections: an IP address, or
+/// anything else as an interface name
+pub fn bind() -> Result<Option<Bind>> {
+    let Some(value) = BIND.get().or(config::get().network.bind.as_ref()).map(|value| value.trim()) else {
+        return Ok(None);
+    };
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if let Ok(address) = value.parse() {
+        return Ok(Some(Bind::Address(address)));
+    }
+
+    // Windows sockets can only be bound to an address
+    #[cfg(windows)]
+    return Err(AuthError::UnsupportedPlatform {
+        command: "--bind <interface>",
+        details: "Windows can't bind connections to an interface by name. Bind to the interface's IP address instead",
+    });
+    #[cfg(not(windows))]
+    Ok(Some(Bind::Interface(value.to_owned())))
+}
+
+/// The DoH resolver of `[network] doh`, which must be an `https://` URL
+fn doh() -> Result<Option<Url>> {
+    let Some(doh) = &config::get().network.doh else {
+        return Ok(None);
+    };
+    match Url::parse(doh) {
+        Ok(url) if url.scheme() == "https" => Ok(Some(url)),
+        _ => Err(AuthError::ConfigError {
+            path: Config::path().map(|path| path.display().to_string()).unwrap_or_default(),
+            details: format!("[network] doh must be an https:// URL, not '{doh}'"),
+        }),
+    }
+}
+
+/// Checks the network settings before anything connects, rather than once
+/// a request has gone out over the default route or the ISP's resolver
+pub fn check() -> Result<()> {
+    if let Some(Bind::Address(address)) = bind()? {
+        let (version, number) = if address.is_ipv4() { (IpVersion::V4, 4) } else { (IpVersion::V6, 6) };
+        if ![version, IpVersion::Auto].contains(&ip_version()) {
+            output::renderer().status(&format!(
+                "{} Connections are bound to {address}, so they're made over IPv{number} whatever the IP version is set to",
+                theme::warning("Warning:").bold()
+            ));
+        }
+    }
+    doh()?;
+    Ok(())
+}
+
+/// Whether names are looked up other than through the system's resolver
+pub fn overrides_dns() -> bool {
+    let network = &config::get().network;
+    !network.hosts.is_empty() || network.doh.is_some()
+}
+
+#[derive(Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Status")]
+    status: u32,
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohRecord>,
+}
+
+#[derive(Deserialize)]
+struct DohRecord {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+/// Looks names up over DNS-over-HTTPS, in the JSON format Cloudflare, Google
+/// and most public resolvers answer `application/dns-json` requests in
+#[derive(Clone)]
+struct DohResolver {
+    url: Url,
+    http: reqwest::Client,
+}
+
+impl DohResolver {
+    async fn query(&self, name: &str, record_type: &str) -> Result<Vec<IpAddr>> {
+        let response: DohResponse = self
+            .http
+            .get(self.url.clone())
+            .query(&[("name", name), ("type", record_type)])
+            .header("accept", "application/dns-json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if response.status != 0 {
+            return Ok(vec![]);
+        }
+        // CNAMEs come along with the addresses they lead to, and are skipped
+        Ok(response
+            .answer
+            .iter()
+            .filter(|record| matches!(record.record_type, 1 | 28))
+            .filter_map(|record| record.data.parse().ok())
+            .collect())
+    }
+
+    async fn lookup(&self, name: &str) -> Result<Vec<IpAddr>> {
+        let mut addresses = vec![];
+        if ip_version() != IpVersion::V6 {
+            addresses.extend(self.query(name, "A").await?);
+        }
+        if ip_version() != IpVersion::V4 {
+            addresses.extend(self.query(name, "AAAA").await?);
+        }
+        Ok(addresses)
+    }
+}
+
+impl Resolve for DohResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let name = name.as_str();
+            let addresses = match resolver.lookup(name).await {
+                Ok(addresses) => addresses,
+                // A resolver that can't be reached shouldn't take the
+                // system's with it
+                Err(error) => {
+                    debug!("Couldn't look {name} up over DoH, using the system's resolver: {error}");
+                    let addresses = tokio::net::lookup_host((name, 0)).await?;
+                    addresses.map(|address| address.ip()).collect()
+                }
+            };
+            if addresses.is_empty() {
+                return Err(format!("{name} has no addresses at {}", resolver.url).into());
+            }
+            let addresses: reqwest::dns::Addrs =
+                Box::new(addresses.into_iter().map(|address| SocketAddr::new(address, 0)));
+            Ok(addresses)
+        })
+    }
+}
+
+fn bound(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    // Binding to the unspecified address of one version leaves the
+    // connector only the other's addresses to skip
+    let unspecified = match ip_version() {
+        IpVersion::V4 => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+        IpVersion::V6 => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        IpVersion::Auto => None,
+    };
+    match bind().ok().flatten() {
+        Some(Bind::Address(address)) => builder.local_address(address),
+        #[cfg(not(windows))]
+        Some(Bind::Interface(interface)) => builder.interface(&interface).local_address(unspecified),
+        None => builder.local_address(unspecified),
+    }
+}
+
+/// Applies the network settings to a client for the requests to Jagex
+pub fn apply(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    let network = &config::get().network;
+    let mut builder = bound(builder);
+    for (host, addresses) in &network.hosts {
+        let addresses = addresses.iter().map(|address| SocketAddr::new(*address, 0)).collect::<Vec<_>>();
+        builder = builder.resolve_to_addrs(host, &addresses);
+    }
+
+    // The resolver's own name is left to the system, and its requests go out
+    // the same way as the rest
+    let Some(url) = doh().ok().flatten() else {
+        return builder;
+    };
+    let Ok(http) = bound(reqwest::Client::builder()).build() else {
+        return builder;
+    };
+    builder.dns_resolver(Arc::new(DohResolver { url, http }))
+}
@@ -0,0 +1,419 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use console::{measure_text_width, pad_str, style, Alignment, Term};
+use miette::Diagnostic;
+use serde::Serialize;
+
+use crate::{client::{self, Account, AccountsDiff}, config, error::{AuthError, AvailableCharacter}, history::{self, Launch}, theme};
+
+/// How results and errors are printed
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Colored text for people
+    Human,
+    /// Aligned columns with headers, for `ls`; like `human` elsewhere
+    Table,
+    /// A JSON object per result, or an array for `ls`, for programs
+    /// wrapping auth-rs
+    Json,
+    /// Comma-separated values with a header row, for spreadsheets
+    Csv,
+    /// Only the values scripts need, like `id<TAB>name` for `ls`, and no
+    /// status messages; the exit status tells how it went
+    Quiet,
+}
+
+/// A result's fields, as the machine-readable formats print them
+pub type Fields<'a> = &'a [(&'static str, String)];
+
+/// Prints command results in the `--format` chosen. Commands hand over each
+/// result both as the line people read and as its fields, so every command
+/// gets the machine-readable formats without handling them itself.
+pub trait Renderer: Send + Sync {
+    /// A result, like a session refreshed or a file written
+    fn record(&self, human: &dyn Display, fields: Fields);
+    /// Text only people need, like headings and hints
+    fn note(&self, _human: &dyn Display) {}
+    /// A single value the command was asked for, like the session ID
+    fn value(&self, human: &dyn Display, name: &'static str, value: &str) {
+        self.record(human, &[(name, value.to_owned())]);
+    }
+    /// What's going on while a command runs, like a window being opened,
+    /// printed on stderr so it stays out of the results
+    fn status(&self, human: &dyn Display) {
+        eprintln!("{human}");
+    }
+    /// Characters, as `ls` lists them. `sessions` adds the session of each
+    /// to the human list, when they come from several.
+    fn accounts(&self, rows: &[Row], long: bool, sessions: bool);
+    /// The error the command failed with, when the format prints its own
+    fn error(&self, _error: &AuthError) -> bool {
+        false
+    }
+    /// Starts a fresh screen for commands that redraw, like `ls --watch`.
+    /// Formats read by programs carry on from the last output instead.
+    fn clear(&self) {}
+}
+
+struct Human {
+    table: bool,
+}
+
+impl Renderer for Human {
+    fn record(&self, human: &dyn Display, _fields: Fields) {
+        println!("{human}");
+    }
+
+    fn note(&self, human: &dyn Display) {
+        println!("{human}");
+    }
+
+    fn clear(&self) {
+        let _ = Term::stdout().clear_screen();
+    }
+
+    fn accounts(&self, rows: &[Row], long: bool, sessions: bool) {
+        if long || self.table {
+            print_table(rows, long);
+            return;
+        }
+        for row in rows {
+            let session = match sessions {
+                true => format!(" {}", style(format!("[{}]", row.session_name.as_deref().unwrap_or("default"))).dim()),
+                false => String::new(),
+            };
+            println!(
+                "  {} {} (ID: {}){session}",
+                theme::accent("•"),
+                theme::highlight(&row.account.display_name),
+                style(&row.account.account_id).bold()
+            );
+        }
+    }
+}
+
+struct Json;
+
+impl Renderer for Json {
+    fn record(&self, _human: &dyn Display, fields: Fields) {
+        let object = fields
+            .iter()
+            .map(|(key, value)| (key.to_string(), serde_json::Value::from(value.as_str())))
+            .collect::<serde_json::Map<_, _>>();
+        println!("{}", serde_json::Value::Object(object));
+    }
+
+    fn accounts(&self, rows: &[Row], long: bool, _sessions: bool) {
+        let launches = history::launches();
+        let objects = rows
+            .iter()
+            .map(|row| {
+                headers(long)
+                    .iter()
+                    .map(|header| header.to_lowercase().replace(' ', "_"))
+                    .zip(row.cells(long, &launches))
+                    .map(|(key, value)| (key, serde_json::Value::from(value)))
+                    .collect::<serde_json::Map<_, _>>()
+            })
+            .collect::<Vec<_>>();
+        println!("{}", serde_json::Value::from(objects));
+    }
+
+    fn error(&self, error: &AuthError) -> bool {
+        print_error(error);
+        true
+    }
+}
+
+/// Prints a header row whenever the fields differ from the last record's,
+/// so a command printing one kind of result gets a single table
+#[derive(Default)]
+struct Csv {
+    header: Mutex<Vec<String>>,
+}
+
+fn csv_line<T: AsRef<str>>(values: impl IntoIterator<Item = T>) -> String {
+    values
+        .into_iter()
+        .map(|value| {
+            let value = value.as_ref();
+            if value.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", value.replace('"', "\"\""))
+            } else {
+                value.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Csv {
+    fn print(&self, header: Vec<String>, rows: impl IntoIterator<Item = Vec<String>>) {
+        let mut last = self.header.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if *last != header {
+            println!("{}", csv_line(&header));
+            *last = header;
+        }
+        for row in rows {
+            println!("{}", csv_line(&row));
+        }
+    }
+}
+
+impl Renderer for Csv {
+    fn record(&self, _human: &dyn Display, fields: Fields) {
+        let header = fields.iter().map(|(key, _)| key.to_string()).collect();
+        self.print(header, [fields.iter().map(|(_, value)| value.clone()).collect()]);
+    }
+
+    fn accounts(&self, rows: &[Row], long: bool, _sessions: bool) {
+        let launches = history::launches();
+        let header = headers(long).iter().map(|header| header.to_lowercase().replace(' ', "_")).collect();
+        self.print(header, rows.iter().map(|row| row.cells(long, &launches)));
+    }
+}
+
+struct Quiet;
+
+impl Renderer for Quiet {
+    fn record(&self, _human: &dyn Display, _fields: Fields) {}
+
+    fn value(&self, _human: &dyn Display, _name: &'static str, value: &str) {
+        println!("{value}");
+    }
+
+    fn status(&self, _human: &dyn Display) {}
+
+    fn accounts(&self, rows: &[Row], _long: bool, _sessions: bool) {
+        for row in rows {
+            println!("{}\t{}", row.account.account_id, row.account.display_name);
+        }
+    }
+}
+
+static RENDERER: OnceLock<Box<dyn Renderer>> = OnceLock::new();
+
+/// Picks the renderer for the rest of the process, at startup. `--quiet`
+/// is `--format quiet`.
+pub fn init(format: Format, quiet: bool) {
+    let format = if quiet { Format::Quiet } else { format };
+    let renderer: Box<dyn Renderer> = match format {
+        Format::Human => Box::new(Human { table: false }),
+        Format::Table => Box::new(Human { table: true }),
+        Format::Json => Box::new(Json),
+        Format::Csv => Box::new(Csv::default()),
+        Format::Quiet => Box::new(Quiet),
+    };
+    let _ = RENDERER.set(renderer);
+}
+
+pub fn renderer() -> &'static dyn Renderer {
+    RENDERER.get_or_init(|| Box::new(Human { table: false })).as_ref()
+}
+
+/// A `✓` line for something that went well, with dimmed details
+pub fn succeeded(subject: &str, details: &str, fields: Fields) {
+    let details = match details {
+        "" => String::new(),
+        details => format!(" {}", style(details).dim()),
+    };
+    let human = format!("  {} {}{details}", theme::highlight("✓"), style(subject).bold());
+    renderer().record(&human, &[&[("status", "ok".to_owned())], fields].concat());
+}
+
+/// A `✗` line for something that failed, with the error
+pub fn failed(subject: &str, error: &AuthError, fields: Fields) {
+    let human = format!("  {} {} {error}", theme::error("✗"), style(subject).bold());
+    let error_fields = [("status", "failed".to_owned()), ("error", error.to_string())];
+    renderer().record(&human, &[fields, &error_fields].concat());
+}
+
+/// An error as printed by `--format json`
+#[derive(Serialize)]
+struct ErrorReport {
+    code: Option<String>,
+    message: String,
+    help: Option<String>,
+    /// Messages of the underlying errors, outermost first
+    details: Vec<String>,
+    /// What the error is about, for errors that carry more than a message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<serde_json::Value>,
+}
+
+impl From<&AuthError> for ErrorReport {
+    fn from(error: &AuthError) -> Self {
+        let mut details = vec![];
+        let mut source = std::error::Error::source(error);
+        while let Some(error) = source {
+            details.push(error.to_string());
+            source = error.source();
+        }
+
+        Self {
+            code: error.code().map(|c| c.to_string()),
+            message: error.to_string(),
+            help: error.help().map(|h| h.to_string()),
+            details,
+            data: error_data(error),
+        }
+    }
+}
+
+fn error_data(error: &AuthError) -> Option<serde_json::Value> {
+    match error {
+        AuthError::CharacterNotFound { character_id, available } => Some(serde_json::json!({
+            "character_id": character_id,
+            "available": available,
+        })),
+        _ => None,
+    }
+}
+
+/// The characters a [`AuthError::CharacterNotFound`] lists, one per line
+pub fn available_characters(available: &[AvailableCharacter]) -> String {
+    available
+        .iter()
+        .map(|c| format!("  • {} (ID: {})", c.display_name, c.account_id))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prints the error as a single line of JSON on stderr
+pub fn print_error(error: &AuthError) {
+    let report = ErrorReport::from(error);
+    match serde_json::to_string(&report) {
+        Ok(json) => eprintln!("{json}"),
+        Err(_) => eprintln!("{}", report.message),
+    }
+}
+
+/// Prints the characters added to and removed from the account since the
+/// cached list, and the renamed ones too unless the caller reports those
+pub fn print_diff(diff: &AccountsDiff, renames: bool) {
+    for account in &diff.added {
+        let human = format!(
+            "  {} {} (ID: {}) is new",
+            theme::highlight("+"),
+            theme::highlight(&account.display_name),
+            style(&account.account_id).bold()
+        );
+        renderer().record(&human, &change("added", &account.account_id, &account.display_name));
+    }
+    for account in &diff.removed {
+        let human = format!(
+            "  {} {} (ID: {}) is gone from the account",
+            theme::error("-"),
+            style(&account.display_name).bold(),
+            style(&account.account_id).bold()
+        );
+        renderer().record(&human, &change("removed", &account.account_id, &account.display_name));
+    }
+    for rename in diff.renamed.iter().filter(|_| renames) {
+        print_rename(rename);
+    }
+}
+
+fn change(kind: &str, id: &str, name: &str) -> [(&'static str, String); 3] {
+    [("change", kind.to_owned()), ("id", id.to_owned()), ("name", name.to_owned())]
+}
+
+pub fn print_rename(rename: &client::Rename) {
+    let human = format!(
+        "  {} {} was renamed from {}",
+        theme::warning("!").bold(),
+        theme::highlight(&rename.new_name),
+        style(&rename.old_name).bold()
+    );
+    let mut fields = change("renamed", &rename.account_id, &rename.new_name).to_vec();
+    fields.push(("old_name", rename.old_name.clone()));
+    renderer().record(&human, &fields);
+}
+
+/// A character as a row of `ls --format table`
+pub struct Row<'a> {
+    pub account: &'a Account,
+    pub session_name: &'a Option<String>,
+    /// Age of the session's offline character list
+    pub cache_age: Option<Duration>,
+}
+
+impl Row<'_> {
+    fn cells(&self, long: bool, launches: &BTreeMap<String, Launch>) -> Vec<String> {
+        let config = config::get();
+        let character = config.character(&self.account.account_id);
+        let mut cells = vec![
+            self.account.display_name.clone(),
+            self.account.account_id.clone(),
+            character.and_then(|c| c.nickname.clone()).unwrap_or_default(),
+            self.session_name.clone().unwrap_or_else(|| "default".to_owned()),
+            self.cache_age.map(client::format_age).unwrap_or_else(|| "-".to_owned()),
+        ];
+
+        if long {
+            let mut tags = config.session(self.session_name).map(|s| s.tags.clone()).unwrap_or_default();
+            tags.extend(character.map(|c| c.tags.clone()).unwrap_or_default());
+            cells.push(tags.join(","));
+            cells.push(character.and_then(|c| c.runelite_profile.clone()).unwrap_or_default());
+            cells.push(self.account.user_hash.clone());
+            cells.push(match launches.get(&self.account.account_id) {
+                Some(launch) => format!("{} ago ({})", client::format_age(launch.age()), launch.client),
+                None => "-".to_owned(),
+            });
+        }
+        cells
+    }
+}
+
+/// Column names of `ls --format table`, and keys of the JSON
+/// and CSV formats after lowercasing
+fn headers(long: bool) -> Vec<&'static str> {
+    let mut headers = vec!["NAME", "ID", "NICKNAME", "SESSION", "CACHED"];
+    if long {
+        headers.extend(["TAGS", "PROFILE", "USER HASH", "LAST LAUNCHED"]);
+    }
+    headers
+}
+
+/// Prints the characters as aligned columns. `long` adds the tags, RuneLite
+/// profile, user hash and last launch.
+fn print_table(rows: &[Row], long: bool) {
+    let headers = headers(long);
+
+    let launches = history::launches();
+    let rows = rows.iter().map(|row| row.cells(long, &launches)).collect::<Vec<_>>();
+    let widths = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            rows.iter()
+                .map(|cells| measure_text_width(&cells[i]))
+                .chain([header.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+
+    let line = |cells: Vec<String>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| pad_str(cell, *width, Alignment::Left, None).into_owned())
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+
+    println!("{}", style(line(headers.iter().map(|h| h.to_string()).collect())).bold());
+    for cells in rows {
+        let mut cells = cells;
+        cells[0] = theme::highlight(pad_str(&cells[0], widths[0], Alignment::Left, None)).to_string();
+        println!("{}", line(cells));
+    }
+}
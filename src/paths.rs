@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use crate::error::{AuthError, Result};
+
+const APP_DIR: &str = "auth-rs";
+
+/// The config file and whatever else the user edits
+pub fn config_dir() -> Result<PathBuf> {
+    Ok(dirs::config_dir().ok_or(AuthError::NoConfigDir)?.join(APP_DIR))
+}
+
+/// Sessions, manifests and launch history, which can't be recreated
+pub fn data_dir() -> Result<PathBuf> {
+    Ok(dirs::data_dir().ok_or(AuthError::NoCacheDir)?.join(APP_DIR))
+}
+
+/// Offline character lists and other files that can be fetched again
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(dirs::cache_dir().ok_or(AuthError::NoCacheDir)?.join(APP_DIR))
+}
+
+/// Files that should survive a restart but aren't worth backing up, like
+/// markers of unfinished logins. `$XDG_STATE_HOME` on Linux, the local
+/// app data directory elsewhere.
+pub fn state_dir() -> Result<PathBuf> {
+    let dir = dirs::state_dir().or_else(dirs::data_local_dir).ok_or(AuthError::NoCacheDir)?;
+    Ok(dir.join(APP_DIR))
+}
+
+/// Log and audit files, under `~/Library/Logs` on macOS
+pub fn log_dir() -> Result<PathBuf> {
+    if cfg!(target_os = "macos") {
+        let home = dirs::home_dir().ok_or(AuthError::NoCacheDir)?;
+        return Ok(home.join("Library").join("Logs").join(APP_DIR));
+    }
+    Ok(state_dir()?.join("logs"))
+}
+
+/// Locks, sockets and other files that only mean something while auth-rs
+/// runs. `$XDG_RUNTIME_DIR` is cleared on logout; elsewhere the directory
+/// is kept under the cache.
+pub fn runtime_dir() -> Result<PathBuf> {
+    match dirs::runtime_dir() {
+        Some(dir) => Ok(dir.join(APP_DIR)),
+        None => Ok(cache_dir()?.join("run")),
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::PathBuf;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+use tao::{
+    dpi::{LogicalSize, PhysicalPosition},
+    event_loop::EventLoopWindowTarget,
+    monitor::MonitorHandle,
+};
+
+use crate::{error::Result, paths};
+
+/// Where the authorize window opens, under `[window]`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub placement: Placement,
+    /// Keep the window above the others until the login is over
+    pub always_on_top: bool,
+    /// How much bigger than normal the login page is shown, for high-DPI
+    /// displays where it's too small to read
+    pub zoom: f64,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            placement: Placement::default(),
+            always_on_top: false,
+            zoom: 1.0,
+        }
+    }
+}
+
+/// Where the authorize window is put when it opens
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    /// Wherever the window manager puts it
+    #[default]
+    System,
+    /// Centered on the monitor the pointer is on
+    Center,
+    /// Where it was last closed, or centered when that's no longer on a
+    /// connected monitor
+    Remember,
+}
+
+/// Where the window was last closed
+#[derive(Serialize, Deserialize)]
+struct Remembered {
+    x: i32,
+    y: i32,
+}
+
+fn remembered_path() -> Result<PathBuf> {
+    Ok(paths::state_dir()?.join("window.json"))
+}
+
+/// The monitor the user is most likely looking at: the one with the pointer,
+/// else the primary one. Wayland doesn't tell where the pointer is.
+fn focused_monitor<T>(target: &EventLoopWindowTarget<T>) -> Option<MonitorHandle> {
+    target
+        .cursor_position()
+        .ok()
+        .and_then(|cursor| target.monitor_from_point(cursor.x, cursor.y))
+        .or_else(|| target.primary_monitor())
+        .or_else(|| target.available_monitors().next())
+}
+
+fn centered(monitor: &MonitorHandle, size: LogicalSize<f64>) -> PhysicalPosition<i32> {
+    let size = size.to_physical::<i32>(monitor.scale_factor());
+    let (origin, area) = (monitor.position(), monitor.size());
+    PhysicalPosition::new(
+        origin.x + (area.width as i32 - size.width).max(0) / 2,
+        origin.y + (area.height as i32 - size.height).max(0) / 2,
+    )
+}
+
+/// Whether the title bar at `position` is on a connected monitor, where it
+/// can be grabbed
+fn on_screen<T>(target: &EventLoopWindowTarget<T>, position: PhysicalPosition<i32>) -> bool {
+    let (x, y) = (position.x + 40, position.y + 10);
+    target.available_monitors().any(|monitor| {
+        let (origin, area) = (monitor.position(), monitor.size());
+        (origin.x..origin.x + area.width as i32).contains(&x) && (origin.y..origin.y + area.height as i32).contains(&y)
+    })
+}
+
+fn remembered<T>(target: &EventLoopWindowTarget<T>) -> Option<PhysicalPosition<i32>> {
+    let contents = std::fs::read(remembered_path().ok()?).ok()?;
+    let Remembered { x, y } = serde_json::from_slice(&contents).ok()?;
+    let position = PhysicalPosition::new(x, y);
+    if !on_screen(target, position) {
+        debug!("The window was last closed at {x},{y}, which no monitor shows any more");
+        return None;
+    }
+    Some(position)
+}
+
+/// Where to open a window of `size`, or `None` to leave it to the window
+/// manager. Wayland compositors place windows themselves whatever is asked.
+pub fn position<T>(
+    target: &EventLoopWindowTarget<T>,
+    placement: Placement,
+    size: LogicalSize<f64>,
+) -> Option<PhysicalPosition<i32>> {
+    match placement {
+        Placement::System => None,
+        Placement::Center => focused_monitor(target).map(|monitor| centered(&monitor, size)),
+        Placement::Remember => {
+            remembered(target).or_else(|| focused_monitor(target).map(|monitor| centered(&monitor, size)))
+        }
+    }
+}
+
+/// Remembers where the window was closed, for [`Placement::Remember`]
+pub fn remember(position: PhysicalPosition<i32>) -> Result<()> {
+    let path = remembered_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_vec(&Remembered { x: position.x, y: position.y })?)?;
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, Config},
+    error::{AuthError, Result},
+    launch, output, theme,
+};
+
+/// What happens when a profile's executable doesn't match its pinned hash
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnMismatch {
+    #[default]
+    Refuse,
+    Warn,
+}
+
+/// Limits on what's launched with the session, under `[policy]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct PolicyConfig {
+    /// The only executables that may be started with the session: paths,
+    /// directories ending in `/` to allow everything inside, or `sha256:<hex>`
+    /// digests. Anything may be started when unset.
+    pub allowed_executables: Option<Vec<String>>,
+    pub on_mismatch: OnMismatch,
+}
+
+/// Lowercase hex SHA-256 of the file's contents
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buffer[..read]);
+    }
+    Ok(context.finish().as_ref().iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// The file a program name or path runs, looked up in `$PATH` when it's a
+/// bare name, with symlinks resolved
+pub fn resolve_executable(program: &str) -> Option<PathBuf> {
+    let path = Path::new(program);
+    let path = if path.components().count() > 1 {
+        path.to_path_buf()
+    } else {
+        launch::find_executable(program)?
+    };
+    std::fs::canonicalize(path).ok()
+}
+
+fn allows(entry: &str, executable: &Path, digest: &mut impl FnMut() -> Option<String>) -> bool {
+    if let Some(expected) = entry.strip_prefix("sha256:") {
+        return digest().is_some_and(|digest| digest.eq_ignore_ascii_case(expected.trim()));
+    }
+
+    let Ok(allowed) = std::fs::canonicalize(entry) else {
+        return false;
+    };
+    if entry.ends_with('/') || entry.ends_with('\\') {
+        executable.starts_with(allowed)
+    } else {
+        executable == allowed
+    }
+}
+
+/// Fails unless `[policy] allowed_executables` lets the program be started
+/// with the session
+pub fn check(program: &str) -> Result<()> {
+    let Some(allowed) = &config::get().policy.allowed_executables else {
+        return Ok(());
+    };
+
+    let not_allowed = || AuthError::ExecutableNotAllowed { program: program.to_owned() };
+    let executable = resolve_executable(program).ok_or_else(not_allowed)?;
+    // Hashed once, and only when an entry needs it
+    let mut cached = None;
+    let mut digest = || cached.get_or_insert_with(|| sha256_file(&executable).ok()).clone();
+
+    if allowed.iter().any(|entry| allows(entry, &executable, &mut digest)) {
+        Ok(())
+    } else {
+        Err(not_allowed())
+    }
+}
+
+/// Fails when `[policy] allowed_executables` is set, as what a container
+/// image runs can't be checked against it
+pub fn check_container(image: &str) -> Result<()> {
+    match &config::get().policy.allowed_executables {
+        Some(_) => Err(AuthError::ContainerNotAllowed { image: image.to_owned() }),
+        None => Ok(()),
+    }
+}
+
+/// Checks the executable against the hash pinned by `profile pin`, failing
+/// or warning on a mismatch as `[policy] on_mismatch` says
+pub fn verify_pin(program: &str, expected: &Option<String>) -> Result<()> {
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+
+    let executable = resolve_executable(program).ok_or_else(|| AuthError::ExecError {
+        program: program.to_owned(),
+        details: "Executable not found".to_owned(),
+    })?;
+    let actual = sha256_file(&executable)?;
+    if actual.eq_ignore_ascii_case(expected) {
+        return Ok(());
+    }
+
+    match config::get().policy.on_mismatch {
+        OnMismatch::Refuse => Err(AuthError::ExecutableChanged {
+            program: executable.display().to_string(),
+            expected: expected.clone(),
+            actual,
+        }),
+        OnMismatch::Warn => {
+            output::renderer().status(&format!(
+                "{} {} doesn't match its pinned SHA-256 (expected {expected}, found {actual})",
+                theme::warning("Warning:").bold(),
+                style(executable.display()).bold()
+            ));
+            Ok(())
+        }
+    }
+}
+
+/// Records the SHA-256 of the profile's executable in the user's config
+/// file, returning the executable and its hash
+pub fn pin(profile_name: &str) -> Result<(PathBuf, String)> {
+    let user_config = Config::load_user()?;
+    let program = user_config.profile_executable(profile_name)?;
+    let executable = resolve_executable(&program).ok_or_else(|| AuthError::ExecError {
+        program: program.clone(),
+        details: "Executable not found".to_owned(),
+    })?;
+    let digest = sha256_file(&executable)?;
+
+    Config::edit_user(|table| {
+        let profile = config::table_mut(config::table_mut(table, "profiles")?, profile_name)?;
+        profile.insert("sha256".to_owned(), digest.clone().into());
+        Ok(())
+    })?;
+    Ok((executable, digest))
+}
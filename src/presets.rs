@@ -0,0 +1,311 @@
+use std::{path::PathBuf, process::Command};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config, error::{AuthError, Result}, game::Game, launch};
+
+/// Minimum Java version HDOS runs on
+const HDOS_MIN_JAVA: u32 = 11;
+
+const HDOS_DEFAULT_JVM_ARGS: &[&str] = &["-Xmx1024m", "-Dawt.useSystemAAFontSettings=on"];
+
+/// Folds the positional `<EXEC>` back into the arguments when a preset is
+/// used, as it's then the first argument meant for the client
+pub fn client_args(exec: Option<String>, args: Vec<String>) -> Vec<String> {
+    exec.into_iter().chain(args).collect()
+}
+
+/// Known game clients that can be launched without spelling out their command line
+#[derive(clap::ValueEnum, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// The HDOS launcher jar, run with a detected Java runtime (Old School only)
+    Hdos,
+    /// The official C++ client of the game, installed by the Jagex Launcher
+    /// (Windows and macOS)
+    Official,
+}
+
+/// Overrides for the HDOS preset, under `[presets.hdos]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct HdosConfig {
+    pub jar: Option<PathBuf>,
+    pub java: Option<PathBuf>,
+    pub jvm_args: Option<Vec<String>>,
+}
+
+/// Overrides for the official client preset, under `[presets.official]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct OfficialConfig {
+    pub path: Option<PathBuf>,
+    /// Path of the RuneScape 3 client, for `--game rs3`
+    pub rs3_path: Option<PathBuf>,
+}
+
+/// How the container gets at the host's display server
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContainerDisplay {
+    X11,
+    Wayland,
+    None,
+}
+
+/// Overrides for `exec --container`, under `[presets.container]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct ContainerConfig {
+    /// `docker` or `podman`, found in `$PATH` when unset (Podman first)
+    pub runtime: Option<String>,
+    /// Detected from `WAYLAND_DISPLAY` and `DISPLAY` when unset
+    pub display: Option<ContainerDisplay>,
+    /// Extra `run` options, e.g. `["--device", "/dev/dri"]`
+    pub run_args: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct PresetsConfig {
+    pub hdos: HdosConfig,
+    pub official: OfficialConfig,
+    pub container: ContainerConfig,
+}
+
+impl Preset {
+    /// Resolves the preset to the game's program and its arguments, with
+    /// `args` passed through to the client
+    pub fn command(&self, game: Game, args: &[String]) -> Result<(String, Vec<String>)> {
+        match (self, game) {
+            (Preset::Hdos, Game::Osrs) => hdos_command(args),
+            (Preset::Hdos, Game::Rs3) => Err(AuthError::PresetNotFound {
+                preset: "hdos",
+                details: "HDOS is an Old School RuneScape client, use --game osrs or another client".to_owned(),
+            }),
+            (Preset::Official, game) => official_command(game, args),
+        }
+    }
+}
+
+/// Parses the major version from `java -version` output, e.g. `"17.0.2"` or `"1.8.0_292"`
+fn parse_java_version(output: &str) -> Option<u32> {
+    let version = output.split('"').nth(1)?;
+    let mut parts = version.split(['.', '_', '-', '+']);
+    match parts.next()?.parse().ok()? {
+        1 => parts.next()?.parse().ok(),
+        major => Some(major),
+    }
+}
+
+fn java_version(java: &PathBuf) -> Option<u32> {
+    let output = Command::new(java).arg("-version").output().ok()?;
+    parse_java_version(&String::from_utf8_lossy(&output.stderr))
+}
+
+fn hdos_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|h| h.join(".hdos"))
+}
+
+fn find_java(min_version: u32) -> Result<PathBuf> {
+    if let Some(java) = &config::get().presets.hdos.java {
+        return Ok(java.clone());
+    }
+
+    let binary = if cfg!(windows) { "java.exe" } else { "java" };
+    let candidates = [
+        // The JRE HDOS downloads for itself
+        hdos_dir().map(|d| d.join("jre").join("bin").join(binary)),
+        std::env::var_os("JAVA_HOME").map(|h| PathBuf::from(h).join("bin").join(binary)),
+        launch::find_executable(binary),
+    ];
+
+    let mut found = vec![];
+    for java in candidates.into_iter().flatten().filter(|p| p.is_file()) {
+        match java_version(&java) {
+            Some(version) if version >= min_version => return Ok(java),
+            Some(version) => found.push(format!("{} (Java {version})", java.display())),
+            None => found.push(format!("{} (unknown version)", java.display())),
+        }
+    }
+
+    Err(AuthError::JavaNotFound {
+        min_version,
+        found: if found.is_empty() { "none".to_owned() } else { found.join(", ") },
+    })
+}
+
+fn find_hdos_jar() -> Result<PathBuf> {
+    if let Some(jar) = &config::get().presets.hdos.jar {
+        return Ok(jar.clone());
+    }
+
+    [hdos_dir(), dirs::data_dir().map(|d| d.join("hdos")), dirs::download_dir()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("hdos-launcher.jar"))
+        .find(|path| path.is_file())
+        .ok_or(AuthError::PresetNotFound {
+            preset: "hdos",
+            details: "Could not find hdos-launcher.jar in ~/.hdos, the data directory or Downloads. Set [presets.hdos] jar in the config file".to_owned(),
+        })
+}
+
+fn hdos_command(args: &[String]) -> Result<(String, Vec<String>)> {
+    let hdos = &config::get().presets.hdos;
+    let jar = find_hdos_jar()?;
+    let java = find_java(HDOS_MIN_JAVA)?;
+
+    let mut java_args = match &hdos.jvm_args {
+        Some(jvm_args) => jvm_args.clone(),
+        None => HDOS_DEFAULT_JVM_ARGS.iter().map(|a| a.to_string()).collect(),
+    };
+    java_args.push("-jar".to_owned());
+    java_args.push(jar.display().to_string());
+    java_args.extend(args.iter().cloned());
+
+    Ok((java.display().to_string(), java_args))
+}
+
+#[cfg(windows)]
+fn official_client_candidates(game: Game) -> Vec<PathBuf> {
+    let (roots, relative): (&[&str], &[&str]) = match game {
+        Game::Osrs => (
+            &["ProgramFiles(x86)", "ProgramFiles"],
+            &["Jagex Launcher", "Games", "Old School RuneScape", "Client", "osclient.exe"],
+        ),
+        Game::Rs3 => (&["ProgramData"], &["Jagex", "launcher", "rs2client.exe"]),
+    };
+    roots
+        .iter()
+        .filter_map(std::env::var_os)
+        .map(|root| relative.iter().fold(PathBuf::from(root), |path, part| path.join(part)))
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn official_client_candidates(game: Game) -> Vec<PathBuf> {
+    let app = format!("{}.app", game.title());
+    [Some(PathBuf::from("/Applications")), dirs::home_dir().map(|h| h.join("Applications"))]
+        .into_iter()
+        .flatten()
+        .filter_map(|dir| macos_bundle_executable(&dir.join(&app)))
+        .collect()
+}
+
+/// Reads `CFBundleExecutable` from the bundle's Info.plist to find the binary to run
+#[cfg(target_os = "macos")]
+fn macos_bundle_executable(bundle: &std::path::Path) -> Option<PathBuf> {
+    let plist = std::fs::read_to_string(bundle.join("Contents").join("Info.plist")).ok()?;
+    let after_key = plist.split("<key>CFBundleExecutable</key>").nth(1)?;
+    let name = after_key.split("<string>").nth(1)?.split("</string>").next()?.trim();
+    Some(bundle.join("Contents").join("MacOS").join(name))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn official_client_candidates(_game: Game) -> Vec<PathBuf> {
+    vec![]
+}
+
+fn official_command(game: Game, args: &[String]) -> Result<(String, Vec<String>)> {
+    let official = &config::get().presets.official;
+    let (path, key) = match game {
+        Game::Osrs => (&official.path, "path"),
+        Game::Rs3 => (&official.rs3_path, "rs3_path"),
+    };
+    if let Some(path) = path {
+        return Ok((path.display().to_string(), args.to_vec()));
+    }
+
+    let details = if cfg!(any(windows, target_os = "macos")) {
+        format!("Install {} with the Jagex Launcher, or set [presets.official] {key} in the config file", game.title())
+    } else {
+        format!("The official client is only available on Windows and macOS. Set [presets.official] {key} in the config file to use it anyway")
+    };
+
+    official_client_candidates(game)
+        .into_iter()
+        .find(|path| path.is_file())
+        .map(|path| (path.display().to_string(), args.to_vec()))
+        .ok_or(AuthError::PresetNotFound {
+            preset: "official",
+            details,
+        })
+}
+
+fn container_runtime() -> Result<String> {
+    if let Some(runtime) = &config::get().presets.container.runtime {
+        return Ok(runtime.clone());
+    }
+
+    ["podman", "docker"]
+        .into_iter()
+        .find(|runtime| launch::find_executable(runtime).is_some())
+        .map(str::to_owned)
+        .ok_or(AuthError::PresetNotFound {
+            preset: "container",
+            details: "Neither podman nor docker was found in $PATH. Install one, or set [presets.container] runtime in the config file".to_owned(),
+        })
+}
+
+fn container_display() -> ContainerDisplay {
+    if let Some(display) = config::get().presets.container.display {
+        return display;
+    }
+
+    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        ContainerDisplay::Wayland
+    } else if std::env::var_os("DISPLAY").is_some() {
+        ContainerDisplay::X11
+    } else {
+        ContainerDisplay::None
+    }
+}
+
+/// Options sharing the host's display socket with the container
+fn container_display_args(display: ContainerDisplay) -> Vec<String> {
+    match display {
+        ContainerDisplay::X11 => vec![
+            "--env".to_owned(),
+            "DISPLAY".to_owned(),
+            "--volume".to_owned(),
+            "/tmp/.X11-unix:/tmp/.X11-unix".to_owned(),
+        ],
+        ContainerDisplay::Wayland => {
+            let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_default();
+            let socket = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_owned());
+            vec![
+                "--env".to_owned(),
+                format!("WAYLAND_DISPLAY={socket}"),
+                "--env".to_owned(),
+                "XDG_RUNTIME_DIR=/tmp/xdg-runtime".to_owned(),
+                "--volume".to_owned(),
+                format!("{runtime_dir}/{socket}:/tmp/xdg-runtime/{socket}"),
+            ]
+        }
+        ContainerDisplay::None => vec![],
+    }
+}
+
+/// Runs `image` with the container runtime, passing the session variables
+/// through by name so their values never show up in the process list
+pub fn container_command(image: &str, args: &[String]) -> Result<(String, Vec<String>)> {
+    let container = &config::get().presets.container;
+    let runtime = container_runtime()?;
+
+    let mut run_args = vec!["run".to_owned(), "--rm".to_owned()];
+    for var in ["JX_SESSION_ID", "JX_CHARACTER_ID", "JX_DISPLAY_NAME"] {
+        run_args.push("--env".to_owned());
+        run_args.push(var.to_owned());
+    }
+    for var in launch::STARTUP_VARS.iter().filter(|var| std::env::var_os(var).is_some()) {
+        run_args.push("--env".to_owned());
+        run_args.push(var.to_string());
+    }
+    run_args.extend(container_display_args(container_display()));
+    run_args.extend(container.run_args.iter().cloned());
+    run_args.push(image.to_owned());
+    run_args.extend(args.iter().cloned());
+
+    Ok((runtime, run_args))
+}
@@ -0,0 +1,54 @@
+use std::{sync::OnceLock, time::{SystemTime, UNIX_EPOCH}};
+
+use serde::Serialize;
+
+/// Machine-readable progress reporting, for UIs that drive auth-rs
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProgressFormat {
+    /// One JSON object per line on stderr
+    JsonLines,
+}
+
+/// Steps of the authorize flow and the requests around it
+#[derive(Serialize, Debug)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event<'a> {
+    AuthUrlOpened,
+    CodeReceived,
+    TokenExchanged,
+    SessionCreated { session_name: &'a Option<String> },
+    AccountsFetched { accounts: usize },
+    /// Jagex is asking for something before the login can finish
+    ActionRequired { reason: &'a str },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    #[serde(flatten)]
+    event: Event<'a>,
+    /// Unix timestamp in milliseconds
+    time: u128,
+}
+
+static FORMAT: OnceLock<ProgressFormat> = OnceLock::new();
+
+pub fn init(format: Option<ProgressFormat>) {
+    if let Some(format) = format {
+        let _ = FORMAT.set(format);
+    }
+}
+
+pub fn format() -> Option<ProgressFormat> {
+    FORMAT.get().copied()
+}
+
+pub fn emit(event: Event) {
+    let Some(ProgressFormat::JsonLines) = format() else {
+        return;
+    };
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or_default();
+    if let Ok(json) = serde_json::to_string(&Line { event, time }) {
+        eprintln!("{json}");
+    }
+}
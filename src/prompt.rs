@@ -0,0 +1,78 @@
+use std::{io::IsTerminal, time::Duration};
+
+use dialoguer::{Confirm, Password};
+
+use crate::{error::{AuthError, Result}, theme};
+
+/// Whether the user can be asked questions, rather than auth-rs running in a script
+pub fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Asks the user to confirm, failing with a hint to pass `skip_flag` when
+/// there's no terminal to ask on
+pub fn confirm(prompt: &str, skip_flag: &str) -> Result<()> {
+    if !is_interactive() {
+        return Err(AuthError::ConfirmationRequired {
+            details: format!("Not running in an interactive terminal, pass {skip_flag} to continue"),
+        });
+    }
+
+    let confirmed = Confirm::with_theme(&theme::prompts())
+        .with_prompt(prompt)
+        .default(false)
+        .interact()?;
+
+    if confirmed { Ok(()) } else { Err(AuthError::Cancelled) }
+}
+
+/// Asks for a passphrase, twice when `confirm` as it's being picked
+pub fn passphrase(prompt: &str, confirm: bool) -> Result<String> {
+    if !is_interactive() {
+        return Err(AuthError::SessionFileLocked {
+            details: "Run auth-rs in a terminal to enter its passphrase".to_owned(),
+        });
+    }
+
+    let input = Password::with_theme(&theme::prompts()).with_prompt(prompt);
+    let input = if confirm { input.with_confirmation("Repeat it", "The passphrases don't match") } else { input };
+    Ok(input.interact()?)
+}
+
+/// Asks a yes/no question, answering no when there's no terminal to ask on
+pub fn ask(prompt: &str) -> Result<bool> {
+    if !is_interactive() {
+        return Ok(false);
+    }
+
+    Ok(Confirm::with_theme(&theme::prompts())
+        .with_prompt(prompt)
+        .default(true)
+        .interact()?)
+}
+
+/// How long to wait for the keyring to be unlocked when there's no terminal to ask on
+const UNLOCK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const UNLOCK_POLL_ATTEMPTS: usize = 30;
+
+/// Decides whether to retry a keyring operation that failed because it was
+/// locked: asks in a terminal, otherwise polls for a minute
+pub fn retry_after_unlock(attempt: usize) -> Result<bool> {
+    if is_interactive() {
+        let retry = Confirm::with_theme(&theme::prompts())
+            .with_prompt("The credential store is locked. Unlock it, then retry?")
+            .default(true)
+            .interact()?;
+        return Ok(retry);
+    }
+
+    if attempt >= UNLOCK_POLL_ATTEMPTS {
+        return Ok(false);
+    }
+
+    if attempt == 0 {
+        eprintln!("The credential store is locked, waiting for it to be unlocked...");
+    }
+    std::thread::sleep(UNLOCK_POLL_INTERVAL);
+    Ok(true)
+}
@@ -0,0 +1,143 @@
+use log::warn;
+use url::Url;
+use wry::{ProxyConfig, ProxyEndpoint};
+
+use crate::{
+    config::{self, Config},
+    error::{AuthError, Result},
+    sessions, theme,
+};
+
+/// How a session reaches Jagex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    /// Through the proxy the environment sets, if any
+    Environment,
+    /// Straight to Jagex, whatever the environment says
+    Direct,
+    Proxy(Url),
+}
+
+impl Route {
+    /// The proxy without its credentials, for showing to people
+    pub fn describe(&self) -> Option<String> {
+        let Route::Proxy(url) = self else {
+            return None;
+        };
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port().map(|port| format!(":{port}")).unwrap_or_default();
+        let isolated = if url.username().is_empty() { "" } else { " (own credentials)" };
+        Some(format!("{}://{host}{port}{isolated}", url.scheme()))
+    }
+}
+
+fn invalid(session_name: &Option<String>, details: String) -> AuthError {
+    let name = session_name.as_deref().unwrap_or("default");
+    AuthError::ConfigError {
+        path: Config::path().map(|path| path.display().to_string()).unwrap_or_default(),
+        details: format!("proxy of session '{name}' {details}"),
+    }
+}
+
+/// The route `[sessions.<name>] proxy` sets: `direct`, or an `http://`,
+/// `https://`, `socks5://` or `socks5h://` URL. With `isolate_circuit`, a
+/// SOCKS proxy without credentials is given the session's own, which Tor
+/// takes as a reason to use a separate circuit.
+pub fn route(session_name: &Option<String>) -> Result<Route> {
+    let Some(session) = config::get().session(session_name) else {
+        return Ok(Route::Environment);
+    };
+    let Some(proxy) = session.proxy.as_deref().map(str::trim) else {
+        return Ok(Route::Environment);
+    };
+    if proxy == "direct" {
+        return Ok(Route::Direct);
+    }
+
+    let mut url = Url::parse(proxy).map_err(|e| invalid(session_name, format!("isn't a URL: {e}")))?;
+    if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") || !url.has_host() {
+        return Err(invalid(session_name, "must be direct or an http, https, socks5 or socks5h URL".to_owned()));
+    }
+    if session.isolate_circuit && url.scheme().starts_with("socks") && url.username().is_empty() {
+        let _ = url.set_username(&format!("auth-rs-{}", sessions::key(session_name)));
+        let _ = url.set_password(Some("auth-rs"));
+    }
+    Ok(Route::Proxy(url))
+}
+
+/// The proxy the login window of the session loads pages through
+pub fn for_webview(session_name: &Option<String>, target: &Url) -> Result<Option<Url>> {
+    Ok(match route(session_name)? {
+        Route::Environment => from_env(target),
+        Route::Direct => None,
+        Route::Proxy(url) => Some(url),
+    })
+}
+
+fn var(names: &[&str]) -> Option<String> {
+    names.iter().find_map(|name| std::env::var(name).ok()).filter(|value| !value.trim().is_empty())
+}
+
+/// Whether `NO_PROXY` says the host is reached directly: `*`, the host
+/// itself, or a domain it's under (`example.com` and `.example.com` both
+/// covering `login.example.com`)
+fn bypassed(host: &str) -> bool {
+    let Some(no_proxy) = var(&["NO_PROXY", "no_proxy"]) else {
+        return false;
+    };
+    no_proxy.split(',').map(str::trim).filter(|entry| !entry.is_empty()).any(|entry| {
+        let domain = entry.trim_start_matches('.');
+        entry == "*" || host == domain || host.ends_with(&format!(".{domain}"))
+    })
+}
+
+/// The proxy the environment sets for the URL, read the way reqwest reads
+/// it for the API calls: `HTTPS_PROXY` or `HTTP_PROXY` by the URL's scheme,
+/// then `ALL_PROXY`, unless `NO_PROXY` covers the host
+pub fn from_env(target: &Url) -> Option<Url> {
+    if target.host_str().is_some_and(bypassed) {
+        return None;
+    }
+    let scheme_var = match target.scheme() {
+        "https" => ["HTTPS_PROXY", "https_proxy"],
+        _ => ["HTTP_PROXY", "http_proxy"],
+    };
+    let value = var(&scheme_var).or_else(|| var(&["ALL_PROXY", "all_proxy"]))?;
+
+    // A bare `host:port` is an HTTP proxy, as curl and reqwest take it
+    let parsed = Url::parse(&value).ok().filter(|url| url.has_host());
+    match parsed.or_else(|| Url::parse(&format!("http://{value}")).ok()) {
+        Some(url) => Some(url),
+        None => {
+            warn!("Ignoring the proxy {value}, it isn't a URL");
+            None
+        }
+    }
+}
+
+/// The proxy as the webview takes it. The webview can't log in to a proxy,
+/// so credentials in the URL are left out with a warning.
+pub fn webview_config(proxy: &Url) -> Option<ProxyConfig> {
+    let host = proxy.host_str()?.to_owned();
+    if !proxy.username().is_empty() || proxy.password().is_some() {
+        eprintln!(
+            "{} The login window can't authenticate to the proxy at {host}, its credentials are ignored",
+            theme::warning("Warning:").bold()
+        );
+    }
+
+    let (default_port, socks) = match proxy.scheme() {
+        "http" => (80, false),
+        "https" => (443, false),
+        "socks5" | "socks5h" => (1080, true),
+        scheme => {
+            eprintln!(
+                "{} The login window doesn't support {scheme} proxies, loading it without the proxy at {host}",
+                theme::warning("Warning:").bold()
+            );
+            return None;
+        }
+    };
+    let endpoint = ProxyEndpoint { host, port: proxy.port().unwrap_or(default_port).to_string() };
+    Some(if socks { ProxyConfig::Socks5(endpoint) } else { ProxyConfig::Http(endpoint) })
+}
@@ -0,0 +1,118 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use console::style;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{self, Client},
+    error::Result,
+    output, paths, sessions, theme,
+};
+
+/// Markers of flows whose process can't be checked are only given up on after this
+#[cfg(not(target_os = "linux"))]
+const ABANDONED_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Left behind while an authorize flow runs, so one cut short by a crash or
+/// power loss can be cleaned up after
+#[derive(Serialize, Deserialize)]
+struct Marker {
+    session_name: Option<String>,
+    pid: u32,
+    /// Unix timestamp the flow started at
+    started_at: u64,
+}
+
+fn markers_dir() -> Result<PathBuf> {
+    Ok(paths::state_dir()?.join("authorize"))
+}
+
+fn marker_path(session_name: &Option<String>) -> Result<PathBuf> {
+    Ok(markers_dir()?.join(format!("{}.json", sessions::key(session_name))))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Whether the flow's process is gone. Only Linux can tell, elsewhere the
+/// marker has to get old first.
+fn is_stale(marker: &Marker) -> bool {
+    if marker.pid == std::process::id() {
+        return false;
+    }
+    #[cfg(target_os = "linux")]
+    return !std::path::Path::new(&format!("/proc/{}", marker.pid)).exists();
+    #[cfg(not(target_os = "linux"))]
+    return now().saturating_sub(marker.started_at) > ABANDONED_AFTER.as_secs();
+}
+
+/// Finds flows that never finished and cleans up after them, saying what
+/// was recovered
+pub fn recover() -> Result<()> {
+    let Ok(entries) = std::fs::read_dir(markers_dir()?) else {
+        return Ok(());
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let marker = std::fs::read(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_slice::<Marker>(&contents).ok());
+        let Some(marker) = marker else {
+            std::fs::remove_file(&path)?;
+            continue;
+        };
+        if !is_stale(&marker) {
+            continue;
+        }
+
+        let removed = Client::new(marker.session_name.clone()).repair_accounts_cache()?;
+        std::fs::remove_file(&path)?;
+
+        let name = marker.session_name.clone().unwrap_or_else(|| "default".to_owned());
+        let age = client::format_age(Duration::from_secs(now().saturating_sub(marker.started_at)));
+        output::renderer().status(&format!(
+            "{} an authorize of session {} started {age} ago was interrupted",
+            theme::warning("Recovered:").bold(),
+            style(name).bold()
+        ));
+        for item in removed {
+            output::renderer().status(&format!("  removed {item}"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Held while an authorize flow runs, removing its marker when dropped so
+/// the flow is marked as over however it ended
+pub struct Guard {
+    path: PathBuf,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Marks an authorize flow for the session as started, until the returned
+/// guard is dropped
+pub fn begin(session_name: &Option<String>) -> Result<Guard> {
+    recover()?;
+
+    let path = marker_path(session_name)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let marker = Marker {
+        session_name: session_name.clone(),
+        pid: std::process::id(),
+        started_at: now(),
+    };
+    std::fs::write(&path, serde_json::to_vec(&marker)?)?;
+    Ok(Guard { path })
+}
@@ -0,0 +1,110 @@
+use dialoguer::Password;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{self, Config},
+    error::{AuthError, Result},
+    history, prompt, theme,
+};
+
+/// Limits on launching, under `[restrictions]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct RestrictionsConfig {
+    /// Local times launching is allowed at, e.g. `["16:00-20:00"]`. A window
+    /// may wrap past midnight. Any time when empty.
+    pub hours: Vec<String>,
+    /// Launches allowed per character each day, unless the character sets
+    /// its own
+    pub daily_launches: Option<u32>,
+    /// Hex SHA-256 of the passphrase that lifts the restrictions for one
+    /// launch. Nothing can override them when unset.
+    pub override_sha256: Option<String>,
+}
+
+/// Minutes since midnight of an `HH:MM` time
+fn parse_time(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+fn parse_window(window: &str) -> Result<(u32, u32)> {
+    window
+        .split_once('-')
+        .and_then(|(start, end)| Some((parse_time(start)?, parse_time(end)?)))
+        .ok_or_else(|| AuthError::ConfigError {
+            path: Config::path().map(|p| p.display().to_string()).unwrap_or_default(),
+            details: format!("Invalid [restrictions] hours window '{window}', expected e.g. \"16:00-20:00\""),
+        })
+}
+
+fn in_window(now: u32, (start, end): (u32, u32)) -> bool {
+    if start <= end {
+        (start..end).contains(&now)
+    } else {
+        now >= start || now < end
+    }
+}
+
+/// Why the character can't be launched right now, if it can't
+fn violation(character_id: &str) -> Result<Option<String>> {
+    let config = config::get();
+    let restrictions = &config.restrictions;
+
+    if !restrictions.hours.is_empty() {
+        let now = jiff::Zoned::now();
+        let minutes = now.hour() as u32 * 60 + now.minute() as u32;
+        let windows = restrictions.hours.iter().map(|w| parse_window(w)).collect::<Result<Vec<_>>>()?;
+        if !windows.into_iter().any(|window| in_window(minutes, window)) {
+            return Ok(Some(format!("Launching is only allowed {}", restrictions.hours.join(", "))));
+        }
+    }
+
+    let limit = config
+        .character(character_id)
+        .and_then(|c| c.daily_launches)
+        .or(restrictions.daily_launches);
+    if let Some(limit) = limit {
+        let launches = history::launches_today(character_id);
+        if launches >= limit {
+            return Ok(Some(format!("The character was launched {launches} time(s) today, the limit is {limit}")));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Fails when `[restrictions]` don't allow launching the character now.
+/// `allow_override` lets the launch go ahead once the passphrase matching
+/// `override_sha256` is typed in a terminal.
+pub fn check(character_id: &str, allow_override: bool) -> Result<()> {
+    let Some(reason) = violation(character_id)? else {
+        return Ok(());
+    };
+    if !allow_override {
+        return Err(AuthError::LaunchRestricted { reason });
+    }
+    let Some(expected) = config::get().restrictions.override_sha256.clone() else {
+        return Err(AuthError::ConfirmationRequired {
+            details: "No override passphrase is set, add its SHA-256 as override_sha256 under [restrictions]".to_owned(),
+        });
+    };
+    if !prompt::is_interactive() {
+        return Err(AuthError::ConfirmationRequired {
+            details: "Overriding the restrictions needs the override passphrase typed in a terminal".to_owned(),
+        });
+    }
+
+    eprintln!("{} {reason}", theme::warning("Restricted:").bold());
+    let typed = Password::with_theme(&theme::prompts())
+        .with_prompt("Override passphrase")
+        .allow_empty_password(true)
+        .interact()?;
+    let digest = ring::digest::digest(&ring::digest::SHA256, typed.as_bytes());
+    let digest: String = digest.as_ref().iter().map(|byte| format!("{byte:02x}")).collect();
+    if !digest.eq_ignore_ascii_case(expected.trim()) {
+        return Err(AuthError::Cancelled);
+    }
+    Ok(())
+}
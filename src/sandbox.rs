@@ -0,0 +1,306 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use crate::{config, envfile, error::{AuthError, Result}, launch, paths};
+
+/// Confinement auth-rs can find itself packaged in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sandbox {
+    Flatpak,
+    Snap,
+}
+
+impl Sandbox {
+    pub fn name(self) -> &'static str {
+        match self {
+            Sandbox::Flatpak => "Flatpak",
+            Sandbox::Snap => "Snap",
+        }
+    }
+}
+
+pub fn detect() -> Option<Sandbox> {
+    static SANDBOX: OnceLock<Option<Sandbox>> = OnceLock::new();
+    *SANDBOX.get_or_init(|| {
+        if Path::new("/.flatpak-info").exists() {
+            Some(Sandbox::Flatpak)
+        } else if std::env::var_os("SNAP").is_some() && std::env::var_os("SNAP_NAME").is_some() {
+            Some(Sandbox::Snap)
+        } else {
+            None
+        }
+    })
+}
+
+/// Flatpak apps can't reach the Secret Service without a hole in the
+/// sandbox, so sessions are sealed with a key from the Secret portal instead
+pub fn uses_secret_portal() -> bool {
+    detect() == Some(Sandbox::Flatpak)
+}
+
+/// Whether the program only exists on the host, outside the Flatpak sandbox
+pub fn needs_host_spawn(program: &str) -> bool {
+    detect() == Some(Sandbox::Flatpak)
+        && !Path::new(program).is_file()
+        && launch::find_executable(program).is_none()
+}
+
+/// Reads the variables from the env file named by `$1`, removes it and runs
+/// the rest of the arguments. `export` takes each line as one word, so values
+/// aren't expanded.
+const HOST_ENV_SCRIPT: &str = r#"while IFS= read -r line; do export "$line"; done < "$1"; rm -f -- "$1"; shift; exec "$@""#;
+
+/// Runs the program on the host with `flatpak-spawn --host`, which doesn't
+/// pass the environment on by itself. The variables are handed over in an
+/// env file only the user can read, as `--env=` would show the session to
+/// anyone listing processes. The state directory is the same path on the
+/// host.
+pub fn host_command(program: &str, args: Vec<String>, env: &[(&str, String)]) -> Result<(String, Vec<String>)> {
+    let dir = paths::state_dir()?;
+    std::fs::create_dir_all(&dir)?;
+    let env_file = dir.join(format!("host-env-{}", uuid::Uuid::new_v4().simple()));
+    envfile::write(&env_file, env)?;
+    // In case the host never gets to run the script
+    envfile::schedule_removal(&env_file, 60)?;
+
+    let mut host_args = vec![
+        "--host".to_owned(),
+        "sh".to_owned(),
+        "-c".to_owned(),
+        HOST_ENV_SCRIPT.to_owned(),
+        "sh".to_owned(),
+        env_file.display().to_string(),
+        program.to_owned(),
+    ];
+    host_args.extend(args);
+    Ok(("flatpak-spawn".to_owned(), host_args))
+}
+
+fn bind(args: &mut Vec<String>, option: &str, path: &Path) {
+    let path = path.display().to_string();
+    args.extend([option.to_owned(), path.clone(), path]);
+}
+
+/// Wraps the client in bubblewrap with a private home directory (`home` when
+/// given), read-only system directories, the display sockets and the
+/// network. The `JX_*` variables are inherited as usual.
+pub fn bwrap_command(program: &str, args: Vec<String>, home_dir: Option<&Path>) -> Result<(String, Vec<String>)> {
+    let bwrap = launch::find_executable("bwrap").ok_or(AuthError::PresetNotFound {
+        preset: "sandbox",
+        details: "bubblewrap (bwrap) was not found in $PATH. Install it from your distribution's packages".to_owned(),
+    })?;
+    let sandbox = &config::get().sandbox;
+
+    let home = dirs::home_dir().ok_or(AuthError::NoCacheDir)?;
+    let sandbox_home = match home_dir.or(sandbox.home.as_deref()) {
+        Some(dir) => dir.to_owned(),
+        None => paths::data_dir()?.join("sandbox-home"),
+    };
+    std::fs::create_dir_all(&sandbox_home)?;
+
+    let mut bwrap_args = vec![
+        "--unshare-all".to_owned(),
+        "--share-net".to_owned(),
+        "--die-with-parent".to_owned(),
+        // Stops the client typing into the terminal it was started from
+        "--new-session".to_owned(),
+        "--proc".to_owned(),
+        "/proc".to_owned(),
+        "--dev".to_owned(),
+        "/dev".to_owned(),
+        "--tmpfs".to_owned(),
+        "/tmp".to_owned(),
+    ];
+    for dir in ["/usr", "/bin", "/sbin", "/lib", "/lib64", "/etc", "/opt", "/sys/dev/char", "/sys/devices"] {
+        bind(&mut bwrap_args, "--ro-bind-try", Path::new(dir));
+    }
+    bind(&mut bwrap_args, "--dev-bind-try", Path::new("/dev/dri"));
+    bind(&mut bwrap_args, "--ro-bind-try", Path::new("/tmp/.X11-unix"));
+    if let Some(runtime_dir) = std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        let wayland = std::env::var_os("WAYLAND_DISPLAY").unwrap_or_else(|| "wayland-0".into());
+        bind(&mut bwrap_args, "--ro-bind-try", &runtime_dir.join(wayland));
+        bind(&mut bwrap_args, "--ro-bind-try", &runtime_dir.join("pulse"));
+        bind(&mut bwrap_args, "--ro-bind-try", &runtime_dir.join("pipewire-0"));
+    }
+
+    bwrap_args.extend([
+        "--bind".to_owned(),
+        sandbox_home.display().to_string(),
+        home.display().to_string(),
+    ]);
+
+    // A client kept in the real home directory would be hidden by the mount above
+    let program_path = if Path::new(program).is_file() {
+        Some(std::path::absolute(program)?)
+    } else {
+        launch::find_executable(program)
+    };
+    if let Some(path) = program_path.filter(|p| p.starts_with(&home)) {
+        bind(&mut bwrap_args, "--ro-bind", &path);
+    }
+
+    for path in &sandbox.ro_binds {
+        bind(&mut bwrap_args, "--ro-bind", path);
+    }
+    for path in &sandbox.binds {
+        bind(&mut bwrap_args, "--bind", path);
+    }
+
+    bwrap_args.push("--".to_owned());
+    bwrap_args.push(program.to_owned());
+    bwrap_args.extend(args);
+    Ok((bwrap.display().to_string(), bwrap_args))
+}
+
+#[cfg(target_os = "linux")]
+pub use portal::{open, open_uri, retrieve_secret, seal};
+
+#[cfg(target_os = "linux")]
+mod portal {
+    use std::{
+        io::Read,
+        os::fd::IntoRawFd,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use dbus::{
+        arg::{OwnedFd, PropMap, Variant},
+        blocking::Connection,
+        message::MatchRule,
+    };
+    use ring::{aead, hkdf, rand::{SecureRandom, SystemRandom}};
+
+    use crate::error::{AuthError, Result};
+
+    const TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn portal_error(error: dbus::Error) -> AuthError {
+        AuthError::CredentialStoreError(format!("Secret portal: {error}"))
+    }
+
+    /// Asks the Secret portal for the app's master secret, which it keeps in
+    /// the host keyring and writes to the pipe we hand it
+    pub fn retrieve_secret() -> Result<Vec<u8>> {
+        let connection = Connection::new_session().map_err(portal_error)?;
+
+        // Subscribe before calling, as the response can beat the reply
+        let token = format!("auth_rs_{}", uuid::Uuid::new_v4().simple());
+        let sender = connection.unique_name().trim_start_matches(':').replace('.', "_");
+        let request = format!("/org/freedesktop/portal/desktop/request/{sender}/{token}");
+        let rule = MatchRule::new_signal("org.freedesktop.portal.Request", "Response")
+            .with_path(dbus::Path::from(request));
+
+        let response = Arc::new(Mutex::new(None));
+        let response_handle = response.clone();
+        connection
+            .add_match(rule, move |(code,): (u32,), _, _| {
+                if let Ok(mut response) = response_handle.lock() {
+                    *response = Some(code);
+                }
+                false
+            })
+            .map_err(portal_error)?;
+
+        let (mut reader, writer) = std::io::pipe()?;
+        // SAFETY: the descriptor was just taken out of the pipe writer, so
+        // nothing else owns it
+        let fd = unsafe { OwnedFd::new(writer.into_raw_fd()) };
+        let mut options = PropMap::new();
+        options.insert("handle_token".to_owned(), Variant(Box::new(token)));
+
+        let proxy = connection.with_proxy(
+            "org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            TIMEOUT,
+        );
+        let _: (dbus::Path,) = proxy
+            .method_call("org.freedesktop.portal.Secret", "RetrieveSecret", (fd, options))
+            .map_err(portal_error)?;
+
+        let deadline = Instant::now() + TIMEOUT;
+        let code = loop {
+            if let Some(code) = response.lock().ok().and_then(|r| *r) {
+                break code;
+            }
+            if Instant::now() > deadline {
+                return Err(AuthError::CredentialStoreError("The Secret portal did not respond".to_owned()));
+            }
+            connection.process(Duration::from_millis(200)).map_err(portal_error)?;
+        };
+
+        if code != 0 {
+            return Err(AuthError::CredentialStoreError(format!(
+                "The Secret portal refused the request (response {code})"
+            )));
+        }
+
+        let mut secret = vec![];
+        reader.read_to_end(&mut secret)?;
+        Ok(secret)
+    }
+
+    /// Asks the OpenURI portal to open the URL, as the sandbox has no browser
+    /// of its own for `xdg-open` to hand it to
+    pub fn open_uri(url: &str) -> Result<()> {
+        let error = |error: dbus::Error| AuthError::ExecError {
+            program: "OpenURI portal".to_owned(),
+            details: error.to_string(),
+        };
+        let connection = Connection::new_session().map_err(error)?;
+
+        let proxy = connection.with_proxy(
+            "org.freedesktop.portal.Desktop",
+            "/org/freedesktop/portal/desktop",
+            TIMEOUT,
+        );
+        let _: (dbus::Path,) = proxy
+            .method_call("org.freedesktop.portal.OpenURI", "OpenURI", ("", url, PropMap::new()))
+            .map_err(error)?;
+        Ok(())
+    }
+
+    fn key(secret: &[u8], context: &str) -> Result<aead::LessSafeKey> {
+        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, b"auth-rs");
+        let info = [context.as_bytes()];
+        let prk = salt.extract(secret);
+        let okm = prk
+            .expand(&info, &aead::AES_256_GCM)
+            .map_err(|_| AuthError::CredentialStoreError("Could not derive the session key".to_owned()))?;
+        Ok(aead::LessSafeKey::new(aead::UnboundKey::from(okm)))
+    }
+
+    /// Encrypts `plaintext` with a key derived for `context`, as the nonce
+    /// followed by the ciphertext
+    pub fn seal(secret: &[u8], context: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let error = || AuthError::CredentialStoreError("Could not encrypt the session".to_owned());
+
+        let mut nonce = [0; aead::NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).map_err(|_| error())?;
+
+        let mut sealed = plaintext.to_vec();
+        key(secret, context)?
+            .seal_in_place_append_tag(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::empty(), &mut sealed)
+            .map_err(|_| error())?;
+
+        Ok([nonce.as_slice(), &sealed].concat())
+    }
+
+    pub fn open(secret: &[u8], context: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        let error = || AuthError::CredentialStoreError("Could not decrypt the stored session".to_owned());
+
+        if sealed.len() < aead::NONCE_LEN {
+            return Err(error());
+        }
+        let (nonce, ciphertext) = sealed.split_at(aead::NONCE_LEN);
+        let nonce = aead::Nonce::try_assume_unique_for_key(nonce).map_err(|_| error())?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = key(secret, context)?
+            .open_in_place(nonce, aead::Aad::empty(), &mut in_out)
+            .map_err(|_| error())?;
+        Ok(plaintext.to_vec())
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::Result, paths};
+
+/// Shortest wait before trying a session again
+const MIN_DELAY: Duration = Duration::from_secs(60);
+/// Longest wait between failed attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// When the daemon next refreshes a session and how it went lately
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct Entry {
+    /// Unix timestamp of the next refresh
+    next_run: u64,
+    /// Failed refreshes in a row
+    failures: u32,
+}
+
+/// Next refreshes by session name, kept in the state directory so a
+/// restarted daemon picks up where it left off instead of refreshing every
+/// session at once
+#[derive(Serialize, Deserialize, Default)]
+pub struct Schedule {
+    sessions: BTreeMap<String, Entry>,
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// A random fraction of `delay`, up to `percent` of it
+fn jitter(delay: Duration, percent: u32) -> Duration {
+    let mut bytes = [0; 4];
+    let random = SystemRandom::new().fill(&mut bytes).map(|_| u32::from_le_bytes(bytes)).unwrap_or_default();
+    delay * (random % (percent + 1)) / 100
+}
+
+impl Schedule {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::state_dir()?.join("daemon-schedule.json"))
+    }
+
+    /// The saved schedule, or an empty one when there's none or it can't be
+    /// read, as everything is then simply due
+    pub fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|path| std::fs::File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(file).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Whether the session should be refreshed now. Sessions never seen
+    /// before are due straight away.
+    pub fn is_due(&self, name: &str) -> bool {
+        self.sessions.get(name).is_none_or(|entry| entry.next_run <= now())
+    }
+
+    /// Time until the first session is due, at most `interval`
+    pub fn until_next(&self, names: &[String], interval: Duration) -> Duration {
+        let now = now();
+        names
+            .iter()
+            .map(|name| match self.sessions.get(name) {
+                Some(entry) => Duration::from_secs(entry.next_run.saturating_sub(now)),
+                None => Duration::ZERO,
+            })
+            .min()
+            .unwrap_or(interval)
+            .min(interval)
+    }
+
+    /// Schedules the next refresh after one finished. Successful refreshes
+    /// come back after `interval`, sooner when the session expires before
+    /// then so its expiry is noticed in time. Failures back off from a minute,
+    /// doubling each time up to six hours. Either way, up to a tenth is
+    /// added at random so sessions drift apart rather than refresh together.
+    pub fn finished(&mut self, name: &str, succeeded: bool, remaining: Option<Duration>, interval: Duration) -> Duration {
+        let entry = self.sessions.entry(name.to_owned()).or_default();
+        let delay = if succeeded {
+            entry.failures = 0;
+            // A session past its estimated expiry that still works says
+            // nothing about when it ends
+            match remaining.filter(|remaining| !remaining.is_zero()) {
+                Some(remaining) => interval.min(remaining / 2),
+                None => interval,
+            }
+        } else {
+            entry.failures = entry.failures.saturating_add(1);
+            MIN_DELAY.saturating_mul(2u32.saturating_pow(entry.failures - 1)).min(MAX_BACKOFF)
+        };
+        let delay = delay.max(MIN_DELAY);
+        let delay = delay + jitter(delay, 10);
+
+        entry.next_run = now() + delay.as_secs();
+        delay
+    }
+
+    /// Makes the session, or every session, due straight away
+    pub fn make_due(&mut self, name: Option<&str>) {
+        for (session, entry) in &mut self.sessions {
+            if name.is_none_or(|name| name == session) {
+                entry.next_run = 0;
+            }
+        }
+    }
+
+    /// Drops sessions that no longer exist
+    pub fn retain(&mut self, names: &[String]) {
+        self.sessions.retain(|name, _| names.contains(name));
+    }
+}
@@ -0,0 +1,180 @@
+use std::{collections::{BTreeMap, BTreeSet}, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{client::Client, config, error::Result, paths};
+
+const DEFAULT_KEY: &str = "session";
+const NAMED_PREFIX: &str = "named-session-";
+
+/// Key a session is stored under, in the credential store and on disk
+pub fn key(session_name: &Option<String>) -> String {
+    match session_name {
+        Some(session_name) => format!("{NAMED_PREFIX}{session_name}"),
+        None => DEFAULT_KEY.to_owned(),
+    }
+}
+
+fn name_from_key(key: &str) -> Option<Option<String>> {
+    if key == DEFAULT_KEY {
+        return Some(None);
+    }
+    key.strip_prefix(NAMED_PREFIX).map(|name| Some(name.to_owned()))
+}
+
+/// Keys of the sessions auth-rs has stored, as most credential stores can't
+/// be listed
+#[derive(Serialize, Deserialize, Default)]
+struct Manifest {
+    sessions: BTreeSet<String>,
+    /// Login emails remembered with `authorize --email`, by key
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    emails: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    fn path() -> Result<PathBuf> {
+        Ok(paths::data_dir()?.join("sessions.json"))
+    }
+
+    fn load() -> Result<Self> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+}
+
+pub fn record(session_name: &Option<String>) -> Result<()> {
+    let mut manifest = Manifest::load()?;
+    if manifest.sessions.insert(key(session_name)) {
+        manifest.save()?;
+    }
+    Ok(())
+}
+
+pub fn forget(session_name: &Option<String>) -> Result<()> {
+    let mut manifest = Manifest::load()?;
+    let key = key(session_name);
+    let removed = manifest.sessions.remove(&key);
+    if manifest.emails.remove(&key).is_some() || removed {
+        manifest.save()?;
+    }
+    Ok(())
+}
+
+pub fn remember_email(session_name: &Option<String>, email: &str) -> Result<()> {
+    let mut manifest = Manifest::load()?;
+    let previous = manifest.emails.insert(key(session_name), email.to_owned());
+    if previous.as_deref() != Some(email) {
+        manifest.save()?;
+    }
+    Ok(())
+}
+
+/// The email to prefill when logging in to the session, from the config file
+/// or remembered by `authorize --email`
+pub fn email(session_name: &Option<String>) -> Option<String> {
+    config::get()
+        .session(session_name)
+        .and_then(|s| s.email.clone())
+        .or_else(|| Manifest::load().ok()?.emails.remove(&key(session_name)))
+}
+
+/// Keys of the sessions in the manifest or saved to disk, found without
+/// reading the credential store so nothing asks for it to be unlocked. The
+/// default session counts as known, as it may predate the manifest.
+pub fn known_keys() -> Result<BTreeSet<String>> {
+    let mut keys = Manifest::load()?.sessions;
+    keys.extend(file_store_keys());
+    keys.insert(DEFAULT_KEY.to_owned());
+    Ok(keys)
+}
+
+/// Sessions saved to disk by the credential store fallback
+fn file_store_keys() -> Vec<String> {
+    let Ok(dir) = paths::data_dir().map(|d| d.join("sessions")) else {
+        return vec![];
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            name.strip_suffix(".locked").or_else(|| name.strip_suffix(".json")).map(str::to_owned)
+        })
+        .collect()
+}
+
+/// Lists the credentials keyring created for the service, whose target
+/// names are "<key>.<service>"
+#[cfg(windows)]
+fn credential_manager_keys(service: &str) -> Vec<String> {
+    use windows_sys::Win32::Security::Credentials::{CredEnumerateW, CredFree, CREDENTIALW};
+
+    fn from_wide(ptr: *const u16) -> String {
+        // SAFETY: Credential Manager returns NUL-terminated UTF-16 strings
+        unsafe {
+            let len = (0..).take_while(|&i| *ptr.add(i) != 0).count();
+            String::from_utf16_lossy(std::slice::from_raw_parts(ptr, len))
+        }
+    }
+
+    let filter = format!("*.{service}")
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect::<Vec<u16>>();
+    let suffix = format!(".{service}");
+    let mut count = 0;
+    let mut credentials: *mut *mut CREDENTIALW = std::ptr::null_mut();
+
+    // SAFETY: on success CredEnumerateW allocates `count` credential pointers,
+    // which stay valid until the CredFree below
+    unsafe {
+        if CredEnumerateW(filter.as_ptr(), 0, &mut count, &mut credentials) == 0 {
+            return vec![];
+        }
+
+        let keys = (0..count as usize)
+            .filter_map(|i| {
+                let target = from_wide((**credentials.add(i)).TargetName);
+                target.strip_suffix(&suffix).map(str::to_owned)
+            })
+            .collect();
+        CredFree(credentials as *const _);
+        keys
+    }
+}
+
+/// All known sessions, from the manifest, the file fallback and, on Windows,
+/// the Credential Manager itself
+pub fn list() -> Result<Vec<Option<String>>> {
+    let mut keys = Manifest::load()?.sessions;
+    keys.extend(file_store_keys());
+    #[cfg(windows)]
+    keys.extend(credential_manager_keys(&Client::credential_store_service()));
+
+    // The default session may predate the manifest
+    if !keys.contains(DEFAULT_KEY) && Client::new(None).session().is_ok() {
+        keys.insert(DEFAULT_KEY.to_owned());
+    }
+
+    Ok(keys.iter().filter_map(|key| name_from_key(key)).collect())
+}
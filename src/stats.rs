@@ -0,0 +1,98 @@
+use std::{
+    collections::BTreeMap,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use console::style;
+
+use crate::{error::Result, history::{self, Play}, output, theme};
+
+const WEEK: u64 = 7 * 24 * 60 * 60;
+
+/// Play time as hours and minutes, e.g. `12h 05m`
+fn format_played(seconds: u64) -> String {
+    format!("{}h {:02}m", seconds / 3600, seconds / 60 % 60)
+}
+
+fn plural(count: usize, unit: &str) -> String {
+    if count == 1 {
+        format!("1 {unit}")
+    } else {
+        format!("{count} {unit}s")
+    }
+}
+
+/// Weeks counted back from now the play started in, 0 for the last 7 days
+fn weeks_ago(play: &Play, now: u64) -> u64 {
+    now.saturating_sub(play.started_at) / WEEK
+}
+
+/// Prints the time played per character, most played first, and the
+/// sessions of each of the last `weeks` weeks
+pub fn run(weeks: u64) -> Result<()> {
+    let plays = history::plays()?;
+    if plays.is_empty() {
+        output::renderer().note(&format!("No play time recorded yet. Launch with {} to record it.", style("--spawn").bold()));
+        return Ok(());
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+    let mut by_character = BTreeMap::<&str, Vec<&Play>>::new();
+    for play in &plays {
+        by_character.entry(&play.character_id).or_default().push(play);
+    }
+    let mut characters = by_character.into_values().collect::<Vec<_>>();
+    characters.sort_by_key(|plays| std::cmp::Reverse(plays.iter().map(|p| p.seconds).sum::<u64>()));
+
+    let renderer = output::renderer();
+    renderer.note(&style("Per character").bold());
+    for plays in &characters {
+        // The latest display name, in case the character was renamed
+        let latest = plays[plays.len() - 1];
+        let seconds = plays.iter().map(|p| p.seconds).sum::<u64>();
+        let first = plays.iter().map(|p| p.started_at).min().unwrap_or(now);
+        let weeks_tracked = (now.saturating_sub(first) as f64 / WEEK as f64).max(1.0);
+        let per_week = plays.len() as f64 / weeks_tracked;
+        let human = format!(
+            "  {} {} {} over {}, {}",
+            theme::accent("•"),
+            theme::highlight(&latest.display_name),
+            format_played(seconds),
+            plural(plays.len(), "session"),
+            style(format!("about {per_week:.1} a week")).dim()
+        );
+        let fields = [
+            ("character", latest.display_name.clone()),
+            ("id", latest.character_id.clone()),
+            ("seconds", seconds.to_string()),
+            ("sessions", plays.len().to_string()),
+            ("sessions_per_week", format!("{per_week:.1}")),
+        ];
+        renderer.record(&human, &fields);
+    }
+
+    renderer.note(&format!("\n{}", style("Per week").bold()));
+    for week in 0..weeks {
+        let in_week = plays.iter().filter(|p| weeks_ago(p, now) == week).collect::<Vec<_>>();
+        let label = match week {
+            0 => "Last 7 days".to_owned(),
+            1 => "1 week ago".to_owned(),
+            _ => format!("{week} weeks ago"),
+        };
+        let seconds = in_week.iter().map(|p| p.seconds).sum::<u64>();
+        let human = format!(
+            "  {} {:<12} {:<12} {}",
+            theme::accent("•"),
+            label,
+            plural(in_week.len(), "session"),
+            style(format_played(seconds)).dim()
+        );
+        let fields =
+            [("weeks_ago", week.to_string()), ("seconds", seconds.to_string()), ("sessions", in_week.len().to_string())];
+        renderer.record(&human, &fields);
+    }
+
+    let total = plays.iter().map(|p| p.seconds).sum::<u64>();
+    renderer.note(&format!("\nTotal: {}", theme::highlight(format_played(total))));
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::{config, error::AuthError};
+
+/// Where outages are looked up when Jagex answers with a server error,
+/// under `[status]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct StatusConfig {
+    /// Status page summary in the Statuspage format, e.g.
+    /// `https://status.example.com/api/v2/summary.json`
+    pub url: Option<String>,
+}
+
+/// The status page shouldn't keep an error waiting
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Summary {
+    status: Indicator,
+    incidents: Vec<Incident>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Indicator {
+    /// `none`, `minor`, `major` or `critical`
+    indicator: String,
+    description: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Incident {
+    name: String,
+    /// `investigating`, `identified`, `monitoring` and so on
+    status: String,
+    shortlink: Option<String>,
+}
+
+/// What the status page says, or `None` when there's none to ask or it
+/// can't be read
+async fn report(http: &reqwest::Client) -> Option<String> {
+    let url = config::get().status.url.as_ref()?;
+    let summary = async {
+        let response = http.get(url).timeout(TIMEOUT).send().await?.error_for_status()?;
+        response.json::<Summary>().await
+    };
+    let summary = match summary.await {
+        Ok(summary) => summary,
+        Err(error) => {
+            debug!("Couldn't read the status page at {url}: {error}");
+            return None;
+        }
+    };
+
+    if summary.status.indicator == "none" && summary.incidents.is_empty() {
+        return Some("The status page reports no incident yet, so it may be brief".to_owned());
+    }
+    let mut report = format!("The status page reports: {}", summary.status.description);
+    for incident in &summary.incidents {
+        report.push_str(&format!("\n• {} ({})", incident.name, incident.status));
+        if let Some(link) = &incident.shortlink {
+            report.push_str(&format!(" {link}"));
+        }
+    }
+    Some(report)
+}
+
+/// The error for a server error from Jagex, with what the status page says
+/// about it when `[status] url` is set
+pub async fn outage(http: &reqwest::Client, status: reqwest::StatusCode) -> AuthError {
+    let report = report(http).await.unwrap_or_else(|| "It's a problem on Jagex's side".to_owned());
+    AuthError::ServiceDown { summary: format!("Jagex answered {status}. {report}") }
+}
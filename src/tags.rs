@@ -0,0 +1,94 @@
+use dialoguer::Select;
+
+use crate::{
+    client::{Account, Client},
+    config,
+    error::{AuthError, Result},
+    history, output, prompt, sessions, theme,
+};
+
+/// A tagged character and the session it was found in
+pub struct Member {
+    pub session_name: Option<String>,
+    pub account: Account,
+}
+
+/// Sessions that may hold tagged characters: every session when any
+/// character carries the tag, as only the accounts call says where it lives
+fn candidate_sessions(tag: &str) -> Result<Vec<Option<String>>> {
+    let config = config::get();
+    let characters_tagged = config.characters.values().any(|c| c.tags.iter().any(|t| t == tag));
+
+    Ok(sessions::list()?
+        .into_iter()
+        .filter(|session_name| {
+            characters_tagged || config.session(session_name).is_some_and(|s| s.tags.iter().any(|t| t == tag))
+        })
+        .collect())
+}
+
+/// Tagged sessions in the group, whether or not their characters could be listed
+pub fn sessions(tag: &str) -> Result<Vec<Option<String>>> {
+    let sessions = candidate_sessions(tag)?;
+    if sessions.is_empty() {
+        return Err(AuthError::TagEmpty { tag: tag.to_owned() });
+    }
+    Ok(sessions)
+}
+
+pub async fn members(tag: &str, offline: bool) -> Result<Vec<Member>> {
+    let config = config::get();
+    let mut members = vec![];
+
+    for session_name in candidate_sessions(tag)? {
+        let client = Client::new(session_name.clone());
+        let accounts = match client.accounts(offline, false).await {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                let name = session_name.as_deref().unwrap_or("default");
+                output::renderer().status(&format!("{} session '{name}': {e}", theme::warning("Skipping")));
+                continue;
+            }
+        };
+
+        members.extend(
+            accounts
+                .into_iter()
+                .filter(|account| config.has_tag(&session_name, &account.account_id, tag))
+                .map(|account| Member { session_name: session_name.clone(), account }),
+        );
+    }
+
+    if members.is_empty() {
+        return Err(AuthError::TagEmpty { tag: tag.to_owned() });
+    }
+    Ok(members)
+}
+
+/// Picks a character from the tag, asking when there's more than one
+pub async fn pick(tag: &str, offline: bool) -> Result<Member> {
+    let mut members = members(tag, offline).await?;
+    if members.len() == 1 {
+        return Ok(members.remove(0));
+    }
+
+    if !prompt::is_interactive() {
+        return Err(AuthError::MissingLaunchOption { option: "--character-id" });
+    }
+    history::sort_by_recency(&mut members, |m| &m.account.account_id);
+
+    let items = members
+        .iter()
+        .map(|m| match &m.session_name {
+            Some(session_name) => format!("{} (ID: {}, session {session_name})", m.account.display_name, m.account.account_id),
+            None => format!("{} (ID: {})", m.account.display_name, m.account.account_id),
+        })
+        .collect::<Vec<_>>();
+    let selection = Select::with_theme(&theme::prompts())
+        .with_prompt(format!("Which '{tag}' character?"))
+        .items(&items)
+        .default(0)
+        .interact()?;
+
+    Ok(members.swap_remove(selection))
+}
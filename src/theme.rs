@@ -0,0 +1,155 @@
+use console::{Color, Style, StyledObject};
+use dialoguer::theme::ColorfulTheme;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+/// Built-in palettes for `[theme]`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThemeName {
+    #[default]
+    Default,
+    /// Darker colors for light terminal backgrounds
+    Light,
+    /// Blue and orange instead of green and red
+    Colorblind,
+    /// Bright, bold colors only
+    HighContrast,
+    /// Bold and dim text, no colors
+    Monochrome,
+}
+
+/// A terminal color by name, or a 256-color palette number
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ThemeColor {
+    Named(NamedColor),
+    Palette(u8),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NamedColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+}
+
+impl From<ThemeColor> for Color {
+    fn from(color: ThemeColor) -> Self {
+        match color {
+            ThemeColor::Palette(n) => Color::Color256(n),
+            ThemeColor::Named(NamedColor::Black) => Color::Black,
+            ThemeColor::Named(NamedColor::Red) => Color::Red,
+            ThemeColor::Named(NamedColor::Green) => Color::Green,
+            ThemeColor::Named(NamedColor::Yellow) => Color::Yellow,
+            ThemeColor::Named(NamedColor::Blue) => Color::Blue,
+            ThemeColor::Named(NamedColor::Magenta) => Color::Magenta,
+            ThemeColor::Named(NamedColor::Cyan) => Color::Cyan,
+            ThemeColor::Named(NamedColor::White) => Color::White,
+        }
+    }
+}
+
+/// Output colors, under `[theme]`. The single colors override the palette's.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(default)]
+pub struct ThemeConfig {
+    pub name: ThemeName,
+    /// Bullets and other decoration
+    pub accent: Option<ThemeColor>,
+    /// Character names, paths and other results
+    pub highlight: Option<ThemeColor>,
+    pub warning: Option<ThemeColor>,
+    pub error: Option<ThemeColor>,
+}
+
+#[derive(Clone, Copy)]
+enum Role {
+    Accent,
+    Highlight,
+    Warning,
+    Error,
+}
+
+fn palette(name: ThemeName, role: Role) -> Option<Color> {
+    match (name, role) {
+        (ThemeName::Monochrome, _) => None,
+        (ThemeName::Default, Role::Accent) => Some(Color::Cyan),
+        (ThemeName::Default, Role::Highlight) => Some(Color::Green),
+        (ThemeName::Default, Role::Warning) => Some(Color::Yellow),
+        (ThemeName::Default, Role::Error) => Some(Color::Red),
+        (ThemeName::Light, Role::Accent) => Some(Color::Blue),
+        (ThemeName::Light, Role::Highlight) => Some(Color::Color256(22)),
+        (ThemeName::Light, Role::Warning) => Some(Color::Color256(130)),
+        (ThemeName::Light, Role::Error) => Some(Color::Color256(124)),
+        (ThemeName::Colorblind, Role::Accent) => Some(Color::Color256(244)),
+        (ThemeName::Colorblind, Role::Highlight) => Some(Color::Color256(33)),
+        (ThemeName::Colorblind, Role::Warning) => Some(Color::Color256(220)),
+        (ThemeName::Colorblind, Role::Error) => Some(Color::Color256(208)),
+        (ThemeName::HighContrast, Role::Accent) => Some(Color::Color256(15)),
+        (ThemeName::HighContrast, Role::Highlight) => Some(Color::Color256(14)),
+        (ThemeName::HighContrast, Role::Warning) => Some(Color::Color256(11)),
+        (ThemeName::HighContrast, Role::Error) => Some(Color::Color256(9)),
+    }
+}
+
+fn role_style(role: Role) -> Style {
+    let theme = &config::get().theme;
+    let configured = match role {
+        Role::Accent => theme.accent,
+        Role::Highlight => theme.highlight,
+        Role::Warning => theme.warning,
+        Role::Error => theme.error,
+    };
+
+    let mut style = Style::new();
+    if let Some(color) = configured.map(Color::from).or_else(|| palette(theme.name, role)) {
+        style = style.fg(color);
+    }
+    if theme.name == ThemeName::HighContrast || matches!(role, Role::Highlight | Role::Error) {
+        style = style.bold();
+    }
+    style
+}
+
+/// Bullets and other decoration
+pub fn accent<D>(value: D) -> StyledObject<D> {
+    role_style(Role::Accent).apply_to(value)
+}
+
+/// Character names, paths and successful results, in bold
+pub fn highlight<D>(value: D) -> StyledObject<D> {
+    role_style(Role::Highlight).apply_to(value)
+}
+
+pub fn warning<D>(value: D) -> StyledObject<D> {
+    role_style(Role::Warning).apply_to(value)
+}
+
+/// Failures, in bold
+pub fn error<D>(value: D) -> StyledObject<D> {
+    role_style(Role::Error).apply_to(value)
+}
+
+/// The prompt theme, in the same colors
+pub fn prompts() -> ColorfulTheme {
+    ColorfulTheme {
+        prompt_prefix: accent("?".to_owned()),
+        success_prefix: highlight("✔".to_owned()),
+        error_prefix: error("✘".to_owned()),
+        error_style: role_style(Role::Error),
+        values_style: role_style(Role::Highlight),
+        active_item_style: role_style(Role::Accent),
+        active_item_prefix: highlight("❯".to_owned()),
+        picked_item_prefix: highlight("❯".to_owned()),
+        checked_item_prefix: highlight("✔".to_owned()),
+        ..ColorfulTheme::default()
+    }
+}
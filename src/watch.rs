@@ -0,0 +1,60 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use console::style;
+
+use crate::{client::Client, error::{AuthError, Result}, output, theme};
+
+/// Time of day in UTC as `HH:MM:SS`
+pub fn clock() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default() % 86_400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Redraws the session's characters every `interval` until interrupted,
+/// carrying on through failures so a recovering session shows up again
+pub async fn run(client: &Client, interval: Duration, write_cache: bool) -> Result<()> {
+    let renderer = output::renderer();
+    let session_name = client.session_name().clone().unwrap_or_else(|| "default".to_owned());
+
+    loop {
+        let result = client.accounts(false, write_cache).await;
+        let time = clock();
+
+        renderer.clear();
+        renderer.note(&format!(
+            "{} {}  {}",
+            style("Session").bold(),
+            theme::highlight(&session_name),
+            style(format!("updated {time} UTC, every {}s (Ctrl+C to stop)", interval.as_secs())).dim()
+        ));
+
+        let fields = [("session", session_name.clone()), ("time", time)];
+        match result {
+            Ok(accounts) => {
+                let human = format!("  {} session valid, {} characters\n", theme::highlight("✓"), accounts.len());
+                let status = [("status", "ok".to_owned()), ("characters", accounts.len().to_string())];
+                renderer.record(&human, &[&fields[..], &status].concat());
+                let rows = accounts
+                    .iter()
+                    .map(|account| output::Row { account, session_name: client.session_name(), cache_age: None })
+                    .collect::<Vec<_>>();
+                renderer.accounts(&rows, false, false);
+                if accounts.is_empty() {
+                    renderer.note(&format!("  {}", theme::warning(AuthError::NoCharacters)));
+                }
+            }
+            Err(error) => {
+                let human = match error {
+                    AuthError::SessionNotFound | AuthError::SessionExpired => {
+                        format!("  {} {error}, run 'auth-rs authorize' to log in again", theme::error("✗"))
+                    }
+                    _ => format!("  {} {error}", theme::error("✗")),
+                };
+                let status = [("status", "failed".to_owned()), ("error", error.to_string())];
+                renderer.record(&human, &[&fields[..], &status].concat());
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
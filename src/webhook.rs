@@ -0,0 +1,106 @@
+use console::style;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{client, config, output, theme};
+
+/// Something the daemon can report to a webhook
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    /// Refreshing a session's characters failed
+    RefreshFailed,
+    /// A session is about to expire, or has
+    SessionExpiring,
+    /// Characters were added, removed or renamed
+    AccountsChanged,
+}
+
+impl Event {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::RefreshFailed => "refresh-failed",
+            Event::SessionExpiring => "session-expiring",
+            Event::AccountsChanged => "accounts-changed",
+        }
+    }
+}
+
+/// The payload shape a webhook expects
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookKind {
+    /// `{"event", "session", "message"}`
+    #[default]
+    Generic,
+    /// A Discord message
+    Discord,
+    /// A Slack incoming webhook message
+    Slack,
+}
+
+/// A URL the daemon posts to, under `[[daemon.webhooks]]`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub kind: WebhookKind,
+    /// Events posted, all of them when empty
+    #[serde(default)]
+    pub events: Vec<Event>,
+    /// JSON body to post instead of the kind's, with `{{event}}`,
+    /// `{{session}}` and `{{message}}` replaced by JSON-escaped values
+    pub template: Option<String>,
+}
+
+/// A JSON string's contents, without the quotes
+fn escape(value: &str) -> String {
+    let quoted = serde_json::to_string(value).unwrap_or_default();
+    quoted[1..quoted.len() - 1].to_owned()
+}
+
+fn body(webhook: &WebhookConfig, event: Event, session: &str, message: &str) -> String {
+    if let Some(template) = &webhook.template {
+        return template
+            .replace("{{event}}", &escape(event.name()))
+            .replace("{{session}}", &escape(session))
+            .replace("{{message}}", &escape(message));
+    }
+
+    let text = format!("auth-rs: {message}");
+    let payload = match webhook.kind {
+        WebhookKind::Generic => json!({ "event": event, "session": session, "message": message }),
+        WebhookKind::Discord => json!({ "content": text }),
+        WebhookKind::Slack => json!({ "text": text }),
+    };
+    payload.to_string()
+}
+
+/// Posts the event to every webhook that wants it. A webhook that can't be
+/// reached only gets a warning, so the daemon carries on.
+pub async fn notify(event: Event, session: &str, message: &str) {
+    for webhook in &config::get().daemon.webhooks {
+        if !webhook.events.is_empty() && !webhook.events.contains(&event) {
+            continue;
+        }
+
+        let request = client::shared_http()
+            .post(&webhook.url)
+            .header("Content-Type", "application/json")
+            .body(body(webhook, event, session, message));
+        let result = match request.send().await {
+            Ok(response) => response.error_for_status().map(|_| ()),
+            Err(error) => Err(error),
+        };
+        if let Err(error) = result {
+            // Webhook URLs carry their secret, so only the host is printed
+            let host = reqwest::Url::parse(&webhook.url).ok().and_then(|url| url.host_str().map(str::to_owned));
+            output::renderer().status(&format!(
+                "{} Couldn't post to the webhook at {}: {}",
+                theme::warning("Warning:").bold(),
+                style(host.unwrap_or_default()).bold(),
+                error.without_url()
+            ));
+        }
+    }
+}